@@ -8,7 +8,10 @@ use fots::types::{FnInfo, Group, GroupId, PtrDir, TypeId, TypeInfo};
 use ndarray::{Array2, Axis};
 use std::collections::HashMap;
 use std::fmt::{Display, Error, Formatter};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::ops::{Deref, DerefMut};
+use std::path::Path;
 
 /// Relation between interface
 #[derive(Debug, Clone, PartialOrd, PartialEq, Eq, Ord)]
@@ -50,6 +53,121 @@ impl RTable {
     pub fn len(&self) -> usize {
         self.0.len_of(Axis(0))
     }
+
+    /// Save table to file at path
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        write_header(&mut w)?;
+        self.write_to(&mut w)?;
+        w.flush()
+    }
+
+    /// Load table saved by `save`, stale or broken file is rejected
+    pub fn load(path: &Path) -> io::Result<RTable> {
+        let mut r = BufReader::new(File::open(path)?);
+        read_header(&mut r)?;
+        RTable::read_from(&mut r)
+    }
+
+    /// Write size and relations of table, one bit for each relation
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&(self.len() as u64).to_le_bytes())?;
+        let mut bytes = vec![0u8; self.0.len().div_ceil(8)];
+        for (i, r) in self.0.iter().enumerate() {
+            if *r == Relation::Some {
+                bytes[i / 8] |= 1 << (i % 8);
+            }
+        }
+        w.write_all(&bytes)
+    }
+
+    fn read_from<R: Read>(r: &mut R) -> io::Result<RTable> {
+        let n = read_u64(r)?;
+        let len = match n.checked_mul(n) {
+            Some(len) if n <= RTABLE_MAX_LEN => len.div_ceil(8),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("relation table of {} interfaces is too large", n),
+                ))
+            }
+        };
+        let n = n as usize;
+        // read through take, so truncated input fails before allocating for len
+        let mut bytes = Vec::new();
+        r.take(len).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let mut table = RTable::new(n);
+        for (i, r) in table.0.iter_mut().enumerate() {
+            if bytes[i / 8] & (1 << (i % 8)) != 0 {
+                *r = Relation::Some;
+            }
+        }
+        Ok(table)
+    }
+}
+
+/// Magic and version of saved relation tables
+const RTABLE_MAGIC: &[u8; 4] = b"RTBL";
+const RTABLE_VERSION: u32 = 1;
+/// Max number of interfaces of a saved relation table
+const RTABLE_MAX_LEN: u64 = 1 << 16;
+
+/// Save relation tables of all groups to file at path
+pub fn save_rtables<S: std::hash::BuildHasher>(
+    rs: &HashMap<GroupId, RTable, S>,
+    path: &Path,
+) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write_header(&mut w)?;
+    w.write_all(&(rs.len() as u64).to_le_bytes())?;
+    let mut gids = rs.keys().collect::<Vec<_>>();
+    gids.sort();
+    for gid in gids {
+        w.write_all(&(*gid as u64).to_le_bytes())?;
+        rs[gid].write_to(&mut w)?;
+    }
+    w.flush()
+}
+
+/// Load relation tables saved by `save_rtables`
+pub fn load_rtables(path: &Path) -> io::Result<HashMap<GroupId, RTable>> {
+    let mut r = BufReader::new(File::open(path)?);
+    read_header(&mut r)?;
+    let n = read_u64(&mut r)?;
+    let mut rs = HashMap::new();
+    for _ in 0..n {
+        let gid = read_u64(&mut r)? as GroupId;
+        rs.insert(gid, RTable::read_from(&mut r)?);
+    }
+    Ok(rs)
+}
+
+fn write_header<W: Write>(w: &mut W) -> io::Result<()> {
+    w.write_all(RTABLE_MAGIC)?;
+    w.write_all(&RTABLE_VERSION.to_le_bytes())
+}
+
+fn read_header<R: Read>(r: &mut R) -> io::Result<()> {
+    let mut magic = [0u8; 4];
+    r.read_exact(&mut magic)?;
+    let mut version = [0u8; 4];
+    r.read_exact(&mut version)?;
+    if &magic != RTABLE_MAGIC || u32::from_le_bytes(version) != RTABLE_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a relation table file or version mismatch",
+        ));
+    }
+    Ok(())
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
 }
 
 impl Deref for RTable {
@@ -158,6 +276,16 @@ fn record_use(uses: &mut HashMap<TypeId, Use>, res: TypeId, fn_index: usize, in_
     }
 }
 
+/// Learn relations from executed prog p of group g.
+///
+/// Only progs that covered new branches are learned, relations between adjacent
+/// calls are upgraded to `Relation::Some` and never downgraded.
+pub fn learn_relation(g: &Group, r: &mut RTable, p: &Prog, covered_new: bool) {
+    if covered_new && !p.is_empty() {
+        prog_analyze(g, r, p);
+    }
+}
+
 /// Analyze call seq of prog, update RTable
 ///
 /// Analysis is based on the order of target in a prog.
@@ -181,3 +309,89 @@ pub fn prog_analyze(g: &Group, r: &mut RTable, p: &Prog) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(n: usize, seed: usize) -> RTable {
+        let mut r = RTable::new(n);
+        for i in 0..n {
+            for j in 0..n {
+                if (i * 31 + j * 17 + seed) % 3 == 1 {
+                    r[(i, j)] = Relation::Some;
+                }
+            }
+        }
+        r
+    }
+
+    #[test]
+    fn rtable_save_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rt");
+        let r = table(13, 0);
+        r.save(&path).unwrap();
+        let l = RTable::load(&path).unwrap();
+        assert_eq!(l.len(), r.len());
+        for i in 0..r.len() {
+            for j in 0..r.len() {
+                assert_eq!(l[(i, j)], r[(i, j)]);
+            }
+        }
+
+        let rs = hashmap! {0 => table(3, 1), 5 => table(9, 2), 7 => RTable::new(0)};
+        save_rtables(&rs, &path).unwrap();
+        let ls = load_rtables(&path).unwrap();
+        assert_eq!(ls.len(), rs.len());
+        for (gid, r) in rs.iter() {
+            assert_eq!(&*ls[gid], &**r);
+        }
+
+        std::fs::write(&path, b"RTBL\x02\x00\x00\x00").unwrap();
+        let err = RTable::load(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        for n in [u64::MAX, 1 << 32, RTABLE_MAX_LEN + 1].iter() {
+            let mut bytes = b"RTBL\x01\x00\x00\x00".to_vec();
+            bytes.extend_from_slice(&n.to_le_bytes());
+            std::fs::write(&path, &bytes).unwrap();
+            let err = RTable::load(&path).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+        let mut bytes = b"RTBL\x01\x00\x00\x00".to_vec();
+        bytes.extend_from_slice(&RTABLE_MAX_LEN.to_le_bytes());
+        bytes.push(0xff);
+        std::fs::write(&path, &bytes).unwrap();
+        let err = RTable::load(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn learn_from_new_coverage() {
+        use crate::prog::Call;
+
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+group L {
+    fn setup(a i32)
+    fn trigger(a i32)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let g = t.iter_group().next().unwrap();
+        let mut p = Prog::new(g.id);
+        p.add_call(Call::new(g.fns[0].id));
+        p.add_call(Call::new(g.fns[1].id));
+
+        let mut r = RTable::new(2);
+        learn_relation(g, &mut r, &p, false);
+        assert_eq!(r[(1, 0)], Relation::None);
+        learn_relation(g, &mut r, &p, true);
+        assert_eq!(r[(1, 0)], Relation::Some);
+        assert_eq!(r[(0, 1)], Relation::None);
+    }
+}
@@ -6,6 +6,7 @@ use crate::prog::{ArgIndex, ArgPos, Call, Prog};
 use crate::target::Target;
 use crate::value::Value;
 use fots::types::{Field, NumInfo, NumLimit, PtrDir, StrType, TypeId, TypeInfo};
+use rand::thread_rng;
 use std::collections::HashMap;
 use std::fmt::{Display, Error, Formatter};
 
@@ -150,7 +151,7 @@ fn translate_arg(
         TypeInfo::Ptr { tid, dir, depth } => {
             assert_eq!(*depth, 1, "Multi-level pointer not supported");
 
-            if val == &Value::None {
+            if val == &Value::None || val == &Value::Absent {
                 Exp::NULL
             } else {
                 let var_name = decl_var(*tid, &val, t, s);
@@ -208,7 +209,7 @@ fn translate_str(str_type: &StrType, v: &Value) -> Exp {
             }
             Exp::ListExp(exps)
         }
-        StrType::FileName | StrType::CStr => Exp::StrLiteral(s.clone()),
+        StrType::FileName | StrType::CStr => Exp::StrLiteral(s.to_string()),
     }
 }
 
@@ -219,10 +220,12 @@ fn decl_var(tid: TypeId, val: &Value, t: &Target, s: &mut State) -> String {
         TypeInfo::Flag { .. } => decl_num(&NumInfo::U32(NumLimit::None), val, s),
         TypeInfo::Len { tid, .. } => decl_var(*tid, val, t, s),
         TypeInfo::Str { str_type, .. } => decl_str(str_type, val, s),
-        TypeInfo::Struct { ident, fields } => decl_struct(ident, fields, val, t, s),
+        TypeInfo::Struct { ident, fields, .. } => decl_struct(ident, fields, val, t, s),
         TypeInfo::Union { ident, fields } => decl_union(ident, fields, val, t, s),
         TypeInfo::Alias { tid, .. } => decl_var(*tid, val, t, s),
-        TypeInfo::Res { tid } => decl_var(*tid, &Value::default_val(*tid, t), t, s),
+        TypeInfo::Res { tid } => {
+            decl_var(*tid, &Value::default_val(*tid, t, &mut thread_rng()), t, s)
+        }
         TypeInfo::Slice { tid: under_tid, .. } => {
             if let TypeInfo::Ptr { tid, .. } = t.type_of(*under_tid) {
                 assert!(!t.is_slice(*tid), "Multi level slice not supported yet");
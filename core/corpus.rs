@@ -0,0 +1,248 @@
+//! Corpus
+//!
+//! Deduplicated set of progs that can be persisted to a directory,
+//! one json file per prog. Group of each prog is stored with it, so
+//! progs that no longer match current target can be dropped after
+//! loading. Strs of progs are interned, so that filenames and other strs
+//! repeated across progs are stored once.
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::prog::Prog;
+use crate::target::Target;
+use crate::value::Value;
+
+/// Extension of prog file in corpus directory
+const PROG_EXT: &str = "json";
+
+/// Set of progs, progs with same calls are stored once
+#[derive(Debug, Default, Clone)]
+pub struct Corpus {
+    progs: HashSet<Prog>,
+    // interned strs of progs
+    strs: HashSet<Arc<str>>,
+}
+
+impl Corpus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add prog to corpus, return false if same prog exists
+    pub fn add(&mut self, mut prog: Prog) -> bool {
+        if self.progs.contains(&prog) {
+            return false;
+        }
+        for (_, val) in prog.iter_args_mut() {
+            intern(val, &mut self.strs);
+        }
+        self.progs.insert(prog)
+    }
+
+    pub fn len(&self) -> usize {
+        self.progs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.progs.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Prog> + '_ {
+        self.progs.iter()
+    }
+
+    /// Save every prog to dir as json file named by hash of prog,
+    /// dir is created if not exists
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        for p in self.progs.iter() {
+            let mut hasher = DefaultHasher::new();
+            p.hash(&mut hasher);
+            let name = format!("{:016x}.{}", hasher.finish(), PROG_EXT);
+            fs::write(dir.join(name), p.to_json())?;
+        }
+        Ok(())
+    }
+
+    /// Load progs saved by `save` from dir, other files are ignored
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        let mut corpus = Self::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension() != Some(std::ffi::OsStr::new(PROG_EXT)) {
+                continue;
+            }
+            let p = Prog::from_json(&fs::read_to_string(&path)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            corpus.add(p);
+        }
+        Ok(corpus)
+    }
+
+    /// Remove progs whose group or fns not exist in t, return number of removed progs
+    pub fn retain_valid(&mut self, t: &Target) -> usize {
+        let len = self.progs.len();
+        self.progs.retain(|p| match t.groups.get(&p.gid) {
+            Some(g) => p.calls.iter().all(|c| g.fns.iter().any(|f| f.id == c.fid)),
+            None => false,
+        });
+        // strs only held by interner are no longer used
+        self.strs.retain(|s| Arc::strong_count(s) > 1);
+        len - self.progs.len()
+    }
+}
+
+/// Replace str val with its shared copy in strs
+fn intern(val: &mut Value, strs: &mut HashSet<Arc<str>>) {
+    if let Value::Str(s) = val {
+        match strs.get(s) {
+            Some(shared) => *s = shared.clone(),
+            None => {
+                strs.insert(s.clone());
+            }
+        }
+    }
+}
+
+/// Indices of corpus sorted by rarity of covered edges, most valuable first.
+///
+/// Each edge weighs 1/n where n is the number of progs covering it, score of prog
+/// is sum of weights of its distinct edges, so prog uniquely covering an edge gets
+/// full weight of it. Ties are broken by shorter prog, then by index. Progs without
+/// coverage score zero.
+pub fn prioritize(corpus: &[Prog], coverage: &HashMap<usize, Vec<u64>>) -> Vec<usize> {
+    let edges = (0..corpus.len())
+        .map(|i| {
+            coverage
+                .get(&i)
+                .map(|c| c.iter().cloned().collect::<HashSet<_>>())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    for e in edges.iter().flatten() {
+        *counts.entry(*e).or_default() += 1;
+    }
+    let scores = edges
+        .iter()
+        .map(|es| es.iter().map(|e| 1.0 / counts[e] as f64).sum::<f64>())
+        .collect::<Vec<_>>();
+
+    let mut indices = (0..corpus.len()).collect::<Vec<_>>();
+    indices.sort_by(|&a, &b| {
+        scores[b]
+            .partial_cmp(&scores[a])
+            .unwrap_or(Ordering::Equal)
+            .then(corpus[a].len().cmp(&corpus[b].len()))
+            .then(a.cmp(&b))
+    });
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze::static_analyze;
+    use crate::gen::{gen, Config};
+
+    #[test]
+    fn save_load_corpus() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+group C {
+    fn open(name *filename) fd
+    fn close(f fd)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let rs = static_analyze(&t);
+        let conf = Config {
+            prog_min_len: 2,
+            ..Default::default()
+        };
+        let mut corpus = Corpus::new();
+        let mut p = gen(&t, &rs, &conf).unwrap();
+        assert!(corpus.add(p.clone()));
+        // seed is not part of identity of prog
+        p.seed = None;
+        assert!(!corpus.add(p.clone()));
+        p.calls.pop();
+        assert!(corpus.add(p));
+        assert_eq!(corpus.len(), 2);
+
+        let dir = tempfile::tempdir().unwrap();
+        corpus.save(dir.path()).unwrap();
+        fs::write(dir.path().join("README"), "not a prog").unwrap();
+        let mut loaded = Corpus::load(dir.path()).unwrap();
+        assert_eq!(loaded.progs, corpus.progs);
+        assert_eq!(loaded.retain_valid(&t), 0);
+
+        let mut other = t;
+        other.groups.clear();
+        assert_eq!(loaded.retain_valid(&other), 2);
+        assert!(loaded.is_empty());
+    }
+
+    #[test]
+    fn intern_strs() {
+        use crate::prog::ProgBuilder;
+        use crate::value::NumValue;
+
+        let mut corpus = Corpus::new();
+        for i in 0..64 {
+            let p = ProgBuilder::new(0)
+                .call(0)
+                .arg(Value::Str("./shared".into()), 1)
+                .arg(Value::Num(NumValue::Unsigned(i)), 2)
+                .build()
+                .unwrap();
+            assert!(corpus.add(p));
+        }
+        let dir = tempfile::tempdir().unwrap();
+        corpus.save(dir.path()).unwrap();
+
+        for corpus in [corpus, Corpus::load(dir.path()).unwrap()].iter() {
+            let strs = corpus
+                .iter()
+                .map(|p| match &p.calls[0].args[0].val {
+                    Value::Str(s) => s.clone(),
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(strs.len(), 64);
+            assert!(strs.iter().all(|s| Arc::ptr_eq(s, &strs[0])));
+            assert_eq!(corpus.strs.len(), 1);
+        }
+    }
+
+    #[test]
+    fn prioritize_rare_edges() {
+        use crate::prog::Call;
+
+        let prog = |n: usize| {
+            let mut p = Prog::new(0);
+            for _ in 0..n {
+                p.add_call(Call::new(0));
+            }
+            p
+        };
+        let corpus = vec![prog(3), prog(2), prog(4), prog(1)];
+        let coverage = hashmap! {
+            0 => vec![1, 2],
+            1 => vec![1, 2],
+            2 => vec![1, 2, 3],
+            3 => vec![],
+        };
+        assert_eq!(prioritize(&corpus, &coverage), vec![2, 1, 0, 3]);
+    }
+}
@@ -0,0 +1,606 @@
+//! Encode
+//!
+//! Lower values to the in-memory bytes that interface expects.
+//! Numbers are in byte order of `Target::endian` at their declared width, fields of
+//! struct are laid out with natural alignment, elements of slice
+//! are packed, cstr and filename end with single nul. Consecutive bitfields
+//! of struct share storage of their num type, from the least significant bit. Pointers and
+//! refs of resource are unknown before execution, so they are encoded
+//! as zero and patched by executor, unless handles observed by earlier
+//! execution are given by `ExecContext`. Buffers that pointer args point to
+//! are laid out in a separate data area by `encode_ptr_data`, or by
+//! `encode_ptr_data_in` with handles of `ExecContext`.
+use fots::types::{Field, NumInfo, StrType, TypeId, TypeInfo};
+
+use crate::exec::ExecContext;
+use crate::prog::Arg;
+use crate::target::Target;
+use crate::value::{NumValue, Value};
+
+/// Width of pointer of target
+const PTR_SIZE: usize = 8;
+/// Width of flag, same as c int
+const FLAG_SIZE: usize = 4;
+
+/// Byte order of nums of target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl Default for Endian {
+    /// Little-endian, same as x86
+    fn default() -> Self {
+        Endian::Little
+    }
+}
+
+/// Encode val of type tid in byte order of t, append bytes to out
+pub fn encode_value(val: &Value, tid: TypeId, t: &Target, out: &mut Vec<u8>) {
+    encode_value_as(val, tid, t, t.endian, out)
+}
+
+/// Encode val of type tid in byte order of t, refs are encoded as handles recorded in ctx
+pub fn encode_value_in(val: &Value, tid: TypeId, t: &Target, ctx: &ExecContext, out: &mut Vec<u8>) {
+    encode_value(&ctx.resolve(val), tid, t, out)
+}
+
+/// Encode val of type tid with nums in byte order endian instead of that of t,
+/// append bytes to out
+pub fn encode_value_as(val: &Value, tid: TypeId, t: &Target, endian: Endian, out: &mut Vec<u8>) {
+    match t.type_of(tid) {
+        TypeInfo::Num(info) => {
+            let val = match val {
+                Value::Num(n) => Value::Num(n.truncate_to(info)),
+                _ => Value::None,
+            };
+            encode_num(&val, num_size(info), endian, out)
+        }
+        TypeInfo::Flag { .. } => encode_num(val, FLAG_SIZE, endian, out),
+        TypeInfo::Len { tid, .. } | TypeInfo::Alias { tid, .. } | TypeInfo::Res { tid } => {
+            encode_value_as(val, *tid, t, endian, out)
+        }
+        TypeInfo::Ptr { .. } => encode_num(&Value::None, PTR_SIZE, endian, out),
+        TypeInfo::Str { str_type, .. } => {
+            if let Value::Str(s) = val {
+                if *str_type == StrType::Str {
+                    out.extend_from_slice(s.as_bytes());
+                } else {
+                    // c string ends with exactly one nul, interior nul is stripped
+                    out.extend(s.bytes().filter(|b| *b != 0));
+                }
+            }
+            if *str_type != StrType::Str {
+                out.push(0);
+            }
+        }
+        TypeInfo::Slice { tid, .. } => {
+            if let Value::Group(vals) = val {
+                for v in vals.iter() {
+                    encode_value_as(v, *tid, t, endian, out);
+                }
+            }
+        }
+        TypeInfo::Struct { fields, packed, .. } => {
+            let start = out.len();
+            let vals = match val {
+                Value::Group(vals) => &vals[..],
+                _ => &[],
+            };
+            // fields of packed struct have alignment 1, so no padding is inserted,
+            // absent optional fields take no space
+            for slot in struct_slots(fields, vals, t) {
+                match slot {
+                    Slot::Field(i) => {
+                        if !packed {
+                            pad_to(out, start, align_of(fields[i].tid, t));
+                        }
+                        encode_value_as(&vals[i], fields[i].tid, t, endian, out);
+                    }
+                    Slot::Bits { size, bits } => {
+                        if !packed {
+                            pad_to(out, start, size);
+                        }
+                        let unit = bits.iter().fold(0, |unit, (i, offset, width)| {
+                            let v = match &vals[*i] {
+                                Value::Num(NumValue::Signed(v)) => *v as u64,
+                                Value::Num(NumValue::Unsigned(v)) => *v,
+                                _ => 0,
+                            };
+                            let mask = u64::MAX >> (64 - u32::from(*width));
+                            unit | ((v & mask) << offset)
+                        });
+                        encode_num(&Value::Num(NumValue::Unsigned(unit)), size, endian, out);
+                    }
+                }
+            }
+            pad_to(out, start, align_of(tid, t));
+        }
+        TypeInfo::Union { fields, .. } => {
+            if let Value::Opt { choice, val } = val {
+                encode_value_as(val, fields[*choice].tid, t, endian, out);
+            }
+        }
+    }
+}
+
+/// Byte size of encoding of val of type tid, same as length of output of
+/// `encode_value` but without producing the bytes
+pub fn value_size(val: &Value, tid: TypeId, t: &Target) -> usize {
+    match t.type_of(tid) {
+        TypeInfo::Num(info) => num_size(info),
+        TypeInfo::Flag { .. } => FLAG_SIZE,
+        TypeInfo::Len { tid, .. } | TypeInfo::Alias { tid, .. } | TypeInfo::Res { tid } => {
+            value_size(val, *tid, t)
+        }
+        TypeInfo::Ptr { .. } => PTR_SIZE,
+        TypeInfo::Str { str_type, .. } => {
+            let len = match val {
+                Value::Str(s) if *str_type == StrType::Str => s.len(),
+                Value::Str(s) => s.bytes().filter(|b| *b != 0).count(),
+                _ => 0,
+            };
+            if *str_type == StrType::Str {
+                len
+            } else {
+                len + 1
+            }
+        }
+        TypeInfo::Slice { tid, .. } => match val {
+            Value::Group(vals) => vals.iter().map(|v| value_size(v, *tid, t)).sum(),
+            _ => 0,
+        },
+        TypeInfo::Struct { fields, packed, .. } => {
+            let vals = match val {
+                Value::Group(vals) => &vals[..],
+                _ => &[],
+            };
+            let mut size = 0usize;
+            for slot in struct_slots(fields, vals, t) {
+                let (align, slot_size) = match slot {
+                    Slot::Field(i) => (
+                        align_of(fields[i].tid, t),
+                        value_size(&vals[i], fields[i].tid, t),
+                    ),
+                    Slot::Bits { size, .. } => (size, size),
+                };
+                if !packed {
+                    size = size.next_multiple_of(align);
+                }
+                size += slot_size;
+            }
+            size.next_multiple_of(align_of(tid, t))
+        }
+        TypeInfo::Union { fields, .. } => match val {
+            Value::Opt { choice, val } => value_size(val, fields[*choice].tid, t),
+            _ => 0,
+        },
+    }
+}
+
+/// Append buffer that pointer arg points to to data, return offset of buffer in data.
+///
+/// Buffer starts at its required alignment of arg if any, or natural alignment
+/// of pointee otherwise, nums of it are in byte order of t. None if arg is not
+/// a pointer or is null.
+pub fn encode_ptr_data(arg: &Arg, t: &Target, data: &mut Vec<u8>) -> Option<usize> {
+    ptr_data(arg, &arg.val, t, data)
+}
+
+/// Like `encode_ptr_data`, refs in buffer are encoded as handles recorded in ctx
+pub fn encode_ptr_data_in(
+    arg: &Arg,
+    t: &Target,
+    ctx: &ExecContext,
+    data: &mut Vec<u8>,
+) -> Option<usize> {
+    ptr_data(arg, &ctx.resolve(&arg.val), t, data)
+}
+
+fn ptr_data(arg: &Arg, val: &Value, t: &Target, data: &mut Vec<u8>) -> Option<usize> {
+    let tid = match t.type_of(arg.tid) {
+        TypeInfo::Ptr { tid, .. } => *tid,
+        _ => return None,
+    };
+    if *val == Value::None {
+        return None;
+    }
+    let align = arg.align.unwrap_or_else(|| align_of(tid, t));
+    pad_to(data, 0, align);
+    let offset = data.len();
+    encode_value(val, tid, t, data);
+    Some(offset)
+}
+
+/// Alignment of type tid
+pub fn align_of(tid: TypeId, t: &Target) -> usize {
+    match t.type_of(tid) {
+        TypeInfo::Num(info) => num_size(info),
+        TypeInfo::Flag { .. } => FLAG_SIZE,
+        TypeInfo::Len { tid, .. } | TypeInfo::Alias { tid, .. } | TypeInfo::Res { tid } => {
+            align_of(*tid, t)
+        }
+        TypeInfo::Ptr { .. } => PTR_SIZE,
+        TypeInfo::Str { .. } => 1,
+        TypeInfo::Slice { tid, .. } => align_of(*tid, t),
+        TypeInfo::Struct { packed: true, .. } => 1,
+        TypeInfo::Struct { fields, .. } | TypeInfo::Union { fields, .. } => {
+            fields.iter().map(|f| align_of(f.tid, t)).max().unwrap_or(1)
+        }
+    }
+}
+
+/// Unit of layout of struct
+enum Slot {
+    /// Index of field
+    Field(usize),
+    /// Storage of size bytes shared by consecutive bitfields,
+    /// (index, bit offset, bit width) of each bitfield
+    Bits {
+        size: usize,
+        bits: Vec<(usize, u32, u8)>,
+    },
+}
+
+/// Layout of fields of struct with values vals, absent optional fields are left out.
+///
+/// Bitfield joins storage of previous bitfield if they have num type of same width
+/// and it fits in remaining bits, otherwise it starts a new storage.
+fn struct_slots(fields: &[Field], vals: &[Value], t: &Target) -> Vec<Slot> {
+    let mut slots = Vec::new();
+    let mut used = 0;
+    for (i, (f, v)) in fields.iter().zip(vals.iter()).enumerate() {
+        if f.optional && *v == Value::Absent {
+            continue;
+        }
+        let width = t.num_info_of(f.tid).map(NumInfo::bit_width);
+        let (bits, width) = match (f.bits, width) {
+            (Some(bits), Some(width)) => (u32::from(bits).clamp(1, width as u32), width),
+            _ => {
+                slots.push(Slot::Field(i));
+                continue;
+            }
+        };
+        match slots.last_mut() {
+            Some(Slot::Bits { size, bits: shared })
+                if *size * 8 == width && used + bits <= width as u32 =>
+            {
+                shared.push((i, used, bits as u8));
+                used += bits;
+            }
+            _ => {
+                slots.push(Slot::Bits {
+                    size: width / 8,
+                    bits: vec![(i, 0, bits as u8)],
+                });
+                used = bits;
+            }
+        }
+    }
+    slots
+}
+
+fn num_size(info: &NumInfo) -> usize {
+    match info {
+        NumInfo::I8(_) | NumInfo::U8(_) => 1,
+        NumInfo::I16(_) | NumInfo::U16(_) => 2,
+        NumInfo::I32(_) | NumInfo::U32(_) => 4,
+        NumInfo::I64(_) | NumInfo::U64(_) | NumInfo::Isize(_) | NumInfo::Usize(_) => 8,
+    }
+}
+
+fn encode_num(val: &Value, size: usize, endian: Endian, out: &mut Vec<u8>) {
+    let bytes = match val {
+        Value::Num(NumValue::Signed(v)) => v.to_le_bytes(),
+        Value::Num(NumValue::Unsigned(v)) => v.to_le_bytes(),
+        _ => [0; 8],
+    };
+    // low bytes hold the truncated value, reverse them for big-endian
+    let bytes = &bytes[..size];
+    match endian {
+        Endian::Little => out.extend_from_slice(bytes),
+        Endian::Big => out.extend(bytes.iter().rev()),
+    }
+}
+
+/// Pad out with zeros until it is aligned relative to start, align 0 means no alignment
+fn pad_to(out: &mut Vec<u8>, start: usize, align: usize) {
+    let align = align.max(1);
+    while !(out.len() - start).is_multiple_of(align) {
+        out.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn target(desc: &str) -> Target {
+        Target::from(fots::parse_items(desc).unwrap())
+    }
+
+    fn param_tid(t: &Target, i: usize) -> TypeId {
+        let g = t.iter_group().next().unwrap();
+        g.fns[0].params.as_ref().unwrap()[i].tid
+    }
+
+    #[test]
+    fn encode_struct() {
+        let t = target(
+            r#"
+struct hdr { magic u32, kind u8 }
+fn send(h hdr)
+"#,
+        );
+        let val = Value::Group(vec![
+            Value::Num(NumValue::Unsigned(0xdead_beef)),
+            Value::Num(NumValue::Unsigned(7)),
+        ]);
+        let mut out = Vec::new();
+        encode_value(&val, param_tid(&t, 0), &t, &mut out);
+        assert_eq!(out, vec![0xef, 0xbe, 0xad, 0xde, 7, 0, 0, 0]);
+    }
+
+    #[test]
+    fn encode_num_width() {
+        let t = target("fn set(a i16{(-8, 8)}, b u64)");
+        let mut out = Vec::new();
+        encode_value(
+            &Value::Num(NumValue::Signed(-2)),
+            param_tid(&t, 0),
+            &t,
+            &mut out,
+        );
+        assert_eq!(out, vec![0xfe, 0xff]);
+
+        out.clear();
+        encode_value(
+            &Value::Num(NumValue::Unsigned(1)),
+            param_tid(&t, 1),
+            &t,
+            &mut out,
+        );
+        assert_eq!(out, vec![1, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn encode_truncated_num() {
+        let t = target("fn set(a i8, b u8)");
+        let mut out = Vec::new();
+        encode_value(
+            &Value::Num(NumValue::Signed(-1)),
+            param_tid(&t, 0),
+            &t,
+            &mut out,
+        );
+        encode_value(
+            &Value::Num(NumValue::Unsigned(257)),
+            param_tid(&t, 1),
+            &t,
+            &mut out,
+        );
+        assert_eq!(out, vec![0xff, 0x01]);
+
+        let info = t.num_info_of(param_tid(&t, 0)).unwrap();
+        assert_eq!(
+            NumValue::Unsigned(200).truncate_to(info),
+            NumValue::Signed(-56)
+        );
+        let info = t.num_info_of(param_tid(&t, 1)).unwrap();
+        assert_eq!(
+            NumValue::Signed(-1).truncate_to(info),
+            NumValue::Unsigned(255)
+        );
+    }
+
+    #[test]
+    fn encode_aligned_ptr_data() {
+        use crate::gen::{gen_seq, Config};
+
+        let t = target("fn read(f u32, buf *Out [i8;8], n u16)");
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            ptr_aligns: hashmap! {param_tid(&t, 1) => 4096},
+            ..Default::default()
+        };
+        let p = gen_seq(&[0], g.id, &t, &conf);
+        let args = &p.calls[0].args;
+        assert_eq!(args[0].align, None);
+        assert_eq!(args[1].align, Some(4096));
+
+        let mut data = vec![0xff; 3];
+        assert_eq!(encode_ptr_data(&args[0], &t, &mut data), None);
+        let offset = encode_ptr_data(&args[1], &t, &mut data).unwrap();
+        assert_eq!(offset, 4096);
+        assert_eq!(data.len(), 4096 + 8);
+
+        // align of deserialized arg may be 0, taken as no alignment
+        let mut arg = args[1].clone();
+        arg.align = Some(0);
+        let mut data = vec![0xff; 3];
+        assert_eq!(encode_ptr_data(&arg, &t, &mut data), Some(3));
+    }
+
+    #[test]
+    fn encode_struct_padding() {
+        let t = target(
+            r#"
+struct padded { a u8, b u32 }
+#[packed]
+struct tight { a u8, b u32 }
+fn set(p padded, q tight)
+"#,
+        );
+        let val = Value::Group(vec![
+            Value::Num(NumValue::Unsigned(1)),
+            Value::Num(NumValue::Unsigned(2)),
+        ]);
+        let mut out = Vec::new();
+        encode_value(&val, param_tid(&t, 0), &t, &mut out);
+        assert_eq!(out, vec![1, 0, 0, 0, 2, 0, 0, 0]);
+
+        out.clear();
+        encode_value(&val, param_tid(&t, 1), &t, &mut out);
+        assert_eq!(out, vec![1, 2, 0, 0, 0]);
+        assert_eq!(align_of(param_tid(&t, 1), &t), 1);
+    }
+
+    #[test]
+    fn encode_endian() {
+        let t = target("fn set(a u32)");
+        let val = Value::Num(NumValue::Unsigned(0x0102_0304));
+        let mut out = Vec::new();
+        encode_value_as(&val, param_tid(&t, 0), &t, Endian::Little, &mut out);
+        assert_eq!(out, vec![4, 3, 2, 1]);
+
+        out.clear();
+        encode_value_as(&val, param_tid(&t, 0), &t, Endian::Big, &mut out);
+        assert_eq!(out, vec![1, 2, 3, 4]);
+        assert_eq!(Endian::default(), Endian::Little);
+        assert_eq!(t.endian, Endian::Little);
+    }
+
+    #[test]
+    fn encode_big_endian_target() {
+        let mut t = target("fn set(p *u32, n u16)");
+        t.endian = Endian::Big;
+        let mut arg = Arg::new(param_tid(&t, 0));
+        arg.val = Value::Num(NumValue::Unsigned(0x0102_0304));
+        let mut data = Vec::new();
+        assert_eq!(encode_ptr_data(&arg, &t, &mut data), Some(0));
+        assert_eq!(data, vec![1, 2, 3, 4]);
+
+        let mut out = Vec::new();
+        let val = Value::Num(NumValue::Unsigned(0x0102));
+        encode_value(&val, param_tid(&t, 1), &t, &mut out);
+        assert_eq!(out, vec![1, 2]);
+        encode_value_as(&val, param_tid(&t, 1), &t, Endian::Little, &mut out);
+        assert_eq!(out, vec![1, 2, 2, 1]);
+    }
+
+    #[test]
+    fn value_size_matches_encoding() {
+        use crate::gen::{gen_seq, Config};
+
+        let t = target(
+            r#"
+#[packed]
+struct tight { a u8, b u16 }
+struct attr { kind u8, payload? u64, name *cstr }
+union u { small u8, wide tight }
+fn f(a attr, b [tight;(0, 4)], c u, d str, e cstr, g filename, h [attr;(1, 3)])
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config::default();
+        let mut sizes = HashSet::new();
+        for _ in 0..64 {
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            for arg in p.calls[0].args.iter() {
+                let mut out = Vec::new();
+                encode_value(&arg.val, arg.tid, &t, &mut out);
+                assert_eq!(
+                    value_size(&arg.val, arg.tid, &t),
+                    out.len(),
+                    "{:?}",
+                    arg.val
+                );
+                sizes.insert(out.len());
+            }
+        }
+        assert!(sizes.len() > 8);
+        let mut out = Vec::new();
+        encode_value(&Value::None, param_tid(&t, 0), &t, &mut out);
+        assert_eq!(value_size(&Value::None, param_tid(&t, 0), &t), out.len());
+    }
+
+    #[test]
+    fn encode_bitfields() {
+        use crate::gen::{gen_seq, Config};
+
+        let t = target(
+            r#"
+struct flags { lo u8:4, hi u8:4, mode u16:3, id u32 }
+fn set(f flags)
+"#,
+        );
+        let val = Value::Group(vec![
+            Value::Num(NumValue::Unsigned(0x3)),
+            Value::Num(NumValue::Unsigned(0xa)),
+            Value::Num(NumValue::Unsigned(0xf)),
+            Value::Num(NumValue::Unsigned(1)),
+        ]);
+        let mut out = Vec::new();
+        encode_value(&val, param_tid(&t, 0), &t, &mut out);
+        assert_eq!(out, vec![0xa3, 0, 0x7, 0, 1, 0, 0, 0]);
+        assert_eq!(value_size(&val, param_tid(&t, 0), &t), out.len());
+
+        let g = t.iter_group().next().unwrap();
+        for _ in 0..64 {
+            let p = gen_seq(&[0], g.id, &t, &Config::default());
+            match &p.calls[0].args[0].val {
+                Value::Group(vals) => {
+                    for (v, bits) in vals.iter().zip([4, 4, 3].iter()) {
+                        match v {
+                            Value::Num(NumValue::Unsigned(v)) => assert!(*v < 1 << bits),
+                            v => panic!("unexpected value: {:?}", v),
+                        }
+                    }
+                }
+                v => panic!("unexpected value: {:?}", v),
+            }
+        }
+    }
+
+    #[test]
+    fn encode_optional_field() {
+        use crate::gen::{gen_seq, Config};
+
+        let t = target(
+            r#"
+struct attr { kind u16, payload? u64 }
+fn send(a attr)
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config::default();
+        let mut lens = HashSet::new();
+        for _ in 0..64 {
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            let val = &p.calls[0].args[0].val;
+            let mut out = Vec::new();
+            encode_value(val, param_tid(&t, 0), &t, &mut out);
+            match val {
+                Value::Group(vals) if vals[1] == Value::Absent => assert_eq!(out.len(), 8),
+                _ => assert_eq!(out.len(), 16),
+            }
+            lens.insert(out.len());
+        }
+        assert_eq!(lens.len(), 2);
+
+        // null pointer in optional field is present, only absent field is left out
+        let t = target(
+            r#"
+struct attr { kind u16, hdr? *u32, payload u64 }
+fn send(a attr)
+"#,
+        );
+        let tid = param_tid(&t, 0);
+        let kind = Value::Num(NumValue::Unsigned(1));
+        let payload = Value::Num(NumValue::Unsigned(u64::MAX));
+        let mut out = Vec::new();
+        let val = Value::Group(vec![kind.clone(), Value::None, payload.clone()]);
+        encode_value(&val, tid, &t, &mut out);
+        assert_eq!(out.len(), 24);
+        assert_eq!(value_size(&val, tid, &t), 24);
+        assert_eq!(out[16..], [0xff; 8]);
+        let mut out = Vec::new();
+        let val = Value::Group(vec![kind, Value::Absent, payload]);
+        encode_value(&val, tid, &t, &mut out);
+        assert_eq!(out.len(), 16);
+        assert_eq!(value_size(&val, tid, &t), 16);
+        assert_eq!(out[8..], [0xff; 8]);
+    }
+}
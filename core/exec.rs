@@ -0,0 +1,211 @@
+//! Execution of prog
+//!
+//! Backends that run progs implement `Executor`, so that generation and
+//! feedback are decoupled from how and where progs are executed. Handles
+//! returned by executed calls are kept in `ExecContext`.
+use std::collections::HashMap;
+
+use crate::prog::{ArgIndex, ArgPos, Prog};
+use crate::target::Target;
+use crate::value::{NumValue, Value};
+
+/// Result of executing a prog, both fields hold one entry per call of prog
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecResult {
+    /// Return value of each call, None if call was not executed
+    pub rets: Vec<Option<i64>>,
+    /// Coverage collected by each call, empty if backend does not collect it
+    pub covs: Vec<Vec<usize>>,
+}
+
+impl ExecResult {
+    /// Number of calls that were executed
+    pub fn executed(&self) -> usize {
+        self.rets.iter().filter(|r| r.is_some()).count()
+    }
+}
+
+/// Backend that executes progs of target
+pub trait Executor {
+    fn exec(&mut self, p: &Prog, t: &Target) -> ExecResult;
+}
+
+/// Executor that runs nothing, every call returns 0 without coverage
+#[derive(Debug, Clone, Default)]
+pub struct DryRunExecutor {
+    /// Number of progs passed to exec
+    pub execs: usize,
+}
+
+impl DryRunExecutor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Executor for DryRunExecutor {
+    fn exec(&mut self, p: &Prog, _t: &Target) -> ExecResult {
+        self.execs += 1;
+        ExecResult {
+            rets: vec![Some(0); p.len()],
+            covs: vec![Vec::new(); p.len()],
+        }
+    }
+}
+
+/// Concrete handles of resources observed during execution, keyed by arg that
+/// produces them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecContext {
+    handles: HashMap<ArgIndex, i64>,
+}
+
+impl ExecContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record returns of executed calls of p that produce resource
+    pub fn record(&mut self, p: &Prog, r: &ExecResult) {
+        for (i, (c, ret)) in p.calls.iter().zip(r.rets.iter()).enumerate() {
+            if let (Some(_), Some(ret)) = (c.ret.as_ref(), ret) {
+                self.handles.insert((i, ArgPos::Ret), *ret);
+            }
+        }
+    }
+
+    /// Record handle produced by arg idx, e.g. one read back from out pointer
+    pub fn insert(&mut self, idx: ArgIndex, handle: i64) {
+        self.handles.insert(idx, handle);
+    }
+
+    pub fn get(&self, idx: &ArgIndex) -> Option<i64> {
+        self.handles.get(idx).cloned()
+    }
+
+    /// Replace refs in val with handles they point to, refs without observed
+    /// handle are kept
+    pub fn resolve(&self, val: &Value) -> Value {
+        match val {
+            Value::Ref(idx) => match self.get(idx) {
+                Some(h) => Value::Num(NumValue::Signed(h)),
+                None => val.clone(),
+            },
+            Value::Group(vals) => Value::Group(vals.iter().map(|v| self.resolve(v)).collect()),
+            Value::Opt { choice, val } => Value::Opt {
+                choice: *choice,
+                val: Box::new(self.resolve(val)),
+            },
+            _ => val.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze::static_analyze;
+    use crate::gen::{gen, Config};
+
+    #[test]
+    fn dry_run() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+group D {
+    fn open(name *filename) fd
+    fn read(f fd, buf *Out [i8], count len<usize, buf>)
+    fn close(f fd)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let rs = static_analyze(&t);
+        let conf = Config {
+            prog_min_len: 2,
+            ..Default::default()
+        };
+        let mut executor = DryRunExecutor::new();
+        for _ in 0..8 {
+            let p = gen(&t, &rs, &conf).unwrap();
+            let r = executor.exec(&p, &t);
+            assert_eq!(r.rets.len(), p.len());
+            assert_eq!(r.covs.len(), p.len());
+            assert_eq!(r.executed(), p.len());
+        }
+        assert_eq!(executor.execs, 8);
+    }
+
+    #[test]
+    fn encode_observed_handles() {
+        use crate::encode::{encode_ptr_data_in, encode_value_in};
+
+        struct Mock;
+        impl Executor for Mock {
+            fn exec(&mut self, p: &Prog, _t: &Target) -> ExecResult {
+                ExecResult {
+                    rets: p.calls.iter().map(|_| Some(5)).collect(),
+                    covs: vec![Vec::new(); p.len()],
+                }
+            }
+        }
+
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+struct io { f fd, n u32 }
+group M {
+    fn open(name *filename) fd
+    fn ioctl(f fd, arg *io)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            null_ptr_prob: 0.0,
+            ..Default::default()
+        };
+        let p = crate::gen::gen_seq(&[0, 1], g.id, &t, &conf);
+        let mut ctx = ExecContext::new();
+        let r = Mock.exec(&p, &t);
+        ctx.record(&p, &r);
+        assert_eq!(ctx.get(&(0, ArgPos::Ret)), Some(5));
+        assert_eq!(ctx.get(&(1, ArgPos::Ret)), None);
+
+        let c = &p.calls[1];
+        assert_eq!(c.args[0].val, Value::Ref((0, ArgPos::Ret)));
+        let mut out = Vec::new();
+        encode_value_in(&c.args[0].val, c.args[0].tid, &t, &ctx, &mut out);
+        assert_eq!(out, 5i32.to_le_bytes());
+
+        // refs inside pointees are resolved too
+        let mut data = Vec::new();
+        let offset = encode_ptr_data_in(&c.args[1], &t, &ctx, &mut data).unwrap();
+        assert_eq!(data[offset..offset + 4], 5i32.to_le_bytes());
+        // unknown handles are encoded as zero
+        let mut out = Vec::new();
+        encode_value_in(
+            &c.args[0].val,
+            c.args[0].tid,
+            &t,
+            &ExecContext::new(),
+            &mut out,
+        );
+        assert_eq!(out, [0; 4]);
+
+        // handles are encoded in byte order of target
+        let mut t = t;
+        t.endian = crate::encode::Endian::Big;
+        let mut out = Vec::new();
+        encode_value_in(&c.args[0].val, c.args[0].tid, &t, &ctx, &mut out);
+        assert_eq!(out, 5i32.to_be_bytes());
+        let mut data = Vec::new();
+        let offset = encode_ptr_data_in(&c.args[1], &t, &ctx, &mut data).unwrap();
+        assert_eq!(data[offset..offset + 4], 5i32.to_be_bytes());
+    }
+}
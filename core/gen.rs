@@ -11,24 +11,34 @@
 //! samply by number of random input. In this case, we need add
 //! some other interfaces that modify that external/global state
 //! which means generating sequence of target not single call.
-use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::hash::BuildHasher;
+use std::ops::Range;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use ndarray::Axis;
-use rand::distributions::Alphanumeric;
+use rand::distributions::uniform::SampleUniform;
+use rand::distributions::{Alphanumeric, Distribution, Standard, WeightedIndex};
 use rand::prelude::*;
-use rand::{random, thread_rng, Rng};
+use rand::rngs::StdRng;
+use rand::{random, Rng};
 
 use fots::types::{
-    Field, Flag, FnInfo, GroupId, NumInfo, NumLimit, PtrDir, StrType, TypeId, TypeInfo,
+    Field, Flag, FnId, FnInfo, Group, GroupId, LenKind, NumInfo, NumLimit, PtrDir, StrType, TypeId,
+    TypeInfo, FN_ATTR_CLOSE,
 };
 
 use crate::analyze::{RTable, Relation};
+use crate::encode::{align_of, value_size};
+use crate::minimize::{for_each_ref, for_each_ref_mut};
 use crate::prog::{Arg, ArgIndex, ArgPos, Call, Prog};
 use crate::target::Target;
 use crate::value::{NumValue, Value};
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct Config {
     pub prog_max_len: usize,
     pub prog_min_len: usize,
@@ -36,6 +46,119 @@ pub struct Config {
     pub str_max_len: usize,
     pub path_max_depth: usize,
     pub sp_delta: f64,
+    /// Seed of rng used by generation, seed from entropy if not set
+    pub seed: Option<u64>,
+    /// Max nesting depth of generated value, default value is used beyond it
+    pub max_gen_depth: usize,
+    /// Max nesting depth of unions in generated value, unions at this depth only
+    /// choose scalar variants, or default value if they have none
+    pub max_union_depth: usize,
+    /// Probability of choosing a resource producing call as the initial
+    /// call of sequence, 0.0 means no preference
+    pub resource_start_bias: f64,
+    /// Probability of generating edge value(0, -1, min, max ...) for num type
+    pub edge_value_prob: f64,
+    /// Charset of chars in generated str and cstr
+    pub str_charset: CharSet,
+    /// Known paths on target(/dev/null, /proc/self/status ...), used for filename generation
+    pub filename_dictionary: Vec<String>,
+    /// Probability of choosing filename from dictionary
+    pub filename_dictionary_prob: f64,
+    /// Probability of generating null for In pointer, Out pointers are never null
+    pub null_ptr_prob: f64,
+    /// Weights of fns used when choosing calls, keyed by group id and index of fn
+    /// in group, fns without weight have weight 1.0. Weights are normalized per group,
+    /// they must be finite and not negative
+    pub fn_weights: HashMap<(GroupId, usize), f64>,
+    /// Exact number of calls of generated prog, overrides prog_min_len and prog_max_len
+    pub target_len: Option<usize>,
+    /// Probability of reusing existing resource for resource arg
+    pub reuse_resource_prob: f64,
+    /// Hints that override generation of specific types
+    pub type_overrides: HashMap<TypeId, GenHint>,
+    /// Weights of fields of union, keyed by type id of union. Fields are chosen
+    /// uniformly if union has no weights or count of weights mismatches
+    pub union_weights: HashMap<TypeId, Vec<f64>>,
+    /// Capacity of str or slice buffer that out pointer points to
+    pub out_buf_len: usize,
+    /// Probability of generating num out of range, flag with undefined bits
+    /// or slice longer than its upper bound. Args holding such values are
+    /// marked as malformed
+    pub malformed_prob: f64,
+    /// Collect `Stats` of generation, see `Generator::stats`
+    pub collect_stats: bool,
+    /// Number of consumer calls generated by `gen_resource_chain`
+    pub resource_chain_len: usize,
+    /// Probability of adding another independent call to sequence, in (0.0, 1.0).
+    /// If not set, the chance decreases as sequence approaches prog_max_len
+    pub continue_prob: Option<f64>,
+    /// Insert a producer call before current call if a resource is needed but
+    /// none is produced yet, instead of generating it from its under type.
+    /// Inserted calls may make prog longer than prog_max_len
+    pub auto_producer: bool,
+    /// Alignment of buffers that pointer params point to, keyed by type id of pointer
+    pub ptr_aligns: HashMap<TypeId, usize>,
+    /// Alignment of buffers of pointer params that have no entry in ptr_aligns
+    pub default_ptr_align: Option<usize>,
+    /// Probability of including optional field of struct, absent field is `Value::Absent`
+    pub optional_field_prob: f64,
+    /// Probability of reusing a string generated earlier in prog for str or cstr,
+    /// strings of any category can be reused, so that named objects created by
+    /// earlier calls are likely referenced later
+    pub str_reuse_prob: f64,
+    /// Max number of values generated for one prog. Once reached, slices are cut
+    /// to their lower bound, optional fields are left out and no more calls are
+    /// added, so prog stays well-formed but truncated. Required parts of values
+    /// being generated are still filled, so count may exceed it slightly
+    pub max_values: usize,
+    /// Group and index in group of fns that may be generated, all fns if not set.
+    /// Groups without any fn in the set are never generated. Dependencies outside
+    /// the set are skipped, their resources are then generated from under type or
+    /// by allowed producers
+    pub allowed_fns: Option<HashSet<(GroupId, usize)>>,
+    /// Group and index in group of fns that are never generated, even as
+    /// dependencies, takes precedence over allowed_fns. Resources only produced
+    /// by denied fns are generated from their under type
+    pub denied_fns: HashSet<(GroupId, usize)>,
+    /// Max number of elements of every generated slice, including out buffers
+    /// and malformed slices. Applies even if declared lower bound is above it,
+    /// arg holding such a cut slice is marked as malformed
+    pub slice_max_elems: usize,
+    /// Well-known handles of resources (fd 0, 1, 2 ...), keyed by type id of
+    /// resource. Resource args reuse them like resources produced by earlier
+    /// calls, args holding them are marked as seeded
+    pub seed_resources: HashMap<TypeId, Vec<Value>>,
+    /// Max number of distinct resources created by one prog. Once reached,
+    /// resource args always reuse existing resources, producers are not inserted
+    /// and resources produced by later calls are not referenced
+    pub max_resources: Option<usize>,
+    /// Times a call of prog that `will_likely_fail` is regenerated, 0 disables
+    /// the check. Calls with malformed args are kept, observer only sees the
+    /// kept attempt
+    pub fail_retries: usize,
+    /// Predicates that generated calls of fn must satisfy, keyed by fn id,
+    /// including inserted producers and calls added by `extend`, `gen_call_at`
+    /// and `gen_resource_chain`. Calls are regenerated up to max_arg_retries
+    /// times until they do, last attempt is kept otherwise
+    pub validators: HashMap<FnId, Validator>,
+    /// Times a call rejected by its validator is regenerated
+    pub max_arg_retries: usize,
+    /// Generators of structured strs, like mount options, keyed by str type.
+    /// Strs of type with grammar are always produced by it, unless type has
+    /// declared vals
+    pub grammars: HashMap<StrType, Grammar>,
+    /// Probability of generating slice of exactly its lower bound (0 if none) or
+    /// its upper bound, chosen evenly. Slices without upper bound only use lower one
+    pub slice_boundary_prob: f64,
+    /// Bias towards dependencies that have dependencies themselves, in [0.0, 1.0].
+    /// Probability of pulling such dependency moves from its selection
+    /// probability towards 1.0 and that of other dependencies towards 0.0, so
+    /// producer-consumer chains get deeper
+    pub chain_bias: f64,
+    /// Probability of generating resource arg as a fabricated handle that no call
+    /// produces, like -1 or a huge fd. Such args are marked as malformed, so
+    /// minimization keeps them
+    pub dangling_ref_prob: f64,
 }
 
 impl Default for Config {
@@ -47,58 +170,761 @@ impl Default for Config {
             str_max_len: 32,
             path_max_depth: 4,
             sp_delta: 0.4,
+            seed: None,
+            max_gen_depth: 32,
+            max_union_depth: usize::MAX,
+            resource_start_bias: 0.0,
+            edge_value_prob: 0.05,
+            str_charset: CharSet::Alphanumeric,
+            filename_dictionary: Vec::new(),
+            filename_dictionary_prob: 0.5,
+            null_ptr_prob: 0.001,
+            fn_weights: HashMap::new(),
+            target_len: None,
+            reuse_resource_prob: 1.0,
+            type_overrides: HashMap::new(),
+            union_weights: HashMap::new(),
+            out_buf_len: 32,
+            malformed_prob: 0.0,
+            collect_stats: false,
+            resource_chain_len: 8,
+            continue_prob: None,
+            auto_producer: false,
+            ptr_aligns: HashMap::new(),
+            default_ptr_align: None,
+            optional_field_prob: 0.5,
+            str_reuse_prob: 0.5,
+            max_values: usize::MAX,
+            allowed_fns: None,
+            denied_fns: HashSet::new(),
+            slice_max_elems: usize::MAX,
+            seed_resources: HashMap::new(),
+            max_resources: None,
+            fail_retries: 0,
+            validators: HashMap::new(),
+            max_arg_retries: 8,
+            grammars: HashMap::new(),
+            slice_boundary_prob: 0.0,
+            chain_bias: 0.0,
+            dangling_ref_prob: 0.0,
+        }
+    }
+}
+
+impl Config {
+    /// Create config with checked lengths, other fields are default
+    pub fn new(
+        prog_min_len: usize,
+        prog_max_len: usize,
+        str_min_len: usize,
+        str_max_len: usize,
+        path_max_depth: usize,
+    ) -> Result<Config, ConfigError> {
+        let conf = Config {
+            prog_min_len,
+            prog_max_len,
+            str_min_len,
+            str_max_len,
+            path_max_depth,
+            ..Default::default()
+        };
+        conf.validate()?;
+        Ok(conf)
+    }
+
+    /// Whether fn at index i of group gid may be generated
+    pub fn is_fn_allowed(&self, gid: GroupId, i: usize) -> bool {
+        if self.denied_fns.contains(&(gid, i)) {
+            return false;
+        }
+        match &self.allowed_fns {
+            Some(fns) => fns.contains(&(gid, i)),
+            None => true,
+        }
+    }
+
+    /// Weight of fn at index i of group gid, 1.0 if not configured
+    fn fn_weight(&self, gid: GroupId, i: usize) -> f64 {
+        *self.fn_weights.get(&(gid, i)).unwrap_or(&1.0)
+    }
+
+    /// Check that values of config are in their valid ranges
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.prog_max_len == 0 {
+            return Err(ConfigError::ZeroProgMaxLen);
+        }
+        if self.prog_min_len > self.prog_max_len {
+            return Err(ConfigError::ProgLen {
+                min: self.prog_min_len,
+                max: self.prog_max_len,
+            });
+        }
+        if self.str_min_len >= self.str_max_len {
+            return Err(ConfigError::StrLen {
+                min: self.str_min_len,
+                max: self.str_max_len,
+            });
+        }
+        if self.path_max_depth == 0 {
+            return Err(ConfigError::ZeroPathDepth);
+        }
+        if self.target_len == Some(0) {
+            return Err(ConfigError::ZeroTargetLen);
+        }
+        if let Some((&(gid, i), &w)) = self
+            .fn_weights
+            .iter()
+            .find(|(_, w)| !(w.is_finite() && **w >= 0.0))
+        {
+            return Err(ConfigError::FnWeight { gid, i, w });
+        }
+        if let Some(p) = self.continue_prob {
+            if !(p > 0.0 && p < 1.0) {
+                return Err(ConfigError::ContinueProb(p));
+            }
+        }
+        let aligns = self
+            .ptr_aligns
+            .values()
+            .chain(self.default_ptr_align.iter());
+        if let Some(align) = aligns.cloned().find(|a| !a.is_power_of_two()) {
+            return Err(ConfigError::PtrAlign(align));
+        }
+        Ok(())
+    }
+}
+
+/// Error of invalid config
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// prog_max_len is zero
+    ZeroProgMaxLen,
+    /// prog_min_len is greater than prog_max_len
+    ProgLen { min: usize, max: usize },
+    /// str_min_len is not less than str_max_len
+    StrLen { min: usize, max: usize },
+    /// path_max_depth is zero
+    ZeroPathDepth,
+    /// target_len is zero
+    ZeroTargetLen,
+    /// Weight of fn at index i of group gid is negative or not finite
+    FnWeight { gid: GroupId, i: usize, w: f64 },
+    /// continue_prob is not in (0.0, 1.0)
+    ContinueProb(f64),
+    /// Alignment of ptr_aligns or default_ptr_align is not a power of two
+    PtrAlign(usize),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            ConfigError::ZeroProgMaxLen => write!(f, "prog_max_len must be positive"),
+            ConfigError::ProgLen { min, max } => write!(
+                f,
+                "prog_min_len({}) must not be greater than prog_max_len({})",
+                min, max
+            ),
+            ConfigError::StrLen { min, max } => write!(
+                f,
+                "str_min_len({}) must be less than str_max_len({})",
+                min, max
+            ),
+            ConfigError::ZeroPathDepth => write!(f, "path_max_depth must be positive"),
+            ConfigError::ZeroTargetLen => write!(f, "target_len must be positive"),
+            ConfigError::FnWeight { gid, i, w } => write!(
+                f,
+                "weight of fn {} of group {} must be finite and not negative, got {}",
+                i, gid, w
+            ),
+            ConfigError::ContinueProb(p) => {
+                write!(f, "continue_prob must be in (0.0, 1.0), got {}", p)
+            }
+            ConfigError::PtrAlign(align) => {
+                write!(f, "pointer alignment must be a power of two, got {}", align)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Validity predicate of generated call, see `Config::validators`.
+///
+/// Lens of call are filled before it is checked.
+#[derive(Clone)]
+pub struct Validator(Arc<dyn Fn(&Call) -> bool + Send + Sync>);
+
+impl Validator {
+    pub fn new<F: Fn(&Call) -> bool + Send + Sync + 'static>(f: F) -> Self {
+        Self(Arc::new(f))
+    }
+
+    pub fn check(&self, call: &Call) -> bool {
+        (self.0)(call)
+    }
+}
+
+impl fmt::Debug for Validator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Validator")
+    }
+}
+
+/// Generator of strs of a str type, see `Config::grammars`
+#[derive(Clone)]
+pub struct Grammar(Arc<dyn Fn(&mut StdRng) -> String + Send + Sync>);
+
+impl Grammar {
+    pub fn new<F: Fn(&mut StdRng) -> String + Send + Sync + 'static>(f: F) -> Self {
+        Self(Arc::new(f))
+    }
+
+    pub fn gen(&self, rng: &mut StdRng) -> String {
+        (self.0)(rng)
+    }
+}
+
+impl fmt::Debug for Grammar {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Grammar")
+    }
+}
+
+/// Hint of generating value of a type
+#[derive(Debug, Clone, PartialEq)]
+pub enum GenHint {
+    /// Generate null with given probability, for pointer type only
+    NullProb(f64),
+    /// Always use default value
+    Default,
+    /// Always use this num, for num type only
+    Const(NumValue),
+}
+
+/// Charset used for string generation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharSet {
+    /// Any ascii char, 0x00..=0x7f
+    Ascii,
+    /// Printable ascii char, 0x20..=0x7e
+    Printable,
+    /// a-z, A-Z, 0-9
+    Alphanumeric,
+    /// Any unicode scalar value
+    Unicode,
+}
+
+impl CharSet {
+    fn sample_str<R: Rng + ?Sized>(self, len: usize, rng: &mut R) -> String {
+        match self {
+            CharSet::Ascii => (0..len).map(|_| rng.gen_range(0u8, 0x80) as char).collect(),
+            CharSet::Printable => (0..len)
+                .map(|_| rng.gen_range(0x20u8, 0x7f) as char)
+                .collect(),
+            CharSet::Alphanumeric => rng.sample_iter(Alphanumeric).take(len).collect(),
+            CharSet::Unicode => rng.sample_iter::<char, _>(Standard).take(len).collect(),
+        }
+    }
+}
+
+/// Statistics of generation, accumulated over all progs of a generator
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stats {
+    /// Number of generated calls of each fn
+    pub fn_counts: HashMap<FnId, usize>,
+    /// Number of generated slices of each length
+    pub slice_lens: HashMap<usize, usize>,
+    /// Number of resource args that reuse existing resource
+    pub res_reused: usize,
+    /// Number of resource args that are generated from their under type
+    pub res_created: usize,
+    /// Max nesting depth of generated values
+    pub max_depth: usize,
+}
+
+/// Callbacks of generation events, for pushing metrics to external systems.
+///
+/// Events are the same as counted by `Stats`. Generation without observer
+/// only checks for its absence.
+pub trait GenObserver {
+    /// A call of fid is generated
+    fn on_call(&mut self, _fid: FnId) {}
+    /// A resource arg of type tid is generated from its under type
+    fn on_resource_created(&mut self, _tid: TypeId) {}
+    /// A resource arg of type tid refs an existing resource
+    fn on_resource_reused(&mut self, _tid: TypeId) {}
+}
+
+/// Event of `GenObserver`, buffered while generated call may still be discarded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GenEvent {
+    Call(FnId),
+    ResourceCreated(TypeId),
+    ResourceReused(TypeId),
+}
+
+/// Error of generation
+#[derive(Debug, Clone, PartialEq)]
+pub enum GenError {
+    /// No group has fn to generate
+    NoFunctions,
+    /// Type referenced by fn to generate not exists in target
+    UnknownType(TypeId),
+    /// Value of config is out of range
+    InvalidConfig(ConfigError),
+    /// Fixture of param of fn not exists or does not fit type of param
+    InvalidFixture { fid: FnId, param: usize },
+}
+
+impl Display for GenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            GenError::NoFunctions => write!(f, "No group has fn to generate"),
+            GenError::UnknownType(tid) => write!(f, "Unknown type id: {}", tid),
+            GenError::InvalidConfig(e) => write!(f, "Invalid config: {}", e),
+            GenError::InvalidFixture { fid, param } => {
+                write!(f, "Invalid fixture of param {} of fn {}", param, fid)
+            }
         }
     }
 }
 
-pub fn gen<S: std::hash::BuildHasher>(
+impl std::error::Error for GenError {}
+
+pub fn gen<S: BuildHasher>(
+    t: &Target,
+    rs: &HashMap<GroupId, RTable, S>,
+    conf: &Config,
+) -> Result<Prog, GenError> {
+    gen_with_fixtures(t, rs, conf, &HashMap::new())
+}
+
+/// Generate prog like `gen`, params that have fixture take value of fixture.
+///
+/// Fixtures are keyed by (fn id, param index), value of fixture must fit type of
+/// the param.
+pub fn gen_with_fixtures<S: BuildHasher>(
     t: &Target,
     rs: &HashMap<GroupId, RTable, S>,
     conf: &Config,
-) -> Prog {
-    assert!(!rs.is_empty());
-    assert_eq!(t.groups.len(), rs.len());
+    fixtures: &HashMap<(FnId, usize), Value>,
+) -> Result<Prog, GenError> {
+    do_gen(t, rs, conf, fixtures, None)
+}
+
+/// Generate prog like `gen`, events of generation are reported to observer
+pub fn gen_with_observer<S: BuildHasher>(
+    t: &Target,
+    rs: &HashMap<GroupId, RTable, S>,
+    conf: &Config,
+    observer: &mut dyn GenObserver,
+) -> Result<Prog, GenError> {
+    do_gen(t, rs, conf, &HashMap::new(), Some(observer))
+}
+
+fn do_gen<'a, S: BuildHasher>(
+    t: &Target,
+    rs: &HashMap<GroupId, RTable, S>,
+    conf: &'a Config,
+    fixtures: &'a HashMap<(FnId, usize), Value>,
+    observer: Option<&'a mut dyn GenObserver>,
+) -> Result<Prog, GenError> {
+    conf.validate().map_err(GenError::InvalidConfig)?;
+    for (&(fid, i), val) in fixtures.iter() {
+        let tid = t
+            .fns
+            .get(&fid)
+            .and_then(|_| t.fn_of(fid).params.as_ref()?.get(i))
+            .map(|p| p.tid);
+        match tid {
+            Some(tid) if fits_type(tid, val, t) => (),
+            _ => return Err(GenError::InvalidFixture { fid, param: i }),
+        }
+    }
+    let gids = gen_gids(t, rs, conf)?;
+    let mut s = State::new(conf);
+    s.fixtures = Some(fixtures);
+    s.observer = observer;
+    let gid = *gids.choose(&mut s.rng).unwrap();
+    let g = &t.groups[&gid];
+    let seq = choose_seq(g, &rs[&gid], t, conf, &mut s.rng);
+    assert!(!seq.is_empty());
+    for &i in seq.iter() {
+        check_types(&g.fns[i], t)?;
+    }
+    Ok(gen_seq_with(&seq, gid, t, &mut s))
+}
+
+/// Generate prog deterministically from bytes, for byte-mutating fuzzing harnesses.
+///
+/// Data is folded into the seed of generation, so identical data always yields
+/// identical prog. Seed of conf is ignored.
+pub fn prog_from_bytes<S: BuildHasher>(
+    data: &[u8],
+    t: &Target,
+    rs: &HashMap<GroupId, RTable, S>,
+    conf: &Config,
+) -> Result<Prog, GenError> {
+    let seed = crate::prog::fnv1a(data);
+    let conf = Config {
+        seed: Some(seed),
+        ..conf.clone()
+    };
+    gen(t, rs, &conf)
+}
+
+/// Make sure that all types reachable from f exist, so that generation never panics
+fn check_types(f: &FnInfo, t: &Target) -> Result<(), GenError> {
+    match t.unknown_type_of(f) {
+        Some(tid) => Err(GenError::UnknownType(tid)),
+        None => Ok(()),
+    }
+}
+
+/// Sorted ids of groups that have allowed fns with positive weight, so that choice of group
+/// only depends on seed
+fn gen_gids<S: BuildHasher>(
+    t: &Target,
+    rs: &HashMap<GroupId, RTable, S>,
+    conf: &Config,
+) -> Result<Vec<GroupId>, GenError> {
+    let mut gids = rs
+        .iter()
+        .filter(|(gid, r)| {
+            !r.is_empty()
+                && (0..t.groups[gid].fns.len())
+                    .any(|i| conf.is_fn_allowed(**gid, i) && conf.fn_weight(**gid, i) > 0.0)
+        })
+        .map(|(gid, _)| *gid)
+        .collect::<Vec<_>>();
+    if gids.is_empty() {
+        return Err(GenError::NoFunctions);
+    }
+    gids.sort();
+    Ok(gids)
+}
+
+/// Generator of progs, reuses the generation state between progs.
+///
+/// Every prog is generated from its own seed drawn from the rng of generator,
+/// so that `gen` with that seed produces exactly the same prog.
+pub struct Generator<'a, S = RandomState> {
+    t: &'a Target,
+    rs: &'a HashMap<GroupId, RTable, S>,
+    gids: Vec<GroupId>,
+    // seed of rng, rng draws one seed per prog
+    seed: u64,
+    rng: StdRng,
+    // number of generated progs
+    progs: u64,
+    s: State<'a>,
+}
+
+/// Progress of a generator, restored by `Generator::restore` to continue
+/// generation where it stopped, e.g. after restart of a campaign.
+///
+/// Rng of generator is recorded as its seed and number of generated progs,
+/// so state can be persisted while progs following restore are the same
+/// as ones of the uninterrupted generator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeneratorState {
+    pub seed: u64,
+    pub progs: u64,
+    pub stats: Option<Stats>,
+}
+
+impl<'a, S: BuildHasher> Generator<'a, S> {
+    pub fn new(
+        t: &'a Target,
+        rs: &'a HashMap<GroupId, RTable, S>,
+        conf: &'a Config,
+    ) -> Result<Self, GenError> {
+        conf.validate().map_err(GenError::InvalidConfig)?;
+        let gids = gen_gids(t, rs, conf)?;
+        let seed = conf.seed.unwrap_or_else(random);
+        Self::with_gids(t, rs, conf, gids, seed)
+    }
+
+    /// Create generator that only generates progs of group gid from its own rng
+    /// seeded with seed, seed of conf is ignored.
+    ///
+    /// Generators share nothing mutable, so generators of different groups can run
+    /// on different threads, each one is deterministic by its seed.
+    pub fn for_group(
+        t: &'a Target,
+        rs: &'a HashMap<GroupId, RTable, S>,
+        conf: &'a Config,
+        gid: GroupId,
+        seed: u64,
+    ) -> Result<Self, GenError> {
+        conf.validate().map_err(GenError::InvalidConfig)?;
+        if !gen_gids(t, rs, conf)?.contains(&gid) {
+            return Err(GenError::NoFunctions);
+        }
+        Self::with_gids(t, rs, conf, vec![gid], seed)
+    }
+
+    fn with_gids(
+        t: &'a Target,
+        rs: &'a HashMap<GroupId, RTable, S>,
+        conf: &'a Config,
+        gids: Vec<GroupId>,
+        seed: u64,
+    ) -> Result<Self, GenError> {
+        for gid in gids.iter() {
+            for f in t.groups[gid].fns.iter() {
+                check_types(f, t)?;
+            }
+        }
+        Ok(Self {
+            t,
+            rs,
+            gids,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            progs: 0,
+            s: State::new(conf),
+        })
+    }
+
+    /// Generate next prog, never ends
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Prog {
+        self.progs += 1;
+        self.s.reseed(self.rng.gen());
+        let gid = *self.gids.choose(&mut self.s.rng).unwrap();
+        gen_prog_with(gid, &self.rs[&gid], self.t, &mut self.s)
+    }
+
+    /// Report events of following generation to observer
+    pub fn set_observer(&mut self, observer: &'a mut dyn GenObserver) {
+        self.s.observer = Some(observer);
+    }
+
+    /// Stats of all generated progs, None if `Config::collect_stats` is not set
+    pub fn stats(&self) -> Option<&Stats> {
+        self.s.stats.as_ref()
+    }
+
+    /// Current progress of generator
+    pub fn checkpoint(&self) -> GeneratorState {
+        GeneratorState {
+            seed: self.seed,
+            progs: self.progs,
+            stats: self.s.stats.clone(),
+        }
+    }
 
-    let mut rng = thread_rng();
-    // choose group
-    let gid = rs.keys().choose(&mut rng).unwrap();
-    gen_prog(*gid, &rs[gid], t, conf)
+    /// Continue from state returned by `checkpoint`, following progs are the
+    /// same as if this generator generated those progs itself.
+    ///
+    /// Stats of state are dropped if `Config::collect_stats` is not set.
+    pub fn restore(&mut self, state: GeneratorState) {
+        self.seed = state.seed;
+        self.rng = StdRng::seed_from_u64(state.seed);
+        for _ in 0..state.progs {
+            self.rng.gen::<u64>();
+        }
+        self.progs = state.progs;
+        if self.s.conf.collect_stats {
+            self.s.stats = Some(state.stats.unwrap_or_default());
+        }
+    }
 }
 
 pub fn gen_prog(gid: GroupId, r: &RTable, t: &Target, conf: &Config) -> Prog {
+    gen_prog_with(gid, r, t, &mut State::new(conf))
+}
+
+fn gen_prog_with(gid: GroupId, r: &RTable, t: &Target, s: &mut State) -> Prog {
     // choose sequence
-    let seq = choose_seq(r, conf);
+    let seq = choose_seq(&t.groups[&gid], r, t, s.conf, &mut s.rng);
     assert!(!seq.is_empty());
 
-    gen_seq(&seq, gid, t, conf)
+    gen_seq_with(&seq, gid, t, s)
 }
 
 pub fn gen_seq(seq: &[usize], gid: GroupId, t: &Target, conf: &Config) -> Prog {
+    gen_seq_with(seq, gid, t, &mut State::new(conf))
+}
+
+/// Generate prog of count calls of fn fid for stress patterns, args of every call
+/// are generated independently.
+///
+/// Resource args reuse resources produced by earlier copies or existing ones
+/// according to `reuse_resource_prob`, producers are never inserted, so every
+/// call of prog is a call of fid.
+pub fn gen_repeat(t: &Target, conf: &Config, fid: FnId, count: usize) -> Prog {
+    let f = t.fn_of(fid);
+    let g = &t.groups[&f.gid];
+    let i = g.fns.iter().position(|f| f.id == fid).unwrap();
+    let conf = Config {
+        auto_producer: false,
+        max_values: usize::MAX,
+        ..conf.clone()
+    };
+    gen_seq_with(&vec![i; count], g.id, t, &mut State::new(&conf))
+}
+
+fn gen_seq_with(seq: &[usize], gid: GroupId, t: &Target, s: &mut State) -> Prog {
     let g = &t.groups[&gid];
     assert!(!g.fns.is_empty());
 
     // gen value
-    let mut s = State::new(Prog::new(g.id), conf);
+    s.reset(g.id);
     for &i in seq.iter() {
-        gen_call(t, &g.fns[i], &mut s);
-    }
-    adjust_size_param(&mut s.prog, t);
-    s.prog
-}
-
-fn adjust_size_param(p: &mut Prog, t: &Target) {
-    for c in &mut p.calls.iter_mut() {
-        let f = t.fn_of(c.fid);
-        if f.has_params() {
-            for (i, p) in f.iter_param().enumerate() {
-                if let Some(path) = t.len_info_of(p.tid) {
-                    if let Some(j) = f.iter_param().position(|p| p.ident == path) {
-                        if let Some(l) = c.args[j].val.len() {
-                            c.args[i].val = Value::Num(NumValue::Unsigned(l as u64));
-                        }
-                    }
-                } else {
-                    adjust_size(p.tid, &mut c.args[i].val, t);
+        if !s.prog.calls.is_empty() && s.values_left() == 0 {
+            break;
+        }
+        gen_checked_call(t, &g.fns[i], s);
+    }
+    let mut p = std::mem::replace(&mut s.prog, Prog::new(g.id));
+    for c in p.calls.iter_mut() {
+        fill_lens(c, t);
+    }
+    p.seed = Some(s.seed);
+    p
+}
+
+/// Generate call of f to be inserted before k-th call of p.
+///
+/// Only resources produced by calls before k are reused, so refs of returned
+/// call stay valid after insertion.
+pub fn gen_call_at(p: &Prog, k: usize, f: &FnInfo, t: &Target, conf: &Config) -> Call {
+    // only one call is returned, there is nowhere to insert producers
+    let conf = Config {
+        auto_producer: false,
+        ..conf.clone()
+    };
+    let mut s = State::new(&conf);
+    s.reset(p.gid);
+    s.replay(&p.calls[..k], t);
+    gen_checked_call(t, f, &mut s);
+    let mut c = s.prog.calls.pop().unwrap();
+    fill_lens(&mut c, t);
+    c
+}
+
+/// Append one new call to p, args of it only ref resources produced by existing
+/// calls and not closed by them.
+///
+/// Fns depending on existing calls in r are preferred, only fns allowed by conf
+/// are chosen, p is unchanged if there is none.
+pub fn extend(p: &mut Prog, t: &Target, r: &RTable, conf: &Config) {
+    let g = &t.groups[&p.gid];
+    let fns = (0..g.fns.len())
+        .filter(|&i| conf.is_fn_allowed(g.id, i))
+        .collect::<Vec<_>>();
+    let conf = Config {
+        auto_producer: false,
+        ..conf.clone()
+    };
+    let mut s = State::new(&conf);
+    let seq = p
+        .calls
+        .iter()
+        .filter_map(|c| g.fns.iter().position(|f| f.id == c.fid))
+        .collect::<Vec<_>>();
+    let deps = fns
+        .iter()
+        .cloned()
+        .filter(|&i| seq.iter().any(|&j| r[(i, j)] == Relation::Some))
+        .collect::<Vec<_>>();
+    let i = if !deps.is_empty() && s.rng.gen() {
+        *deps.choose(&mut s.rng).unwrap()
+    } else {
+        match fns.choose(&mut s.rng) {
+            Some(&i) => i,
+            None => return,
+        }
+    };
+
+    s.reset(p.gid);
+    s.replay(&p.calls, t);
+    gen_checked_call(t, &g.fns[i], &mut s);
+    let mut c = s.prog.calls.pop().unwrap();
+    fill_lens(&mut c, t);
+    p.calls.push(c);
+}
+
+/// Generate a call of root_fn of group g followed by `resource_chain_len` calls
+/// that consume resources produced by it.
+///
+/// Consumers are fns that depend on root_fn in r and take a resource produced by
+/// root_fn, every resource arg of them refs the root call. Only the root call is
+/// generated if root_fn has no consumer.
+pub fn gen_resource_chain(
+    g: &Group,
+    t: &Target,
+    r: &RTable,
+    root_fn: usize,
+    conf: &Config,
+) -> Prog {
+    let (_, produced) = t.res_use_of(&g.fns[root_fn]);
+    let consumers = (0..g.fns.len())
+        .filter(|&j| {
+            j != root_fn && r[(j, root_fn)] == Relation::Some && conf.is_fn_allowed(g.id, j)
+        })
+        .filter(|&j| {
+            let (consumed, _) = t.res_use_of(&g.fns[j]);
+            consumed.iter().any(|tid| produced.contains(tid))
+        })
+        .collect::<Vec<_>>();
+
+    // resources of root call must always be reused
+    let conf = Config {
+        reuse_resource_prob: 1.0,
+        ..conf.clone()
+    };
+    let mut s = State::new(&conf);
+    s.reset(g.id);
+    gen_checked_call(t, &g.fns[root_fn], &mut s);
+    let root_res = s.res.clone();
+    if !consumers.is_empty() {
+        for _ in 0..conf.resource_chain_len {
+            let j = *consumers.choose(&mut s.rng).unwrap();
+            gen_checked_call(t, &g.fns[j], &mut s);
+            // forget resources produced by consumers
+            s.res = root_res.clone();
+        }
+    }
+
+    let mut p = std::mem::replace(&mut s.prog, Prog::new(g.id));
+    for c in p.calls.iter_mut() {
+        fill_lens(c, t);
+    }
+    p.seed = Some(s.seed);
+    p
+}
+
+/// Fill value of len type params and fields of call
+///
+/// Len value is the element count of slice or the byte size of str that
+/// its path points to, or the encoded byte size of it for `LenKind::Bytes`
+/// and `LenKind::WithSelf`. A path begins with ident of a sibling param (or
+/// a sibling field for len inside struct), following idents select
+/// fields of struct, pointers are dereferenced during walking.
+pub fn fill_lens(call: &mut Call, t: &Target) {
+    let f = t.fn_of(call.fid);
+    if !f.has_params() {
+        return;
+    }
+
+    for (p, arg) in f.iter_param().zip(call.args.iter_mut()) {
+        if t.len_info_of(p.tid).is_none() {
+            adjust_size(p.tid, &mut arg.val, t);
+        }
+    }
+    for (i, p) in f.iter_param().enumerate() {
+        if let Some(path) = t.len_info_of(p.tid) {
+            let mut sub_paths = path.split('.');
+            let first = sub_paths.next().unwrap();
+            if let Some(j) = f.iter_param().position(|p| p.ident == first) {
+                let tid = call.args[j].tid;
+                if let Some(l) = lookup(tid, &call.args[j].val, sub_paths, t)
+                    .and_then(|(tid, v)| len_of(p.tid, tid, v, t))
+                {
+                    call.args[i].val = Value::Num(NumValue::Unsigned(l as u64));
                 }
             }
         }
@@ -127,55 +953,127 @@ fn adjust_size(tid: TypeId, v: &mut Value, t: &Target) {
             };
             asign_struct(fields, vals, t);
         }
+        TypeInfo::Union { fields, .. } => {
+            if let Value::Opt { choice, val } = v {
+                adjust_size(fields[*choice].tid, val, t);
+            }
+        }
         TypeInfo::Alias { tid, .. } => adjust_size(*tid, v, t),
         _ => (),
     }
 }
 
 fn asign_struct(fields: &[Field], vals: &mut [Value], t: &Target) {
+    for (f, v) in fields.iter().zip(vals.iter_mut()) {
+        if t.len_info_of(f.tid).is_none() {
+            adjust_size(f.tid, v, t);
+        }
+    }
+    fill_struct_lens(fields, vals, t);
+}
+
+/// Fill len fields of struct whose path begins with a sibling field
+fn fill_struct_lens(fields: &[Field], vals: &mut [Value], t: &Target) {
     for (i, f) in fields.iter().enumerate() {
-        if let Some(p) = t.len_info_of(f.tid) {
-            asign_len_val(i, p, fields, vals, t)
-        } else if let Some((_, fields)) = t.struct_info_of(f.tid) {
-            let vals = if let Value::Group(val) = &mut vals[i] {
-                val
-            } else {
-                panic!()
-            };
-            asign_struct(fields, vals, t)
+        if let Some(path) = t.len_info_of(f.tid) {
+            let mut sub_paths = path.split('.');
+            let first = sub_paths.next().unwrap();
+            if let Some(j) = fields.iter().position(|f| f.ident == first) {
+                if let Some(l) = lookup(fields[j].tid, &vals[j], sub_paths, t)
+                    .and_then(|(tid, v)| len_of(f.tid, tid, v, t))
+                {
+                    vals[i] = Value::Num(NumValue::Unsigned(l as u64));
+                }
+            }
         }
     }
 }
 
-fn asign_len_val(index: usize, path: &str, fields: &[Field], vals: &mut [Value], t: &Target) {
-    let mut sub_paths = path.split('.');
-    let mut p = sub_paths.next().unwrap();
+/// Value of len type len_tid that measures value v of type tid
+fn len_of(len_tid: TypeId, tid: TypeId, v: &Value, t: &Target) -> Option<usize> {
+    match t.len_kind_of(len_tid)? {
+        LenKind::Count => v.len(),
+        LenKind::Bytes => Some(pointee_size(tid, v, t)),
+        LenKind::WithSelf => Some(pointee_size(tid, v, t) + align_of(len_tid, t)),
+    }
+}
 
-    let mut crt_field = fields;
-    let mut crt_vals = &vals[..];
-    loop {
-        if let Some(i) = crt_field.iter().position(|f| f.ident == p) {
-            if let Some(n_p) = sub_paths.next() {
-                if let Some((_, n_fields)) = t.struct_info_of(crt_field[i].tid) {
-                    crt_field = n_fields;
-                } else {
-                    panic!();
-                }
-                crt_vals = if let Value::Group(val) = &crt_vals[i] {
-                    val
-                } else {
-                    panic!()
-                };
-                p = n_p;
+/// Encoded byte size of v, null pointer points to nothing
+fn pointee_size(tid: TypeId, v: &Value, t: &Target) -> usize {
+    if *v == Value::None {
+        0
+    } else {
+        value_size(v, tid, t)
+    }
+}
+
+/// Find value that sub paths point to and its type, begin with value v of type tid.
+/// Pointers are dereferenced, so the type is never a pointer type.
+fn lookup<'a, 'b>(
+    mut tid: TypeId,
+    mut v: &'a Value,
+    sub_paths: impl Iterator<Item = &'b str>,
+    t: &Target,
+) -> Option<(TypeId, &'a Value)> {
+    let deref = |mut tid| {
+        while let TypeInfo::Ptr { tid: under_tid, .. } = t.type_of(tid) {
+            tid = *under_tid;
+        }
+        tid
+    };
+    for p in sub_paths {
+        tid = deref(tid);
+        let (_, fields) = t.struct_info_of(tid)?;
+        let i = fields.iter().position(|f| f.ident == p)?;
+        v = if let Value::Group(vals) = v {
+            &vals[i]
+        } else {
+            return None;
+        };
+        tid = fields[i].tid;
+    }
+    Some((deref(tid), v))
+}
+
+/// Collect resource types that are produced by arg, pointer arg whose direction
+/// is overridden to In only produces resources of out pointers inside its value
+pub(crate) fn arg_out_res(arg: &Arg, t: &Target, res: &mut Vec<TypeId>) {
+    match (arg.dir, t.type_of(arg.tid)) {
+        (Some(PtrDir::In), TypeInfo::Ptr { tid, .. }) => {
+            if arg.val != Value::None {
+                out_res_of(*tid, &arg.val, t, res)
+            }
+        }
+        _ => out_res_of(arg.tid, &arg.val, t, res),
+    }
+}
+
+/// Collect resource types that are produced by out pointers in val
+pub(crate) fn out_res_of(tid: TypeId, val: &Value, t: &Target, res: &mut Vec<TypeId>) {
+    match (t.type_of(tid), val) {
+        (_, Value::None) | (_, Value::Ref(_)) => (),
+        (TypeInfo::Ptr { dir, tid, .. }, val) => {
+            if *dir != PtrDir::In && t.is_res(*tid) {
+                res.push(*tid);
             } else {
-                if let Some(l) = crt_vals[i].len() {
-                    vals[index] = Value::Num(NumValue::Unsigned(l as u64));
-                }
-                break;
+                out_res_of(*tid, val, t, res);
             }
-        } else {
-            panic!()
         }
+        (TypeInfo::Alias { tid, .. }, val) => out_res_of(*tid, val, t, res),
+        (TypeInfo::Struct { fields, .. }, Value::Group(vals)) => {
+            for (f, v) in fields.iter().zip(vals.iter()) {
+                out_res_of(f.tid, v, t, res);
+            }
+        }
+        (TypeInfo::Slice { tid, .. }, Value::Group(vals)) => {
+            for v in vals.iter() {
+                out_res_of(*tid, v, t, res);
+            }
+        }
+        (TypeInfo::Union { fields, .. }, Value::Opt { choice, val }) => {
+            out_res_of(fields[*choice].tid, val, t, res)
+        }
+        _ => (),
     }
 }
 
@@ -184,27 +1082,171 @@ struct State<'a> {
     strs: HashMap<StrType, Vec<String>>,
     prog: Prog,
     conf: &'a Config,
+    seed: u64,
+    rng: StdRng,
+    // nesting depth of value being generated
+    depth: usize,
+    // nesting depth of unions of value being generated
+    union_depth: usize,
+    // variant of next generated union, chosen by tag of struct
+    union_choice: Option<usize>,
+    // only exists if collect_stats is set
+    stats: Option<Stats>,
+    // resources whose producers are being inserted
+    inserting: HashSet<TypeId>,
+    // values of params pinned by gen_with_fixtures
+    fixtures: Option<&'a HashMap<(FnId, usize), Value>>,
+    observer: Option<&'a mut dyn GenObserver>,
+    // events of attempt of gen_checked_call, reported once attempt is kept
+    events: Option<Vec<GenEvent>>,
+    // number of values generated for current prog, bounded by max_values
+    values: usize,
+    // number of distinct resources recorded for current prog, bounded by max_resources
+    created: usize,
 }
 
 impl<'a> State<'a> {
-    pub fn new(prog: Prog, conf: &'a Config) -> Self {
+    pub fn new(conf: &'a Config) -> Self {
+        let seed = conf.seed.unwrap_or_else(random);
         Self {
             res: HashMap::new(),
-            strs: hashmap! {StrType::FileName => Vec::new()},
-            prog,
+            strs: hashmap! {
+                StrType::Str => Vec::new(),
+                StrType::CStr => Vec::new(),
+                StrType::FileName => Vec::new()
+            },
+            prog: Prog::new(0),
             conf,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            depth: 0,
+            union_depth: 0,
+            union_choice: None,
+            stats: if conf.collect_stats {
+                Some(Stats::default())
+            } else {
+                None
+            },
+            inserting: HashSet::new(),
+            fixtures: None,
+            observer: None,
+            events: None,
+            values: 0,
+            created: 0,
+        }
+    }
+
+    /// Report events of kept attempt, to outer attempt if there is one
+    fn flush_events(&mut self, outer: Option<Vec<GenEvent>>) {
+        let events = std::mem::replace(&mut self.events, outer);
+        for e in events.into_iter().flatten() {
+            self.notify(e);
+        }
+    }
+
+    /// Report event to observer, or buffer it if current call may be discarded
+    fn notify(&mut self, e: GenEvent) {
+        if let Some(events) = self.events.as_mut() {
+            return events.push(e);
+        }
+        if let Some(o) = self.observer.as_mut() {
+            match e {
+                GenEvent::Call(fid) => o.on_call(fid),
+                GenEvent::ResourceCreated(tid) => o.on_resource_created(tid),
+                GenEvent::ResourceReused(tid) => o.on_resource_reused(tid),
+            }
+        }
+    }
+
+    /// Number of values that can still be generated for current prog
+    fn values_left(&self) -> usize {
+        self.conf.max_values.saturating_sub(self.values)
+    }
+
+    /// Whether current prog has created as many resources as max_resources
+    fn res_exhausted(&self) -> bool {
+        self.conf.max_resources.is_some_and(|m| self.created >= m)
+    }
+
+    /// Restart rng of state with seed
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Clear generated calls and recorded values, start a new prog of group gid
+    pub fn reset(&mut self, gid: GroupId) {
+        self.res.clear();
+        for strs in self.strs.values_mut() {
+            strs.clear();
         }
+        self.prog = Prog::new(gid);
+        self.depth = 0;
+        self.union_depth = 0;
+        self.values = 0;
+        self.created = 0;
     }
 
+    /// Record resource of type tid produced by last ret or arg of last call.
+    ///
+    /// Refs point to args as a whole, so resources of all elements of an array
+    /// of out pointers are recorded once as the position of their arg.
     pub fn record_res(&mut self, tid: TypeId, is_ret: bool) {
         let cid = self.prog.len() - 1;
-
-        let idx = self.res.entry(tid).or_insert_with(Default::default);
-        if is_ret {
-            idx.push((cid, ArgPos::Ret))
+        let pos = if is_ret {
+            ArgPos::Ret
         } else {
-            let arg_pos = self.prog.calls[cid].args.len() - 1;
-            idx.push((cid, ArgPos::Arg(arg_pos)))
+            ArgPos::Arg(self.prog.calls[cid].args.len() - 1)
+        };
+        self.push_res(tid, (cid, pos));
+    }
+
+    fn push_res(&mut self, tid: TypeId, idx: ArgIndex) {
+        let exhausted = self.res_exhausted();
+        let res = self.res.entry(tid).or_default();
+        if !res.contains(&idx) && !exhausted {
+            res.push(idx);
+            self.created += 1;
+        }
+    }
+
+    /// Record resources produced by last call, which is not generated by state
+    pub fn record_call_res(&mut self, t: &Target) {
+        let cid = self.prog.len() - 1;
+        let c = &self.prog.calls[cid];
+        let mut res = Vec::new();
+        for (i, arg) in c.args.iter().enumerate() {
+            let mut tids = Vec::new();
+            arg_out_res(arg, t, &mut tids);
+            res.extend(tids.into_iter().map(|tid| (tid, (cid, ArgPos::Arg(i)))));
+        }
+        if let Some(ret) = c.ret.as_ref() {
+            if t.is_res(ret.tid) {
+                res.push((ret.tid, (cid, ArgPos::Ret)));
+            }
+        }
+        for (tid, idx) in res {
+            self.push_res(tid, idx);
+        }
+    }
+
+    /// Add calls that are not generated by state, resources are tracked as if they were
+    pub fn replay(&mut self, calls: &[Call], t: &Target) {
+        for c in calls.iter() {
+            self.add_call(c.clone());
+            self.record_call_res(t);
+            self.close_call_res(t);
+        }
+    }
+
+    /// Forget resources that last call closes, so that later calls never ref them
+    pub fn close_call_res(&mut self, t: &Target) {
+        let closed = closed_res(self.prog.calls.last().unwrap(), t);
+        if closed.is_empty() {
+            return;
+        }
+        for idx in self.res.values_mut() {
+            idx.retain(|i| !closed.contains(i));
         }
     }
 
@@ -213,28 +1255,57 @@ impl<'a> State<'a> {
         vals.push(val.into())
     }
 
-    pub fn try_reuse_res(&self, tid: TypeId) -> Option<Value> {
-        let mut rng = thread_rng();
-        if let Some(res) = self.res.get(&tid) {
-            if !res.is_empty() {
-                let r = res.choose(&mut rng).unwrap();
-                return Some(Value::Ref(r.clone()));
+    pub fn try_reuse_res(&mut self, tid: TypeId) -> Option<Value> {
+        let p = self.conf.reuse_resource_prob;
+        if p < 1.0 && !self.res_exhausted() && self.rng.gen::<f64>() >= p {
+            return None;
+        }
+        let refs = self.res.get(&tid).map_or(0, Vec::len);
+        let seeds = self.conf.seed_resources.get(&tid).map_or(0, Vec::len);
+        if refs + seeds == 0 {
+            return None;
+        }
+        let i = self.rng.gen_range(0, refs + seeds);
+        if i < refs {
+            Some(Value::Ref(self.res[&tid][i].clone()))
+        } else {
+            if let Some(arg) = self.prog.calls.last_mut().and_then(|c| c.args.last_mut()) {
+                arg.seeded = true;
             }
+            Some(self.conf.seed_resources[&tid][i - refs].clone())
         }
-        None
     }
 
-    pub fn try_reuse_str(&self, str_type: StrType) -> Option<Value> {
-        let mut rng = thread_rng();
+    pub fn try_reuse_str(&mut self, str_type: StrType) -> Option<Value> {
+        if str_type != StrType::FileName {
+            return self.try_reuse_any_str(str_type == StrType::CStr);
+        }
         if let Some(strs) = self.strs.get(&str_type) {
-            if !strs.is_empty() && rng.gen() {
-                let s = strs.choose(&mut rng).unwrap();
-                return Some(Value::Str(s.clone()));
+            if !strs.is_empty() && self.rng.gen() {
+                let s = strs.choose(&mut self.rng).unwrap();
+                return Some(Value::Str(s.as_str().into()));
             }
         }
         None
     }
 
+    // reuse string of any category with str_reuse_prob, strings with nul are skipped for cstr
+    fn try_reuse_any_str(&mut self, cstr: bool) -> Option<Value> {
+        if self.rng.gen::<f64>() >= self.conf.str_reuse_prob {
+            return None;
+        }
+        let mut strs = self
+            .strs
+            .values()
+            .flatten()
+            .filter(|s| !cstr || !s.contains('\0'))
+            .collect::<Vec<_>>();
+        // order of map is unspecified, sort to keep generation reproducible by seed
+        strs.sort();
+        strs.choose(&mut self.rng)
+            .map(|s| Value::Str(s.as_str().into()))
+    }
+
     // add call
     pub fn add_call(&mut self, call: Call) -> &mut Call {
         self.prog.add_call(call)
@@ -252,20 +1323,74 @@ impl<'a> State<'a> {
         self.prog.calls[i].ret.as_mut().unwrap()
     }
 
-    pub fn update_val(&mut self, val: Value) {
-        let c = self.prog.calls.last_mut().unwrap();
-        let arg_index = c.args.len() - 1;
-        c.args[arg_index].val = val;
+    /// Mark arg being generated as malformed
+    pub fn mark_malformed(&mut self) {
+        if let Some(arg) = self.prog.calls.last_mut().and_then(|c| c.args.last_mut()) {
+            arg.malformed = true;
+        }
+    }
+
+    /// Cut len of slice with lower bound l to slice_max_elems, arg is malformed
+    /// if len falls below l
+    fn cap_slice_len(&mut self, len: usize, l: isize) -> usize {
+        let max = self.conf.slice_max_elems;
+        if len > max && max < l.max(0) as usize {
+            self.mark_malformed();
+        }
+        len.min(max)
+    }
+
+    /// Whether to generate malformed value, rng is untouched if malformed_prob is 0
+    pub fn should_malform(&mut self) -> bool {
+        self.conf.malformed_prob > 0.0 && self.rng.gen::<f64>() < self.conf.malformed_prob
+    }
+
+    /// Length of slice with bounds l and h, boundary lengths are chosen with
+    /// slice_boundary_prob, rng is untouched for them if it is 0
+    fn slice_len(&mut self, l: isize, h: isize) -> usize {
+        let p = self.conf.slice_boundary_prob;
+        if p > 0.0 && self.rng.gen::<f64>() < p {
+            let lo = l.max(0);
+            return if h >= lo && self.rng.gen() {
+                h as usize
+            } else {
+                lo as usize
+            };
+        }
+        gen_slice_len(l, h, &mut self.rng)
+    }
+
+    pub fn update_val(&mut self, val: Value) {
+        let c = self.prog.calls.last_mut().unwrap();
+        let arg_index = c.args.len() - 1;
+        c.args[arg_index].val = val;
     }
 }
 
 fn gen_call(t: &Target, f: &FnInfo, s: &mut State) {
     s.add_call(Call::new(f.id));
+    if let Some(stats) = s.stats.as_mut() {
+        *stats.fn_counts.entry(f.id).or_default() += 1;
+    }
+    if s.observer.is_some() {
+        s.notify(GenEvent::Call(f.id));
+    }
 
     if f.has_params() {
-        for p in f.iter_param() {
-            s.add_arg(Arg::new(p.tid));
-            let val = gen_value(p.tid, t, s);
+        for (i, p) in f.iter_param().enumerate() {
+            let align = match t.type_of(p.tid) {
+                TypeInfo::Ptr { .. } => {
+                    let align = s.conf.ptr_aligns.get(&p.tid).cloned();
+                    align.or(s.conf.default_ptr_align)
+                }
+                _ => None,
+            };
+            s.add_arg(Arg::new(p.tid)).align = align;
+            let fixture = s.fixtures.and_then(|fs| fs.get(&(f.id, i)));
+            let val = match fixture {
+                Some(val) => val.clone(),
+                None => gen_value(p.tid, t, s),
+            };
             s.update_val(val);
         }
     }
@@ -276,12 +1401,41 @@ fn gen_call(t: &Target, f: &FnInfo, s: &mut State) {
             s.record_res(tid, true);
         }
     }
+    s.close_call_res(t);
 }
 
+/// Range of fabricated handles, above handles that a prog plausibly gets
+const DANGLING_HANDLES: Range<i64> = 1 << 16..1 << 20;
+
 /// generate value for any type
 fn gen_value(tid: TypeId, t: &Target, s: &mut State) -> Value {
-    match t.type_of(tid) {
-        TypeInfo::Num(num_info) => gen_num(num_info),
+    // stop recursion of recursive type, pointers are null in default value
+    if s.depth >= s.conf.max_gen_depth {
+        return Value::default_val(tid, t, &mut s.rng);
+    }
+
+    if let Some(hint) = s.conf.type_overrides.get(&tid) {
+        match hint {
+            GenHint::NullProb(p) => {
+                if s.rng.gen::<f64>() < *p {
+                    return Value::None;
+                }
+            }
+            GenHint::Default => return Value::default_val(tid, t, &mut s.rng),
+            GenHint::Const(n) => return Value::Num(n.clone()),
+        }
+    }
+
+    s.depth += 1;
+    s.values += 1;
+    if let Some(stats) = s.stats.as_mut() {
+        stats.max_depth = stats.max_depth.max(s.depth);
+    }
+    let val = match t.type_of(tid) {
+        TypeInfo::Num(num_info) => match gen_malformed_num(num_info, s) {
+            Some(val) => val,
+            None => gen_num(num_info, s.conf, &mut s.rng),
+        },
         TypeInfo::Ptr { dir, tid, depth } => {
             assert_eq!(*depth, 1, "Multi-level pointer not supported");
             gen_ptr(*dir, *tid, t, s)
@@ -289,14 +1443,295 @@ fn gen_value(tid: TypeId, t: &Target, s: &mut State) -> Value {
         // TODO  what if tid is type of res
         TypeInfo::Slice { tid, l, h } => gen_slice(*tid, *l, *h, t, s),
         TypeInfo::Str { str_type, vals } => gen_str(str_type, vals, s),
-        TypeInfo::Struct { fields, .. } => gen_struct(&fields[..], t, s),
-        TypeInfo::Union { fields, .. } => gen_union(&fields[..], t, s),
-        TypeInfo::Flag { flags, .. } => gen_flag(&flags[..]),
+        TypeInfo::Struct { fields, tag, .. } => gen_struct(&fields[..], *tag, t, s),
+        TypeInfo::Union { fields, .. } => gen_union(tid, &fields[..], t, s),
+        TypeInfo::Flag {
+            flags, required, ..
+        } => match gen_malformed_flag(&flags[..], required, s) {
+            Some(val) => val,
+            None => gen_flag(&flags[..], required, &mut s.rng),
+        },
 
         TypeInfo::Alias { tid: under_id, .. } => gen_alias(tid, *under_id, t, s),
         TypeInfo::Res { tid: under_tid } => gen_res(tid, *under_tid, t, s),
+        // placeholder, filled by fill_lens after the whole call is generated
         TypeInfo::Len { .. } => Value::Num(NumValue::Unsigned(0)),
+    };
+    s.depth -= 1;
+    val
+}
+
+/// Generate call of f, regenerate it while it will likely fail or its validator rejects it.
+///
+/// Likely failing calls are retried up to fail_retries times, rejected ones up
+/// to max_arg_retries times.
+fn gen_checked_call(t: &Target, f: &FnInfo, s: &mut State) {
+    let validator = s.conf.validators.get(&f.id);
+    let retries = match validator {
+        Some(_) => s.conf.fail_retries.max(s.conf.max_arg_retries),
+        None => s.conf.fail_retries,
+    };
+    if retries == 0 {
+        return gen_call(t, f, s);
+    }
+    let len = s.prog.len();
+    let saved = (
+        s.res.clone(),
+        s.strs.clone(),
+        s.stats.clone(),
+        s.values,
+        s.created,
+    );
+    // events of outer attempt, if call is generated within one, e.g. as producer
+    let outer = match s.observer {
+        Some(_) => s.events.replace(Vec::new()),
+        None => None,
+    };
+    for i in 0..retries {
+        gen_call(t, f, s);
+        let c = s.prog.calls.last_mut().unwrap();
+        let doomed = i < s.conf.fail_retries
+            && !c.args.iter().any(|a| a.malformed)
+            && will_likely_fail(c, t);
+        let rejected = i < s.conf.max_arg_retries
+            && validator.is_some_and(|v| {
+                fill_lens(c, t);
+                !v.check(c)
+            });
+        if !doomed && !rejected {
+            return s.flush_events(outer);
+        }
+        if let Some(events) = s.events.as_mut() {
+            events.clear();
+        }
+        // producers inserted for the call are dropped with it
+        s.prog.calls.truncate(len);
+        let saved = saved.clone();
+        s.res = saved.0;
+        s.strs = saved.1;
+        s.stats = saved.2;
+        s.values = saved.3;
+        s.created = saved.4;
+    }
+    gen_call(t, f, s);
+    s.flush_events(outer);
+}
+
+/// Whether call is obviously doomed to fail, heuristic and static.
+///
+/// Call is doomed if an In pointer param is null, or a num, str, slice or union
+/// of its args is out of what the type declares. Refs and lens are not checked.
+pub fn will_likely_fail(call: &Call, t: &Target) -> bool {
+    call.args
+        .iter()
+        .any(|arg| match (arg.dir, t.type_of(arg.tid)) {
+            (dir, TypeInfo::Ptr { dir: ty_dir, .. }) if arg.val == Value::None => {
+                dir.unwrap_or(*ty_dir) == PtrDir::In
+            }
+            _ => !val_may_fit(arg.tid, &arg.val, t),
+        })
+}
+
+/// Like `fits_type`, but refs and null pointers always fit and nums are only
+/// checked against their limits, since they are truncated to their width anyway
+fn val_may_fit(tid: TypeId, val: &Value, t: &Target) -> bool {
+    match (t.type_of(tid), val) {
+        (_, Value::Ref(_)) | (TypeInfo::Ptr { .. }, Value::None) => true,
+        (TypeInfo::Num(info), Value::Num(n)) => num_fits(info, &n.truncate_to(info)),
+        (TypeInfo::Ptr { tid, .. }, val)
+        | (TypeInfo::Alias { tid, .. }, val)
+        | (TypeInfo::Res { tid }, val) => val_may_fit(*tid, val, t),
+        (TypeInfo::Slice { tid, l, h }, Value::Group(vals)) => {
+            (*l == -1 || vals.len() >= *l as usize)
+                && (*h == -1 || vals.len() <= *h as usize)
+                && vals.iter().all(|v| val_may_fit(*tid, v, t))
+        }
+        (TypeInfo::Struct { fields, .. }, Value::Group(vals)) => {
+            fields.len() == vals.len()
+                && fields
+                    .iter()
+                    .zip(vals.iter())
+                    .all(|(f, v)| (f.optional && *v == Value::Absent) || val_may_fit(f.tid, v, t))
+        }
+        (TypeInfo::Union { fields, .. }, Value::Opt { choice, val }) => {
+            *choice < fields.len() && val_may_fit(fields[*choice].tid, val, t)
+        }
+        _ => fits_type(tid, val, t),
+    }
+}
+
+/// Whether shape of val matches type tid and nums are within their limits.
+///
+/// Refs are never accepted, since they depend on other calls of prog.
+fn fits_type(tid: TypeId, val: &Value, t: &Target) -> bool {
+    match (t.type_of(tid), val) {
+        (_, Value::Ref(_)) => false,
+        (TypeInfo::Num(info), Value::Num(n)) => num_fits(info, n),
+        (TypeInfo::Flag { .. }, Value::Num(_)) | (TypeInfo::Len { .. }, Value::Num(_)) => true,
+        (TypeInfo::Ptr { .. }, Value::None) => true,
+        (TypeInfo::Ptr { tid, .. }, val)
+        | (TypeInfo::Alias { tid, .. }, val)
+        | (TypeInfo::Res { tid }, val) => fits_type(*tid, val, t),
+        (TypeInfo::Str { vals, .. }, Value::Str(s)) => match vals {
+            Some(vals) => vals.is_empty() || vals.iter().any(|v| **v == **s),
+            None => true,
+        },
+        (TypeInfo::Slice { tid, l, h }, Value::Group(vals)) => {
+            (*l == -1 || vals.len() >= *l as usize)
+                && (*h == -1 || vals.len() <= *h as usize)
+                && vals.iter().all(|v| fits_type(*tid, v, t))
+        }
+        (TypeInfo::Struct { fields, .. }, Value::Group(vals)) => {
+            fields.len() == vals.len()
+                && fields
+                    .iter()
+                    .zip(vals.iter())
+                    .all(|(f, v)| (f.optional && *v == Value::Absent) || fits_type(f.tid, v, t))
+        }
+        (TypeInfo::Union { fields, .. }, Value::Opt { choice, val }) => {
+            *choice < fields.len() && fits_type(fields[*choice].tid, val, t)
+        }
+        _ => false,
+    }
+}
+
+fn num_fits(info: &NumInfo, n: &NumValue) -> bool {
+    fn in_limit<T: PartialOrd + Copy>(l: &NumLimit<T>, v: T) -> bool {
+        match l {
+            NumLimit::Vals(vals) => vals.contains(&v),
+            NumLimit::Range(r) => r.contains(&v),
+            NumLimit::None => true,
+        }
+    }
+
+    let v = match n.truncate_to(info) {
+        truncated if truncated != *n => return false,
+        NumValue::Signed(v) => v as u64,
+        NumValue::Unsigned(v) => v,
+    };
+    match info {
+        NumInfo::I8(l) => in_limit(l, v as i8),
+        NumInfo::I16(l) => in_limit(l, v as i16),
+        NumInfo::I32(l) => in_limit(l, v as i32),
+        NumInfo::I64(l) => in_limit(l, v as i64),
+        NumInfo::U8(l) => in_limit(l, v as u8),
+        NumInfo::U16(l) => in_limit(l, v as u16),
+        NumInfo::U32(l) => in_limit(l, v as u32),
+        NumInfo::U64(l) => in_limit(l, v),
+        NumInfo::Usize(l) => in_limit(l, v as usize),
+        NumInfo::Isize(l) => in_limit(l, v as isize),
+    }
+}
+
+/// Re-randomize val of type tid in place.
+///
+/// Refs, resources, consts and out pointers are kept, flags are always valid
+/// combination. Existing elements of slice are re-randomized and new elements
+/// are generated when it grows. Len values are not updated, `fill_lens` should
+/// be called on the call after. Return whether the new val is malformed.
+pub(crate) fn regen_value(tid: TypeId, val: &mut Value, t: &Target, conf: &Config) -> bool {
+    let conf = detached_conf(conf);
+    let mut s = State::new(&conf);
+    // resources produced by out pointers of generated values are not recorded by anyone,
+    // give them a call to record to
+    s.add_call(Call::new(0));
+    s.add_arg(Arg::new(tid));
+    do_regen_value(tid, val, t, &mut s);
+    s.prog.calls[0].args[0].malformed
+}
+
+/// Config for generating a value outside of any prog, the value is not part of
+/// prog being generated, there is nowhere to insert producers. Seed of conf is
+/// ignored, so that repeated generation of the same value differs
+fn detached_conf(conf: &Config) -> Config {
+    Config {
+        seed: None,
+        auto_producer: false,
+        ..conf.clone()
+    }
+}
+
+/// Generate value that pointer of direction dir to type tid points to, return it
+/// with whether it is malformed
+pub(crate) fn gen_pointee(dir: PtrDir, tid: TypeId, t: &Target, conf: &Config) -> (Value, bool) {
+    let conf = detached_conf(conf);
+    let mut s = State::new(&conf);
+    // same as regen_value, out resources need a call to record to
+    s.add_call(Call::new(0));
+    s.add_arg(Arg::new(tid));
+    let val = gen_ptr(dir, tid, t, &mut s);
+    (val, s.prog.calls[0].args[0].malformed)
+}
+
+fn do_regen_value(tid: TypeId, val: &mut Value, t: &Target, s: &mut State) {
+    if let Value::Ref(_) = val {
+        return;
+    }
+    if t.is_res(tid) || t.is_const(tid) {
+        return;
+    }
+
+    match t.type_of(tid) {
+        TypeInfo::Ptr { dir, tid, .. } => {
+            if *dir == PtrDir::In {
+                if *val == Value::None {
+                    *val = gen_value(*tid, t, s);
+                } else {
+                    do_regen_value(*tid, val, t, s);
+                }
+            }
+        }
+        TypeInfo::Alias { tid, .. } => do_regen_value(*tid, val, t, s),
+        TypeInfo::Slice { tid, l, h } => {
+            if let Value::Group(vals) = val {
+                let len = s.slice_len(*l, *h);
+                vals.truncate(len);
+                for v in vals.iter_mut() {
+                    do_regen_value(*tid, v, t, s);
+                }
+                while vals.len() < len {
+                    vals.push(gen_value(*tid, t, s));
+                }
+            }
+        }
+        TypeInfo::Struct { fields, .. } => {
+            if let Value::Group(vals) = val {
+                for (f, v) in fields.iter().zip(vals.iter_mut()) {
+                    do_regen_value(f.tid, v, t, s);
+                }
+            }
+        }
+        TypeInfo::Union { fields, .. } => {
+            if let Value::Opt { choice, val } = val {
+                do_regen_value(fields[*choice].tid, val, t, s);
+            }
+        }
+        TypeInfo::Flag {
+            flags, required, ..
+        } => *val = combine_flags(flags, required, &mut s.rng),
+        TypeInfo::Len { .. } => (),
+        _ => *val = gen_value(tid, t, s),
+    }
+}
+
+/// Resources refed by params of c that its fn closes, see `FN_ATTR_CLOSE`
+pub(crate) fn closed_res(c: &Call, t: &Target) -> Vec<ArgIndex> {
+    let f = t.fn_of(c.fid);
+    let attr = match f.get_attr(FN_ATTR_CLOSE) {
+        Some(attr) => attr,
+        None => return Vec::new(),
+    };
+    let mut closed = Vec::new();
+    for (p, arg) in f.params.iter().flatten().zip(c.args.iter()) {
+        if attr
+            .vals
+            .as_ref()
+            .is_none_or(|vals| vals.contains(&p.ident))
+        {
+            for_each_ref(&arg.val, |idx| closed.push(idx.clone()));
+        }
     }
+    closed
 }
 
 fn gen_alias(tid: TypeId, under_id: TypeId, t: &Target, s: &mut State) -> Value {
@@ -308,11 +1743,100 @@ fn gen_alias(tid: TypeId, under_id: TypeId, t: &Target, s: &mut State) -> Value
 }
 
 fn gen_res(res_tid: TypeId, tid: TypeId, t: &Target, s: &mut State) -> Value {
-    if let Some(res) = s.try_reuse_res(res_tid) {
-        res
-    } else {
-        gen_value(tid, t, s)
+    let p = s.conf.dangling_ref_prob;
+    if p > 0.0 && s.rng.gen::<f64>() < p {
+        s.mark_malformed();
+        let handle = match s.rng.gen_range(0, 3) {
+            0 => -1,
+            1 => i64::from(i32::MAX),
+            _ => s
+                .rng
+                .gen_range(DANGLING_HANDLES.start, DANGLING_HANDLES.end),
+        };
+        return Value::Num(NumValue::Signed(handle));
     }
+    let mut res = s.try_reuse_res(res_tid);
+    let produced = s.res.get(&res_tid).is_some_and(|r| !r.is_empty());
+    if res.is_none() && !produced && insert_producer(res_tid, t, s) {
+        // producer may record nothing, e.g. its out pointer is null
+        res = s
+            .res
+            .get(&res_tid)
+            .and_then(|r| r.last())
+            .cloned()
+            .map(Value::Ref);
+    }
+    if let Some(stats) = s.stats.as_mut() {
+        if res.is_some() {
+            stats.res_reused += 1;
+        } else {
+            stats.res_created += 1;
+        }
+    }
+    if s.observer.is_some() {
+        s.notify(match res {
+            Some(_) => GenEvent::ResourceReused(res_tid),
+            None => GenEvent::ResourceCreated(res_tid),
+        });
+    }
+    match res {
+        Some(res) => res,
+        None => gen_value(tid, t, s),
+    }
+}
+
+/// Insert call of cheapest producer of res_tid before current call, cheapest
+/// producer consumes the fewest resources. Return false if nothing is inserted.
+fn insert_producer(res_tid: TypeId, t: &Target, s: &mut State) -> bool {
+    if !s.conf.auto_producer || s.inserting.contains(&res_tid) || s.res_exhausted() {
+        return false;
+    }
+    let g = &t.groups[&s.prog.gid];
+    let f = g
+        .fns
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| s.conf.is_fn_allowed(g.id, *i))
+        .map(|(_, f)| (f, t.res_use_of(f)))
+        .filter(|(_, (_, produced))| produced.contains(&res_tid))
+        .min_by_key(|(f, (consumed, _))| (consumed.len(), f.params.as_ref().map_or(0, Vec::len)))
+        .map(|(f, _)| f);
+    let f = match f {
+        Some(f) => f,
+        None => return false,
+    };
+
+    // move current call out, resources of it must not be used by producer
+    let mut crt = s.prog.calls.pop().unwrap();
+    let cid = s.prog.len();
+    let mut crt_res = Vec::new();
+    for (tid, idx) in s.res.iter_mut() {
+        idx.retain(|(i, pos)| {
+            if *i == cid {
+                crt_res.push((*tid, (cid + 1, pos.clone())));
+            }
+            *i != cid
+        });
+    }
+
+    s.inserting.insert(res_tid);
+    let depth = std::mem::replace(&mut s.depth, 0);
+    gen_checked_call(t, f, s);
+    s.depth = depth;
+    s.inserting.remove(&res_tid);
+
+    for arg in crt.args.iter_mut() {
+        for_each_ref_mut(&mut arg.val, |(i, _)| {
+            if *i == cid {
+                *i += 1;
+            }
+        });
+    }
+    s.prog.calls.push(crt);
+    for (tid, idx) in crt_res {
+        s.res.entry(tid).or_default().push(idx);
+    }
+    true
 }
 
 fn gen_ptr(dir: PtrDir, tid: TypeId, t: &Target, s: &mut State) -> Value {
@@ -320,87 +1844,242 @@ fn gen_ptr(dir: PtrDir, tid: TypeId, t: &Target, s: &mut State) -> Value {
         if t.is_res(tid) {
             s.record_res(tid, false);
         }
-        return Value::default_val(tid, t);
+        return gen_out_buf(tid, t, s);
     }
 
-    if thread_rng().gen::<f64>() >= 0.001 {
+    if s.rng.gen::<f64>() >= s.conf.null_ptr_prob {
         gen_value(tid, t, s)
     } else {
         Value::None
     }
 }
 
-fn gen_flag(flags: &[Flag]) -> Value {
-    assert!(!flags.is_empty());
+/// Value that out pointer points to, str and slice get out_buf_len capacity
+/// so that callee has somewhere to write.
+fn gen_out_buf(tid: TypeId, t: &Target, s: &mut State) -> Value {
+    let len = s.conf.out_buf_len;
+    match t.type_of(tid) {
+        TypeInfo::Alias { tid, .. } => gen_out_buf(*tid, t, s),
+        // filled with space instead of nul, so that encoded cstr keeps its capacity
+        TypeInfo::Str { .. } => Value::Str(" ".repeat(len).into()),
+        TypeInfo::Slice { tid, l, h } => {
+            let len = match (*l, *h) {
+                (-1, -1) => len,
+                (l, -1) => len.max(l as usize),
+                (l, h) => len.max(l as usize).min(h as usize),
+            };
+            let len = s.cap_slice_len(len, *l);
+            let vals = (0..len)
+                .map(|_| Value::default_val(*tid, t, &mut s.rng))
+                .collect();
+            Value::Group(vals)
+        }
+        _ => Value::default_val(tid, t, &mut s.rng),
+    }
+}
 
-    let mut rng = thread_rng();
+fn gen_flag(flags: &[Flag], required: &[Flag], rng: &mut StdRng) -> Value {
+    assert!(!flags.is_empty());
 
-    if rng.gen::<f64>() < 0.005 {
+    // random value may miss required flag
+    if required.is_empty() && rng.gen::<f64>() < 0.005 {
         Value::Num(NumValue::Signed(rng.gen::<u8>() as i64))
     } else {
-        let flag = flags.iter().choose(&mut rng).unwrap();
-        let mut val = flag.val;
+        combine_flags(flags, required, rng)
+    }
+}
 
-        loop {
-            if rng.gen() {
-                let flag = flags.iter().choose(&mut rng).unwrap();
-                val &= flag.val;
-            } else {
-                break;
-            }
+/// Valid combination of flags with one undefined bit set
+fn gen_malformed_flag(flags: &[Flag], required: &[Flag], s: &mut State) -> Option<Value> {
+    if !s.should_malform() {
+        return None;
+    }
+    let defined = flags.iter().fold(0, |acc, f| acc | f.val);
+    let undefined = (0..32)
+        .filter(|i| defined & (1 << i) == 0)
+        .collect::<Vec<_>>();
+    let bit = *undefined.choose(&mut s.rng)?;
+    let val = match combine_flags(flags, required, &mut s.rng) {
+        Value::Num(NumValue::Signed(val)) => val,
+        _ => unreachable!(),
+    };
+    s.mark_malformed();
+    Some(Value::Num(NumValue::Signed(val | (1 << bit))))
+}
+
+/// OR-combine flags, result is always a valid combination. If required is not
+/// empty, result has exactly one of required flags plus optional extras
+fn combine_flags(flags: &[Flag], required: &[Flag], rng: &mut StdRng) -> Value {
+    if !required.is_empty() {
+        let mut val = required.choose(rng).unwrap().val;
+        let extras = flags
+            .iter()
+            .filter(|f| !required.contains(f))
+            .collect::<Vec<_>>();
+        while !extras.is_empty() && rng.gen() {
+            val |= extras.choose(rng).unwrap().val;
         }
-        Value::Num(NumValue::Signed(val))
+        return Value::Num(NumValue::Signed(val));
+    }
+
+    let flag = flags.iter().choose(rng).unwrap();
+    let mut val = flag.val;
+
+    // number of combined flags is geometric distributed, bounded by flags count
+    let mut n = 1;
+    while n < flags.len() && rng.gen() {
+        let flag = flags.iter().choose(rng).unwrap();
+        val |= flag.val;
+        n += 1;
     }
+    Value::Num(NumValue::Signed(val))
 }
 
-fn gen_union(fields: &[Field], t: &Target, s: &mut State) -> Value {
+fn gen_union(tid: TypeId, fields: &[Field], t: &Target, s: &mut State) -> Value {
     assert!(!fields.is_empty());
 
-    let i = thread_rng().gen_range(0, fields.len());
+    if let Some(i) = s.union_choice.take().filter(|&i| i < fields.len()) {
+        s.union_depth += 1;
+        let val = gen_value(fields[i].tid, t, s);
+        s.union_depth -= 1;
+        return Value::Opt {
+            choice: i,
+            val: Box::new(val),
+        };
+    }
+    if s.union_depth + 1 >= s.conf.max_union_depth {
+        let scalars = (0..fields.len())
+            .filter(|&i| is_scalar(fields[i].tid, t))
+            .collect::<Vec<_>>();
+        let i = match scalars.choose(&mut s.rng) {
+            Some(&i) => i,
+            None => return Value::default_val(tid, t, &mut s.rng),
+        };
+        return Value::Opt {
+            choice: i,
+            val: Box::new(gen_value(fields[i].tid, t, s)),
+        };
+    }
+
+    let weights = s
+        .conf
+        .union_weights
+        .get(&tid)
+        .filter(|ws| ws.len() == fields.len())
+        .and_then(|ws| WeightedIndex::new(ws).ok());
+    let i = match weights {
+        Some(weights) => weights.sample(&mut s.rng),
+        None => s.rng.gen_range(0, fields.len()),
+    };
     let field = &fields[i];
 
+    s.union_depth += 1;
+    let val = gen_value(field.tid, t, s);
+    s.union_depth -= 1;
     Value::Opt {
         choice: i,
-        val: Box::new(gen_value(field.tid, t, s)),
+        val: Box::new(val),
+    }
+}
+
+/// Whether values of type tid hold no nested values
+fn is_scalar(tid: TypeId, t: &Target) -> bool {
+    match t.type_of(tid) {
+        TypeInfo::Alias { tid, .. } => is_scalar(*tid, t),
+        TypeInfo::Num(_) | TypeInfo::Flag { .. } | TypeInfo::Res { .. } | TypeInfo::Len { .. } => {
+            true
+        }
+        _ => false,
     }
 }
 
-fn gen_struct(fields: &[Field], t: &Target, s: &mut State) -> Value {
+/// Generate fields of struct, len fields are placeholders during generation and
+/// read length of their sibling fields after all fields are generated, so that
+/// struct is consistent even before `fill_lens` of whole call.
+fn gen_struct(fields: &[Field], tag: Option<(usize, usize)>, t: &Target, s: &mut State) -> Value {
+    // member of tag flag and variant of tagged union named as it
+    let tagged = tag.and_then(|(flag, body)| {
+        let variants = tag_variants(fields[flag].tid, fields[body].tid, t);
+        variants
+            .choose(&mut s.rng)
+            .map(|&(val, choice)| (flag, val, body, choice))
+    });
     let mut vals = Vec::new();
-    for field in fields.iter() {
-        vals.push(gen_value(field.tid, t, s));
+    for (i, field) in fields.iter().enumerate() {
+        if field.optional
+            && (s.values_left() == 0 || s.rng.gen::<f64>() >= s.conf.optional_field_prob)
+        {
+            vals.push(Value::Absent);
+        } else if let Some((_, val, ..)) = tagged.filter(|(flag, ..)| *flag == i) {
+            vals.push(Value::Num(NumValue::Signed(val)));
+        } else {
+            if let Some((.., choice)) = tagged.filter(|(_, _, body, _)| *body == i) {
+                s.union_choice = Some(choice);
+            }
+            let val = match (gen_value(field.tid, t, s), field.bits) {
+                // bitfield only holds low bits of value
+                (Value::Num(n), Some(bits)) => Value::Num(n.truncate_bits(bits)),
+                (val, _) => val,
+            };
+            // not taken if union is not generated, e.g. beyond max_gen_depth
+            s.union_choice = None;
+            vals.push(val);
+        }
     }
+    fill_struct_lens(fields, &mut vals, t);
     Value::Group(vals)
 }
 
+/// Pairs of value of member of flag and index of variant of union with same name
+fn tag_variants(flag_tid: TypeId, union_tid: TypeId, t: &Target) -> Vec<(i64, usize)> {
+    match (t.type_of(flag_tid), t.type_of(union_tid)) {
+        (TypeInfo::Flag { flags, .. }, TypeInfo::Union { fields, .. }) => flags
+            .iter()
+            .filter_map(|f| {
+                let choice = fields.iter().position(|v| v.ident == f.ident)?;
+                Some((f.val, choice))
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
 fn gen_str(str_type: &StrType, vals: &Option<Vec<String>>, s: &mut State) -> Value {
-    let mut rng = thread_rng();
     if let Some(vals) = vals {
         if !vals.is_empty() {
-            return Value::Str(vals.choose(&mut rng).unwrap().clone());
+            return Value::Str(vals.choose(&mut s.rng).unwrap().as_str().into());
         }
     }
+    if let Some(g) = s.conf.grammars.get(str_type) {
+        let val = g.gen(&mut s.rng);
+        s.record_str(str_type.clone(), &val);
+        return Value::Str(val.into());
+    }
     if let Some(s) = s.try_reuse_str(str_type.clone()) {
         return s;
     }
 
+    let rng = &mut s.rng;
     let len = rng.gen_range(s.conf.str_min_len, s.conf.str_max_len);
     match str_type {
         StrType::Str => {
-            //            let val = rng
-            //                .sample_iter::<char, Standard>(Standard)
-            //                .take(len)
-            //                .collect::<String>();
-            let val = rng.sample_iter(Alphanumeric).take(len).collect::<String>();
+            let val = s.conf.str_charset.sample_str(len, rng);
             s.record_str(StrType::Str, &val);
-            Value::Str(val)
+            Value::Str(val.into())
         }
         StrType::CStr => {
-            let val = rng.sample_iter(Alphanumeric).take(len).collect::<String>();
+            // interior nul terminates c string early
+            let val = s.conf.str_charset.sample_str(len, rng).replace('\0', "");
             s.record_str(StrType::CStr, &val);
-            Value::Str(val)
+            Value::Str(val.into())
         }
         StrType::FileName => {
+            let dict = &s.conf.filename_dictionary;
+            if !dict.is_empty() && rng.gen::<f64>() < s.conf.filename_dictionary_prob {
+                let p = dict.choose(rng).unwrap().clone();
+                s.record_str(StrType::FileName, &p);
+                return Value::Str(p.into());
+            }
             let mut path = PathBuf::from(".");
             let mut depth = 0;
             loop {
@@ -411,7 +2090,7 @@ fn gen_str(str_type: &StrType, vals: &Option<Vec<String>>, s: &mut State) -> Val
                     continue;
                 } else if let Ok(p) = path.into_os_string().into_string() {
                     s.record_str(StrType::FileName, &p);
-                    return Value::Str(p);
+                    return Value::Str(p.into());
                 } else {
                     path = PathBuf::from(".");
                     depth = 0;
@@ -422,149 +2101,296 @@ fn gen_str(str_type: &StrType, vals: &Option<Vec<String>>, s: &mut State) -> Val
 }
 
 fn gen_slice(tid: TypeId, l: isize, h: isize, t: &Target, s: &mut State) -> Value {
-    let len: usize = gen_slice_len(l, h);
+    let len = if h != -1 && s.should_malform() {
+        s.mark_malformed();
+        s.rng
+            .gen_range(h as usize + 1, h as usize + 1 + SLICE_EXTRA_LEN)
+    } else {
+        s.slice_len(l, h)
+    };
+    // elements beyond budget are dropped, but lower bound is kept
+    let len = len.min(s.values_left()).max(l.max(0) as usize);
+    let len = s.cap_slice_len(len, l);
+    if let Some(stats) = s.stats.as_mut() {
+        *stats.slice_lens.entry(len).or_default() += 1;
+    }
     let mut vals = Vec::new();
 
-    for _ in 0..len {
+    let min_len = l.max(0) as usize;
+    for i in 0..len {
+        // budget may run out while generating elements
+        if i >= min_len && s.values_left() == 0 {
+            break;
+        }
         vals.push(gen_value(tid, t, s));
     }
     Value::Group(vals)
 }
 
-pub(crate) fn gen_slice_len(l: isize, h: isize) -> usize {
+/// Max number of extra elements for slice that only has lower bound
+const SLICE_EXTRA_LEN: usize = 8;
+
+pub(crate) fn gen_slice_len<R: Rng + ?Sized>(l: isize, h: isize, rng: &mut R) -> usize {
     match (l, h) {
-        (-1, -1) => thread_rng().gen_range(1, 8),
-        (l, -1) => l as usize,
-        (l, h) => thread_rng().gen_range(l as usize, h as usize),
+        (-1, -1) => rng.gen_range(1, 8),
+        // fixed length
+        (l, h) if l == h => l as usize,
+        // l is lower bound, no upper bound
+        (l, -1) => rng.gen_range(l as usize, l as usize + SLICE_EXTRA_LEN),
+        (l, h) if h > l => rng.gen_range(l as usize, h as usize),
+        // empty range, use lower bound
+        (l, _) => l as usize,
     }
 }
 
-fn gen_num(type_info: &NumInfo) -> Value {
-    let mut rng = thread_rng();
-
+fn gen_num(type_info: &NumInfo, conf: &Config, rng: &mut StdRng) -> Value {
+    let p = conf.edge_value_prob;
     match type_info {
-        NumInfo::I8(l) => match l {
-            NumLimit::Vals(vals) => {
-                Value::Num(NumValue::Signed(*vals.choose(&mut rng).unwrap() as i64))
-            }
-            NumLimit::Range(r) => {
-                Value::Num(NumValue::Signed(rng.gen_range(r.start, r.end) as i64))
-            }
-            NumLimit::None => Value::Num(NumValue::Signed(rng.gen::<i8>() as i64)),
-        },
-        NumInfo::I16(l) => match l {
-            NumLimit::Vals(vals) => {
-                Value::Num(NumValue::Signed(*vals.choose(&mut rng).unwrap() as i64))
-            }
-            NumLimit::Range(r) => {
-                Value::Num(NumValue::Signed(rng.gen_range(r.start, r.end) as i64))
-            }
-            NumLimit::None => Value::Num(NumValue::Signed(rng.gen::<i16>() as i64)),
-        },
-        NumInfo::I32(l) => match l {
-            NumLimit::Vals(vals) => {
-                Value::Num(NumValue::Signed(*vals.choose(&mut rng).unwrap() as i64))
-            }
-            NumLimit::Range(r) => {
-                Value::Num(NumValue::Signed(rng.gen_range(r.start, r.end) as i64))
-            }
-            NumLimit::None => Value::Num(NumValue::Signed(rng.gen::<i32>() as i64)),
-        },
-        NumInfo::I64(l) => match l {
-            NumLimit::Vals(vals) => {
-                Value::Num(NumValue::Signed(*vals.choose(&mut rng).unwrap() as i64))
-            }
-            NumLimit::Range(r) => {
-                Value::Num(NumValue::Signed(rng.gen_range(r.start, r.end) as i64))
-            }
-            NumLimit::None => Value::Num(NumValue::Signed(rng.gen::<i64>() as i64)),
-        },
-        NumInfo::U8(l) => match l {
-            NumLimit::Vals(vals) => {
-                Value::Num(NumValue::Unsigned(*vals.choose(&mut rng).unwrap() as u64))
-            }
-            NumLimit::Range(r) => {
-                Value::Num(NumValue::Unsigned(rng.gen_range(r.start, r.end) as u64))
-            }
-            NumLimit::None => Value::Num(NumValue::Unsigned(rng.gen::<u8>() as u64)),
-        },
-        NumInfo::U16(l) => match l {
-            NumLimit::Vals(vals) => {
-                Value::Num(NumValue::Unsigned(*vals.choose(&mut rng).unwrap() as u64))
-            }
-            NumLimit::Range(r) => {
-                Value::Num(NumValue::Unsigned(rng.gen_range(r.start, r.end) as u64))
-            }
-            NumLimit::None => Value::Num(NumValue::Unsigned(rng.gen::<u16>() as u64)),
-        },
-        NumInfo::U32(l) => match l {
-            NumLimit::Vals(vals) => {
-                Value::Num(NumValue::Unsigned(*vals.choose(&mut rng).unwrap() as u64))
-            }
-            NumLimit::Range(r) => {
-                Value::Num(NumValue::Unsigned(rng.gen_range(r.start, r.end) as u64))
-            }
-            NumLimit::None => Value::Num(NumValue::Unsigned(rng.gen::<u32>() as u64)),
-        },
-        NumInfo::U64(l) => match l {
-            NumLimit::Vals(vals) => {
-                Value::Num(NumValue::Unsigned(*vals.choose(&mut rng).unwrap() as u64))
-            }
-            NumLimit::Range(r) => {
-                Value::Num(NumValue::Unsigned(rng.gen_range(r.start, r.end) as u64))
-            }
-            NumLimit::None => Value::Num(NumValue::Unsigned(rng.gen::<u64>() as u64)),
-        },
-        NumInfo::Usize(l) => match l {
-            NumLimit::Vals(vals) => {
-                Value::Num(NumValue::Unsigned(*vals.choose(&mut rng).unwrap() as u64))
-            }
-            NumLimit::Range(r) => {
-                Value::Num(NumValue::Unsigned(rng.gen_range(r.start, r.end) as u64))
-            }
-            NumLimit::None => Value::Num(NumValue::Unsigned(rng.gen::<usize>() as u64)),
-        },
-        NumInfo::Isize(l) => match l {
-            NumLimit::Vals(vals) => {
-                Value::Num(NumValue::Signed(*vals.choose(&mut rng).unwrap() as i64))
+        NumInfo::I8(l) => Value::Num(NumValue::Signed(gen_num_val(l, p, rng) as i64)),
+        NumInfo::I16(l) => Value::Num(NumValue::Signed(gen_num_val(l, p, rng) as i64)),
+        NumInfo::I32(l) => Value::Num(NumValue::Signed(gen_num_val(l, p, rng) as i64)),
+        NumInfo::I64(l) => Value::Num(NumValue::Signed(gen_num_val(l, p, rng))),
+        NumInfo::U8(l) => Value::Num(NumValue::Unsigned(gen_num_val(l, p, rng) as u64)),
+        NumInfo::U16(l) => Value::Num(NumValue::Unsigned(gen_num_val(l, p, rng) as u64)),
+        NumInfo::U32(l) => Value::Num(NumValue::Unsigned(gen_num_val(l, p, rng) as u64)),
+        NumInfo::U64(l) => Value::Num(NumValue::Unsigned(gen_num_val(l, p, rng))),
+        NumInfo::Usize(l) => Value::Num(NumValue::Unsigned(gen_num_val(l, p, rng) as u64)),
+        NumInfo::Isize(l) => Value::Num(NumValue::Signed(gen_num_val(l, p, rng) as i64)),
+    }
+}
+
+/// Num out of declared range, only range limited num can be malformed
+fn gen_malformed_num(type_info: &NumInfo, s: &mut State) -> Option<Value> {
+    if !s.should_malform() {
+        return None;
+    }
+    let rng = &mut s.rng;
+    let val = match type_info {
+        NumInfo::I8(l) => Value::Num(NumValue::Signed(malformed_num_val(l, rng)? as i64)),
+        NumInfo::I16(l) => Value::Num(NumValue::Signed(malformed_num_val(l, rng)? as i64)),
+        NumInfo::I32(l) => Value::Num(NumValue::Signed(malformed_num_val(l, rng)? as i64)),
+        NumInfo::I64(l) => Value::Num(NumValue::Signed(malformed_num_val(l, rng)?)),
+        NumInfo::U8(l) => Value::Num(NumValue::Unsigned(malformed_num_val(l, rng)? as u64)),
+        NumInfo::U16(l) => Value::Num(NumValue::Unsigned(malformed_num_val(l, rng)? as u64)),
+        NumInfo::U32(l) => Value::Num(NumValue::Unsigned(malformed_num_val(l, rng)? as u64)),
+        NumInfo::U64(l) => Value::Num(NumValue::Unsigned(malformed_num_val(l, rng)?)),
+        NumInfo::Usize(l) => Value::Num(NumValue::Unsigned(malformed_num_val(l, rng)? as u64)),
+        NumInfo::Isize(l) => Value::Num(NumValue::Signed(malformed_num_val(l, rng)? as i64)),
+    };
+    s.mark_malformed();
+    Some(val)
+}
+
+fn malformed_num_val<T: EdgeVal + Copy>(l: &NumLimit<T>, rng: &mut StdRng) -> Option<T> {
+    match l {
+        NumLimit::Range(r) => T::edge_vals_out(r).choose(rng).copied(),
+        _ => None,
+    }
+}
+
+/// Generate num value under limit, edge value is used with probability edge_prob.
+fn gen_num_val<T>(l: &NumLimit<T>, edge_prob: f64, rng: &mut StdRng) -> T
+where
+    T: EdgeVal + SampleUniform + Copy,
+    Standard: Distribution<T>,
+{
+    match l {
+        NumLimit::Vals(vals) => *vals.choose(rng).unwrap(),
+        NumLimit::Range(r) => {
+            if rng.gen::<f64>() < edge_prob {
+                if let Some(v) = T::edge_vals_in(r).choose(rng) {
+                    return *v;
+                }
             }
-            NumLimit::Range(r) => {
-                Value::Num(NumValue::Signed(rng.gen_range(r.start, r.end) as i64))
+            rng.gen_range(r.start, r.end)
+        }
+        NumLimit::None => {
+            if rng.gen::<f64>() < edge_prob {
+                *T::edge_vals().choose(rng).unwrap()
+            } else {
+                rng.gen()
             }
-            NumLimit::None => Value::Num(NumValue::Signed(rng.gen::<isize>() as i64)),
-        },
+        }
     }
 }
 
-fn choose_seq(rs: &RTable, conf: &Config) -> Vec<usize> {
+/// Interesting values of num type: 0, 1, -1, min, max and powers of two.
+trait EdgeVal: Sized {
+    fn edge_vals() -> Vec<Self>;
+
+    /// Edge values inside range r, bounds of r are edge values too.
+    fn edge_vals_in(r: &Range<Self>) -> Vec<Self>;
+
+    /// Edge values outside range r, values just beyond bounds of r are included.
+    fn edge_vals_out(r: &Range<Self>) -> Vec<Self>;
+}
+
+macro_rules! impl_edge_val {
+    ($($t:ty),*) => {
+        $(
+            impl EdgeVal for $t {
+                fn edge_vals() -> Vec<Self> {
+                    let bits = std::mem::size_of::<$t>() * 8;
+                    let mut vals: Vec<$t> = vec![0, 1, (0 as $t).wrapping_sub(1), <$t>::MIN, <$t>::MAX];
+                    for i in 1..bits - 1 {
+                        let v: $t = 1 << i;
+                        vals.push(v);
+                        vals.push(v.wrapping_neg());
+                    }
+                    vals.sort();
+                    vals.dedup();
+                    vals
+                }
+
+                fn edge_vals_in(r: &Range<Self>) -> Vec<Self> {
+                    if r.start >= r.end {
+                        return Vec::new();
+                    }
+                    let mut vals = Self::edge_vals();
+                    vals.retain(|v| r.contains(v));
+                    vals.push(r.start);
+                    vals.push(r.end - 1);
+                    vals.sort();
+                    vals.dedup();
+                    vals
+                }
+
+                fn edge_vals_out(r: &Range<Self>) -> Vec<Self> {
+                    let mut vals = Self::edge_vals();
+                    vals.retain(|v| !r.contains(v));
+                    if r.start > <$t>::MIN {
+                        vals.push(r.start - 1);
+                    }
+                    vals.push(r.end);
+                    vals.sort();
+                    vals.dedup();
+                    vals
+                }
+            }
+        )*
+    };
+}
+
+impl_edge_val!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+fn choose_seq(g: &Group, rs: &RTable, t: &Target, conf: &Config, rng: &mut StdRng) -> Vec<usize> {
     assert!(!rs.is_empty());
 
-    // selection prability list
-    let mut sps = std::iter::repeat(1.0).take(rs.len()).collect::<Vec<_>>();
+    // selection prability list, fns that are not allowed are never selected
+    let mut sps = (0..rs.len())
+        .map(|i| {
+            if conf.is_fn_allowed(g.id, i) {
+                1.0
+            } else {
+                0.0
+            }
+        })
+        .collect::<Vec<_>>();
+    let weights = fn_weights(g, conf);
     let mut seq = Vec::new();
     let mut i;
-    while !should_stop(seq.len(), &conf) {
-        let index = choose_call(&sps);
+    while !should_stop_seq(seq.len(), &conf, rng) {
+        let ws = match &weights {
+            Some(weights) => sps.iter().zip(weights).map(|(sp, w)| sp * w).collect(),
+            None => sps.clone(),
+        };
+        let index = if seq.is_empty() && rng.gen::<f64>() < conf.resource_start_bias {
+            choose_producer(g, &ws, t, rng).unwrap_or_else(|| choose_call(&ws, rng))
+        } else {
+            choose_call(&ws, rng)
+        };
         sps[index] *= conf.sp_delta;
         seq.push(index);
         i = seq.len() - 1;
-        push_deps(rs, &mut seq, i, &mut sps, conf);
+        push_deps(g.id, rs, &mut seq, i, &mut sps, conf, rng);
     }
 
     seq.shrink_to_fit();
     seq.reverse();
-    assert!(seq.len() >= conf.prog_min_len);
+    assert!(seq.len() >= conf.target_len.unwrap_or(conf.prog_min_len));
     seq
 }
 
-fn should_stop(prog_len: usize, conf: &Config) -> bool {
+/// Hard limit of length of sequence
+fn max_len(conf: &Config) -> usize {
+    conf.target_len.unwrap_or(conf.prog_max_len)
+}
+
+/// Normalized weights of fns of group g, None if no weight is configured
+fn fn_weights(g: &Group, conf: &Config) -> Option<Vec<f64>> {
+    if !conf.fn_weights.keys().any(|(gid, _)| *gid == g.id) {
+        return None;
+    }
+    let weights = (0..g.fns.len())
+        .map(|i| conf.fn_weight(g.id, i))
+        .collect::<Vec<_>>();
+    let sum = weights.iter().sum::<f64>();
+    Some(weights.into_iter().map(|w| w / sum).collect())
+}
+
+/// Fns of group g that can be called with all consumed resources produced.
+///
+/// A fn is reachable if every resource it consumes is produced by some other
+/// reachable fn it depends on in r, fns with zero weight or not allowed by
+/// config are never reachable.
+/// Result is indexed by the position of fn in group.
+pub fn reachable_fns(g: &Group, t: &Target, r: &RTable, conf: &Config) -> Vec<bool> {
+    let uses = g.fns.iter().map(|f| t.res_use_of(f)).collect::<Vec<_>>();
+    let allowed = (0..g.fns.len())
+        .map(|i| conf.fn_weight(g.id, i) > 0.0 && conf.is_fn_allowed(g.id, i))
+        .collect::<Vec<_>>();
+    let mut reachable = vec![false; g.fns.len()];
+    loop {
+        let mut changed = false;
+        for (i, (consumed, _)) in uses.iter().enumerate() {
+            if reachable[i] || !allowed[i] {
+                continue;
+            }
+            let satisfied = consumed.iter().all(|tid| {
+                uses.iter().enumerate().any(|(j, (_, produced))| {
+                    j != i && reachable[j] && r[(i, j)] == Relation::Some && produced.contains(tid)
+                })
+            });
+            if satisfied {
+                reachable[i] = true;
+                changed = true;
+            }
+        }
+        if !changed {
+            return reachable;
+        }
+    }
+}
+
+/// Whether to stop adding independent calls to sequence
+fn should_stop_seq(prog_len: usize, conf: &Config, rng: &mut StdRng) -> bool {
+    match conf.continue_prob {
+        Some(p) if conf.target_len.is_none() => {
+            !(prog_len < conf.prog_min_len
+                || (prog_len < conf.prog_max_len && rng.gen::<f64>() < p))
+        }
+        _ => should_stop(prog_len, conf, rng),
+    }
+}
+
+/// Whether fn i depends on other allowed fns
+fn has_deps(gid: GroupId, rs: &RTable, i: usize, conf: &Config) -> bool {
+    rs.index_axis(Axis(0), i)
+        .iter()
+        .enumerate()
+        .any(|(j, r)| j != i && *r == Relation::Some && conf.is_fn_allowed(gid, j))
+}
+
+fn should_stop(prog_len: usize, conf: &Config, rng: &mut StdRng) -> bool {
+    if let Some(len) = conf.target_len {
+        return prog_len >= len;
+    }
     let crt_progress = (prog_len as f64) / (conf.prog_max_len as f64);
     !(prog_len < conf.prog_min_len
-        || (prog_len < conf.prog_max_len && random::<f64>() > crt_progress))
+        || (prog_len < conf.prog_max_len && rng.gen::<f64>() > crt_progress))
 }
 
-fn choose_call(sps: &[f64]) -> usize {
-    let mut rng = thread_rng();
+fn choose_call(sps: &[f64], rng: &mut StdRng) -> usize {
     let mut cum_sum = std::iter::repeat(0.0).take(sps.len()).collect::<Vec<_>>();
     let mut pre = 0.0;
 
@@ -582,15 +2408,53 @@ fn choose_call(sps: &[f64]) -> usize {
     unreachable!()
 }
 
+/// Choose call that returns resource, return None if group has no such call.
+fn choose_producer(g: &Group, sps: &[f64], t: &Target, rng: &mut StdRng) -> Option<usize> {
+    let sps = g
+        .iter_fn()
+        .zip(sps.iter())
+        .map(|(f, sp)| match f.r_tid {
+            Some(tid) if t.is_res(tid) => *sp,
+            _ => 0.0,
+        })
+        .collect::<Vec<_>>();
+    if sps.iter().any(|sp| *sp > 0.0) {
+        Some(choose_call(&sps, rng))
+    } else {
+        None
+    }
+}
+
+/// Push deps of calls in seq[i..] to seq.
+///
+/// Seq itself is used as work queue, pushed deps are visited in later iterations,
+/// so deep dependency chains don't grow the stack.
 #[allow(clippy::collapsible_if)]
-fn push_deps(rs: &RTable, seq: &mut Vec<usize>, mut i: usize, sps: &mut [f64], conf: &Config) {
+fn push_deps(
+    gid: GroupId,
+    rs: &RTable,
+    seq: &mut Vec<usize>,
+    mut i: usize,
+    sps: &mut [f64],
+    conf: &Config,
+    rng: &mut StdRng,
+) {
     let mut call_index;
 
-    while !should_stop(seq.len(), &conf) && i < seq.len() {
+    while !should_stop(seq.len(), &conf, rng) && i < seq.len() {
         call_index = seq[i];
         for (j, r) in rs.index_axis(Axis(0), call_index).iter().enumerate() {
-            if call_index != j && random::<f64>() < sps[j] {
-                if *r == Relation::Some || random::<f64>() < 0.05 {
+            // max len is a hard limit
+            if seq.len() >= max_len(conf) {
+                return;
+            }
+            let sp = match conf.chain_bias {
+                b if b > 0.0 && has_deps(gid, rs, j, conf) => sps[j] + b * (1.0 - sps[j]),
+                b if b > 0.0 => sps[j] * (1.0 - b),
+                _ => sps[j],
+            };
+            if call_index != j && conf.is_fn_allowed(gid, j) && rng.gen::<f64>() < sp {
+                if *r == Relation::Some || rng.gen::<f64>() < 0.05 {
                     sps[j] *= conf.sp_delta;
                     seq.push(j);
                 }
@@ -599,3 +2463,1774 @@ fn push_deps(rs: &RTable, seq: &mut Vec<usize>, mut i: usize, sps: &mut [f64], c
         i += 1;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze::static_analyze;
+
+    const DESC: &str = r#"
+type fd = res<i32>
+flag open_flags{O_RDWR=2,O_APPEND=1024,O_TRUNC=512,O_CREAT=64,O_EXCL=128}
+
+group RW{
+    fn open(f *filename, flags open_flags) fd
+    fn read(f fd, buf *Out [i8], count len<i32, buf>)
+    fn write(f fd, buf *[i8], count len<i32, buf>)
+    fn close(f fd)
+}
+"#;
+
+    fn target(desc: &str) -> Target {
+        Target::from(fots::parse_items(desc).unwrap())
+    }
+
+    #[test]
+    fn gen_with_seed() {
+        let t = target(DESC);
+        let rs = static_analyze(&t);
+        let mut conf = Config {
+            seed: Some(42),
+            ..Default::default()
+        };
+
+        let p = gen(&t, &rs, &conf).unwrap();
+        assert_eq!(p.seed, Some(42));
+        for _ in 0..8 {
+            assert_eq!(gen(&t, &rs, &conf).unwrap(), p);
+        }
+
+        // replay prog generated with entropy seed
+        let p = gen(&t, &rs, &Config::default()).unwrap();
+        conf.seed = p.seed;
+        assert_eq!(gen(&t, &rs, &conf).unwrap(), p);
+    }
+
+    #[test]
+    fn gen_flag_or_combined() {
+        let flags = (0..8)
+            .map(|i| Flag::new(&format!("F{}", i), 1 << i))
+            .collect::<Vec<_>>();
+        let mask = flags.iter().fold(0, |acc, f| acc | f.val);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let mut combined = false;
+        for _ in 0..1024 {
+            if let Value::Num(NumValue::Signed(val)) = gen_flag(&flags, &[], &mut rng) {
+                assert_eq!(val & !mask, 0);
+                combined |= val.count_ones() > 1;
+            } else {
+                panic!()
+            }
+        }
+        assert!(combined);
+    }
+
+    #[test]
+    fn slice_len_bounds() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..1024 {
+            let len = gen_slice_len(0, -1, &mut rng);
+            assert!(len < SLICE_EXTRA_LEN);
+            let len = gen_slice_len(4, -1, &mut rng);
+            assert!((4..4 + SLICE_EXTRA_LEN).contains(&len));
+            assert_eq!(gen_slice_len(2, 2, &mut rng), 2);
+        }
+    }
+
+    #[test]
+    fn fill_len_of_slice() {
+        let t = target(
+            r#"
+struct iovec { base *[i8], base_len len<u64, base> }
+group VEC {
+    fn write(buf *[i8], count len<i32, buf>)
+    fn writev(iov *iovec, count len<i32, iov.base>)
+}
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        for seed in 0..64 {
+            let conf = Config {
+                seed: Some(seed),
+                ..Default::default()
+            };
+            let p = gen_seq(&[0, 1], g.id, &t, &conf);
+            if let Value::Group(buf) = &p.calls[0].args[0].val {
+                let l = Value::Num(NumValue::Unsigned(buf.len() as u64));
+                assert_eq!(p.calls[0].args[1].val, l);
+            }
+            if let Value::Group(iov) = &p.calls[1].args[0].val {
+                if let Value::Group(base) = &iov[0] {
+                    let l = Value::Num(NumValue::Unsigned(base.len() as u64));
+                    assert_eq!(iov[1], l);
+                    assert_eq!(p.calls[1].args[1].val, l);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gen_recursive_type() {
+        fn depth(v: &Value) -> usize {
+            match v {
+                Value::Group(vals) => 1 + vals.iter().map(depth).max().unwrap_or(0),
+                Value::Opt { val, .. } => 1 + depth(val),
+                _ => 0,
+            }
+        }
+
+        let t = target(
+            r#"
+struct node { val i32, next *node }
+fn walk(n *node)
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            seed: Some(0),
+            max_gen_depth: 8,
+            ..Default::default()
+        };
+        let p = gen_seq(&[0], g.id, &t, &conf);
+        assert!(depth(&p.calls[0].args[0].val) <= conf.max_gen_depth);
+    }
+
+    #[test]
+    fn gen_tagged_union() {
+        let t = target(
+            r#"
+flag af {AF_UNIX=1, AF_INET=2}
+struct sa_un { path [i8;(4, 8)] }
+struct sa_in { port u16, addr u32 }
+union sa { AF_UNIX sa_un, AF_INET sa_in }
+#[tag(family, addr)]
+struct sockaddr { family af, addr sa }
+fn bind(a *sockaddr)
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let mut seen = HashSet::new();
+        for _ in 0..64 {
+            let conf = Config {
+                null_ptr_prob: 0.0,
+                ..Default::default()
+            };
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            match &p.calls[0].args[0].val {
+                Value::Group(vals) => match (&vals[0], &vals[1]) {
+                    (Value::Num(NumValue::Signed(family)), Value::Opt { choice, .. }) => {
+                        assert_eq!(*family, *choice as i64 + 1);
+                        seen.insert(*choice);
+                    }
+                    vals => panic!("unexpected sockaddr {:?}", vals),
+                },
+                val => panic!("unexpected sockaddr {:?}", val),
+            }
+        }
+        assert_eq!(seen.len(), 2);
+    }
+
+    #[test]
+    fn gen_max_union_depth() {
+        fn union_depth(val: &Value) -> usize {
+            match val {
+                Value::Group(vals) => vals.iter().map(union_depth).max().unwrap_or(0),
+                Value::Opt { val, .. } => 1 + union_depth(val),
+                _ => 0,
+            }
+        }
+
+        let t = target(
+            r#"
+union expr { lit i32, pair *pair, name *cstr }
+struct pair { l expr, r expr }
+fn eval(e *expr)
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let mut deepest = 0;
+        for seed in 0..64 {
+            let mut conf = Config {
+                seed: Some(seed),
+                ..Default::default()
+            };
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            deepest = deepest.max(union_depth(&p.calls[0].args[0].val));
+
+            conf.max_union_depth = 2;
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            assert!(union_depth(&p.calls[0].args[0].val) <= 2);
+        }
+        assert!(deepest > 2);
+    }
+
+    #[test]
+    fn start_with_producer() {
+        let t = target(DESC);
+        let rs = static_analyze(&t);
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            resource_start_bias: 1.0,
+            ..Default::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..256 {
+            let seq = choose_seq(g, &rs[&g.id], &t, &conf, &mut rng);
+            // first chosen call is the last one of reversed sequence
+            let f = &g.fns[*seq.last().unwrap()];
+            assert!(t.is_res(f.r_tid.unwrap()));
+        }
+    }
+
+    #[test]
+    fn gen_edge_num() {
+        let conf = Config {
+            edge_value_prob: 1.0,
+            ..Default::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let mut vals = HashSet::new();
+        for _ in 0..4096 {
+            vals.insert(gen_num(&NumInfo::I8(NumLimit::None), &conf, &mut rng));
+        }
+        for v in [0, 1, -1, i8::MIN as i64, i8::MAX as i64, 64].iter() {
+            assert!(vals.contains(&Value::Num(NumValue::Signed(*v))));
+        }
+
+        let info = NumInfo::U32(NumLimit::Range(10..100));
+        let mut vals = HashSet::new();
+        for _ in 0..4096 {
+            if let Value::Num(NumValue::Unsigned(v)) = gen_num(&info, &conf, &mut rng) {
+                assert!((10..100).contains(&v));
+                vals.insert(v);
+            }
+        }
+        for v in [10, 16, 32, 64, 99].iter() {
+            assert!(vals.contains(v));
+        }
+    }
+
+    #[test]
+    fn gen_dangling_ref() {
+        use crate::minimize::minimize_args;
+
+        let t = target(DESC);
+        let g = t.iter_group().next().unwrap();
+        let mut conf = Config::default();
+        for _ in 0..32 {
+            let p = gen_seq(&[0, 3], g.id, &t, &conf);
+            assert_eq!(p.calls[1].args[0].val, Value::Ref((0, ArgPos::Ret)));
+            assert!(!p.calls[1].args[0].malformed);
+        }
+
+        conf.dangling_ref_prob = 1.0;
+        for _ in 0..32 {
+            let p = gen_seq(&[0, 3], g.id, &t, &conf);
+            let arg = &p.calls[1].args[0];
+            assert!(arg.malformed);
+            match arg.val {
+                Value::Num(NumValue::Signed(h)) => {
+                    assert!(h == -1 || h == i64::from(i32::MAX) || DANGLING_HANDLES.contains(&h))
+                }
+                ref val => panic!("unexpected handle {:?}", val),
+            }
+            let m = minimize_args(&p, &t, |_| true);
+            assert_eq!(m.calls[1].args[0], *arg);
+        }
+    }
+
+    #[test]
+    fn gen_with_chain_bias() {
+        // longest chain of calls linked by refs
+        fn chain_depth(p: &Prog) -> usize {
+            let mut depths = Vec::new();
+            for c in p.calls.iter() {
+                let mut d = 1;
+                for a in c.args.iter() {
+                    for_each_ref(&a.val, |(i, _)| d = d.max(depths[*i] + 1));
+                }
+                depths.push(d);
+            }
+            depths.into_iter().max().unwrap_or(0)
+        }
+
+        let t = target(
+            r#"
+type r0 = res<i32>
+type r1 = res<i32>
+type r2 = res<i32>
+type r3 = res<i32>
+group L {
+    fn l0() r0
+    fn l1(a r0) r1
+    fn l2(a r1) r2
+    fn l3(a r2) r3
+    fn l4(a r3)
+    fn p1() r1
+    fn p2() r2
+    fn p3() r3
+}
+"#,
+        );
+        let rs = static_analyze(&t);
+        let avg_depth = |chain_bias: f64| {
+            let conf = Config {
+                chain_bias,
+                ..Default::default()
+            };
+            let depth = (0..1024)
+                .map(|seed| {
+                    let conf = Config {
+                        seed: Some(seed),
+                        ..conf.clone()
+                    };
+                    chain_depth(&gen(&t, &rs, &conf).unwrap())
+                })
+                .sum::<usize>();
+            depth as f64 / 1024.0
+        };
+        let (flat, biased) = (avg_depth(0.0), avg_depth(1.0));
+        assert!(biased > flat * 1.1);
+    }
+
+    #[test]
+    fn gen_boundary_slice_len() {
+        let t = target(
+            r#"
+fn write(buf *[i8;(2, 6)], raw *[u8])
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            slice_boundary_prob: 1.0,
+            null_ptr_prob: 0.0,
+            ..Default::default()
+        };
+        let mut lens = HashSet::new();
+        for _ in 0..64 {
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            let args = &p.calls[0].args;
+            lens.insert(args[0].val.len().unwrap());
+            assert_eq!(args[1].val.len(), Some(0));
+        }
+        assert_eq!(lens, hashset! {2, 6});
+    }
+
+    #[test]
+    fn gen_str_with_grammar() {
+        let t = target(
+            r#"
+fn mount(src *filename, opts *cstr)
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let opts = Grammar::new(|rng| {
+            let n = rng.gen_range(1, 4);
+            (0..n)
+                .map(|i| format!("{}={}", (b'a' + i) as char, rng.gen_range(0, 10)))
+                .collect::<Vec<_>>()
+                .join(",")
+        });
+        let conf = Config {
+            grammars: hashmap! {StrType::CStr => opts},
+            null_ptr_prob: 0.0,
+            ..Default::default()
+        };
+        for _ in 0..32 {
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            let opts = match &p.calls[0].args[1].val {
+                Value::Str(s) => s.clone(),
+                val => panic!("unexpected opts {:?}", val),
+            };
+            for (i, opt) in opts.split(',').enumerate() {
+                let kv = opt.split('=').collect::<Vec<_>>();
+                assert_eq!(kv[0], ((b'a' + i as u8) as char).to_string());
+                assert!(kv[1].parse::<u8>().unwrap() < 10);
+            }
+            // filename has no grammar
+            assert!(matches!(&p.calls[0].args[0].val, Value::Str(s) if !s.contains('=')));
+        }
+    }
+
+    #[test]
+    fn gen_str_charset() {
+        let conf = Config {
+            str_charset: CharSet::Ascii,
+            str_min_len: 1,
+            ..Default::default()
+        };
+        let mut s = State::new(&conf);
+        for _ in 0..256 {
+            s.reset(0);
+            if let Value::Str(val) = gen_str(&StrType::Str, &None, &mut s) {
+                assert!(val.chars().all(|c| c as u32 <= 0x7f));
+            } else {
+                panic!("str value expected");
+            }
+        }
+    }
+
+    #[test]
+    fn gen_filename_from_dict() {
+        let dict = vec!["/dev/null".to_string(), "/proc/self/status".to_string()];
+        let conf = Config {
+            filename_dictionary: dict.clone(),
+            filename_dictionary_prob: 1.0,
+            ..Default::default()
+        };
+        let mut s = State::new(&conf);
+        for _ in 0..256 {
+            if let Value::Str(val) = gen_str(&StrType::FileName, &None, &mut s) {
+                assert!(dict.iter().any(|d| **d == *val));
+            } else {
+                panic!("str value expected");
+            }
+        }
+    }
+
+    #[test]
+    fn gen_reused_cstr() {
+        let t = target(
+            r#"
+group D {
+    fn create(name *cstr)
+    fn open(name *cstr)
+}
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            str_min_len: 4,
+            str_reuse_prob: 1.0,
+            ..Default::default()
+        };
+        let p = gen_seq(&[0, 1], g.id, &t, &conf);
+        assert_eq!(p.calls[0].args[0].val, p.calls[1].args[0].val);
+
+        let conf = Config {
+            str_min_len: 4,
+            str_reuse_prob: 0.0,
+            ..Default::default()
+        };
+        let p = gen_seq(&[0, 1], g.id, &t, &conf);
+        assert_ne!(p.calls[0].args[0].val, p.calls[1].args[0].val);
+    }
+
+    #[test]
+    fn gen_null_ptr() {
+        let t = target(
+            r#"
+type fd = res<i32>
+group P {
+    fn dup2(old *fd, new *Out fd)
+}
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            null_ptr_prob: 1.0,
+            ..Default::default()
+        };
+        let p = gen_seq(&[0], g.id, &t, &conf);
+        assert_eq!(p.calls[0].args[0].val, Value::None);
+        assert_ne!(p.calls[0].args[1].val, Value::None);
+    }
+
+    #[test]
+    fn generator_matches_gen() {
+        let t = target(DESC);
+        let rs = static_analyze(&t);
+        let conf = Config {
+            seed: Some(0),
+            ..Default::default()
+        };
+        let mut g = Generator::new(&t, &rs, &conf).unwrap();
+        for _ in 0..10000 {
+            let p = g.next();
+            let conf = Config {
+                seed: p.seed,
+                ..Default::default()
+            };
+            assert_eq!(gen(&t, &rs, &conf).unwrap(), p);
+        }
+    }
+
+    #[test]
+    fn generator_for_group() {
+        let t = target(DESC);
+        let rs = static_analyze(&t);
+        let conf = Config::default();
+        let gid = *t.groups.keys().max().unwrap();
+        let progs = std::thread::scope(|scope| {
+            let handles = (0..2)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut g = Generator::for_group(&t, &rs, &conf, gid, 7).unwrap();
+                        (0..256).map(|_| g.next()).collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(progs[0], progs[1]);
+        assert!(progs[0].iter().all(|p| p.gid == gid));
+        assert!(Generator::for_group(&t, &rs, &conf, 1000, 7).is_err());
+    }
+
+    #[test]
+    fn generator_checkpoint_restore() {
+        let t = target(DESC);
+        let rs = static_analyze(&t);
+        let conf = Config {
+            seed: Some(3),
+            collect_stats: true,
+            ..Default::default()
+        };
+        let mut whole = Generator::new(&t, &rs, &conf).unwrap();
+        let progs = (0..100).map(|_| whole.next()).collect::<Vec<_>>();
+
+        let mut g = Generator::new(&t, &rs, &conf).unwrap();
+        for _ in 0..50 {
+            g.next();
+        }
+        let json = serde_json::to_string(&g.checkpoint()).unwrap();
+        let state: GeneratorState = serde_json::from_str(&json).unwrap();
+        assert_eq!(state.progs, 50);
+
+        // fresh generator with unrelated seed, as after restart
+        let other = Config {
+            seed: Some(4),
+            collect_stats: true,
+            ..Default::default()
+        };
+        let mut restored = Generator::new(&t, &rs, &other).unwrap();
+        restored.restore(state);
+        assert_eq!(restored.stats(), g.stats());
+        let rest = (0..50).map(|_| restored.next()).collect::<Vec<_>>();
+        assert_eq!(rest, progs[50..]);
+        assert_eq!(restored.checkpoint(), whole.checkpoint());
+    }
+
+    #[test]
+    fn gen_allowed_fns() {
+        let t = target(DESC);
+        let rs = static_analyze(&t);
+        let g = t.iter_group().find(|g| g.fns.len() > 2).unwrap();
+        let conf = Config {
+            allowed_fns: Some([(g.id, 1), (g.id, 2)].iter().cloned().collect()),
+            prog_min_len: 4,
+            auto_producer: true,
+            ..Default::default()
+        };
+        let allowed = [g.fns[1].id, g.fns[2].id];
+        for _ in 0..256 {
+            let p = gen_prog(g.id, &rs[&g.id], &t, &conf);
+            assert!(p.calls.iter().all(|c| allowed.contains(&c.fid)));
+        }
+        let reachable = reachable_fns(g, &t, &rs[&g.id], &conf);
+        assert!(!reachable[0]);
+
+        let conf = Config {
+            allowed_fns: Some(HashSet::new()),
+            ..Default::default()
+        };
+        assert_eq!(gen(&t, &rs, &conf), Err(GenError::NoFunctions));
+
+        // fns of groups other than the whitelisted ones are never generated
+        let t = target(&format!("{}group X {{\n    fn sync()\n}}\n", DESC));
+        let rs = static_analyze(&t);
+        let g = t.iter_group().find(|g| g.fns.len() > 2).unwrap();
+        let conf = Config {
+            allowed_fns: Some([(g.id, 0)].iter().cloned().collect()),
+            ..Default::default()
+        };
+        for _ in 0..64 {
+            assert_eq!(gen(&t, &rs, &conf).unwrap().gid, g.id);
+        }
+    }
+
+    #[test]
+    fn gen_denied_fns() {
+        let t = target(DESC);
+        let rs = static_analyze(&t);
+        let g = t.iter_group().find(|g| g.fns.len() > 2).unwrap();
+        // open is the only producer of fd, which every other fn depends on
+        let conf = Config {
+            denied_fns: [(g.id, 0)].iter().cloned().collect(),
+            prog_min_len: 4,
+            auto_producer: true,
+            ..Default::default()
+        };
+        for _ in 0..256 {
+            let p = gen_prog(g.id, &rs[&g.id], &t, &conf);
+            assert!(p.calls.iter().all(|c| c.fid != g.fns[0].id));
+            assert!(p.iter_args().all(|(_, v)| !matches!(v, Value::Ref(_))));
+        }
+
+        // fn at same index of other group is still generated
+        let t = target(&format!("{}group X {{\n    fn sync()\n}}\n", DESC));
+        let rs = static_analyze(&t);
+        let g = t.iter_group().find(|g| g.fns.len() > 2).unwrap();
+        let x = t.iter_group().find(|g| g.fns.len() == 1).unwrap();
+        let conf = Config {
+            denied_fns: [(g.id, 0)].iter().cloned().collect(),
+            ..Default::default()
+        };
+        let mut gen_x = Generator::for_group(&t, &rs, &conf, x.id, 0).unwrap();
+        assert_eq!(gen_x.next().calls[0].fid, x.fns[0].id);
+    }
+
+    #[test]
+    fn gen_repeated_calls() {
+        let t = target(DESC);
+        let g = t.iter_group().find(|g| g.fns.len() > 2).unwrap();
+        let fid = g.fns[3].id;
+        let conf = Config {
+            auto_producer: true,
+            ..Default::default()
+        };
+        let p = gen_repeat(&t, &conf, fid, 10);
+        assert_eq!(p.len(), 10);
+        assert!(p.calls.iter().all(|c| c.fid == fid));
+    }
+
+    #[test]
+    fn choose_seq_with_seed() {
+        let n = 32;
+        let mut rs = RTable::new(n);
+        for i in 0..n {
+            for j in 0..n {
+                if (i * 7 + j * 3) % 5 == 0 {
+                    rs[(i, j)] = Relation::Some;
+                }
+            }
+        }
+        let fns = (0..n)
+            .map(|i| format!("    fn f{}(a i32)", i))
+            .collect::<Vec<_>>();
+        let t = target(&format!("group D {{\n{}\n}}", fns.join("\n")));
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            prog_max_len: 256,
+            ..Default::default()
+        };
+
+        for seed in 0..16 {
+            let seq = choose_seq(g, &rs, &t, &conf, &mut StdRng::seed_from_u64(seed));
+            assert!(seq.iter().all(|&i| i < n));
+            assert_eq!(
+                seq,
+                choose_seq(g, &rs, &t, &conf, &mut StdRng::seed_from_u64(seed))
+            );
+        }
+    }
+
+    #[test]
+    fn choose_seq_max_len() {
+        let n = 16;
+        let mut rs = RTable::new(n);
+        rs.fill(Relation::Some);
+        let fns = (0..n)
+            .map(|i| format!("    fn f{}(a i32)", i))
+            .collect::<Vec<_>>();
+        let t = target(&format!("group D {{\n{}\n}}", fns.join("\n")));
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            prog_max_len: 3,
+            ..Default::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..1024 {
+            let seq = choose_seq(g, &rs, &t, &conf, &mut rng);
+            assert!(seq.len() <= conf.prog_max_len);
+        }
+    }
+
+    #[test]
+    fn choose_weighted_fn() {
+        let n = 8;
+        let rs = RTable::new(n);
+        let fns = (0..n)
+            .map(|i| format!("    fn f{}(a i32)", i))
+            .collect::<Vec<_>>();
+        let t = target(&format!("group W {{\n{}\n}}", fns.join("\n")));
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            prog_max_len: 1,
+            fn_weights: hashmap! {(g.id, 3) => 10.0},
+            ..Default::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut count = vec![0; n];
+        for _ in 0..4096 {
+            for i in choose_seq(g, &rs, &t, &conf, &mut rng) {
+                count[i] += 1;
+            }
+        }
+        for (i, c) in count.iter().enumerate() {
+            if i != 3 {
+                assert!(count[3] > c * 5);
+            }
+        }
+    }
+
+    #[test]
+    fn gen_with_zero_weights() {
+        let t = target("fn f0(a i32)\nfn f1(a i32)");
+        let rs = static_analyze(&t);
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            fn_weights: hashmap! {(g.id, 0) => 0.0, (g.id, 1) => 0.0},
+            ..Default::default()
+        };
+        assert_eq!(gen(&t, &rs, &conf), Err(GenError::NoFunctions));
+        assert!(Generator::new(&t, &rs, &conf).is_err());
+        assert!(Generator::for_group(&t, &rs, &conf, g.id, 0).is_err());
+
+        let conf = Config {
+            fn_weights: hashmap! {(g.id, 0) => 0.0},
+            ..Default::default()
+        };
+        for _ in 0..64 {
+            assert!(gen(&t, &rs, &conf).is_ok());
+        }
+    }
+
+    #[test]
+    fn gen_fixed_array() {
+        let t = target("fn digest(out *[u8;16])");
+        let g = t.iter_group().next().unwrap();
+        let tid = g.fns[0].params.as_ref().unwrap()[0].tid;
+        match t.type_of(tid) {
+            TypeInfo::Ptr { tid, .. } => match t.type_of(*tid) {
+                TypeInfo::Slice { l, h, .. } => assert_eq!((*l, *h), (16, 16)),
+                info => panic!("unexpected type: {}", info),
+            },
+            info => panic!("unexpected type: {}", info),
+        }
+        for seed in 0..64 {
+            let conf = Config {
+                seed: Some(seed),
+                null_ptr_prob: 0.0,
+                ..Default::default()
+            };
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            assert_eq!(p.calls[0].args[0].val.len(), Some(16));
+        }
+    }
+
+    #[test]
+    fn gen_target_len() {
+        let t = target(DESC);
+        let rs = static_analyze(&t);
+        for seed in 0..64 {
+            let conf = Config {
+                seed: Some(seed),
+                target_len: Some(5),
+                ..Default::default()
+            };
+            assert_eq!(gen(&t, &rs, &conf).unwrap().len(), 5);
+        }
+        let conf = Config {
+            target_len: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            gen(&t, &rs, &conf),
+            Err(GenError::InvalidConfig(ConfigError::ZeroTargetLen))
+        );
+    }
+
+    #[test]
+    fn gen_without_res_reuse() {
+        let t = target(DESC);
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            seed: Some(0),
+            reuse_resource_prob: 0.0,
+            ..Default::default()
+        };
+        let p = gen_seq(&[0, 1, 2, 3], g.id, &t, &conf);
+        for c in p.calls.iter() {
+            for arg in c.args.iter() {
+                assert!(!matches!(arg.val, Value::Ref(_)));
+            }
+        }
+        let conf = Config {
+            seed: Some(0),
+            ..Default::default()
+        };
+        let p = gen_seq(&[0, 1, 2, 3], g.id, &t, &conf);
+        assert_eq!(p.calls[3].args[0].val, Value::Ref((0, ArgPos::Ret)));
+    }
+
+    #[test]
+    fn gen_without_fns() {
+        let t = target(DESC);
+        let rs: HashMap<GroupId, RTable> = HashMap::new();
+        assert_eq!(gen(&t, &rs, &Config::default()), Err(GenError::NoFunctions));
+        assert!(Generator::new(&t, &rs, &Config::default()).is_err());
+    }
+
+    #[test]
+    fn reachable_without_producer() {
+        let t = target(
+            r#"
+type fd = res<i32>
+type pid = res<i32>
+group R {
+    fn open(path *In cstr) fd
+    fn read(f fd, buf *Out [i8])
+    fn kill(p pid, sig i32)
+    fn getuid() u32
+}
+"#,
+        );
+        let rs = static_analyze(&t);
+        let g = t.iter_group().next().unwrap();
+        let reachable = reachable_fns(g, &t, &rs[&g.id], &Config::default());
+        assert_eq!(reachable, vec![true, true, false, true]);
+
+        let conf = Config {
+            fn_weights: hashmap! {(g.id, 0) => 0.0},
+            ..Default::default()
+        };
+        let reachable = reachable_fns(g, &t, &rs[&g.id], &conf);
+        assert_eq!(reachable, vec![false, false, false, true]);
+    }
+
+    #[test]
+    fn gen_out_buf_capacity() {
+        let t = target(
+            r#"
+fn getcwd(buf *Out str, size len<usize, buf>)
+fn getgroups(size len<i32, list>, list *Out [u32;(4, 8)])
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            out_buf_len: 16,
+            ..Default::default()
+        };
+        let p = gen_seq(&[0, 1], g.id, &t, &conf);
+        assert_eq!(p.calls[0].args[0].val.len(), Some(16));
+        assert_eq!(p.calls[0].args[1].val, Value::Num(NumValue::Unsigned(16)));
+        assert_eq!(p.calls[1].args[1].val.len(), Some(8));
+        assert_eq!(p.calls[1].args[0].val, Value::Num(NumValue::Unsigned(8)));
+    }
+
+    #[test]
+    fn gen_malformed() {
+        let t = target(
+            r#"
+flag mode {A=1,B=2}
+fn set(a u32{(10, 20)}, m mode, b *[i8;(2, 6)])
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            malformed_prob: 1.0,
+            null_ptr_prob: 0.0,
+            ..Default::default()
+        };
+        for _ in 0..64 {
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            let args = &p.calls[0].args;
+            assert!(args.iter().all(|a| a.malformed));
+            match args[0].val {
+                Value::Num(NumValue::Unsigned(v)) => assert!(!(10..20).contains(&v), "{}", v),
+                ref v => panic!("unexpected value: {:?}", v),
+            }
+            match args[1].val {
+                Value::Num(NumValue::Signed(v)) => assert_ne!(v & !3, 0),
+                ref v => panic!("unexpected value: {:?}", v),
+            }
+            assert!(args[2].val.len().unwrap() > 6);
+        }
+
+        let p = gen_seq(&[0], g.id, &t, &Config::default());
+        assert!(p.calls[0].args.iter().all(|a| !a.malformed));
+    }
+
+    #[test]
+    fn gen_struct_with_len() {
+        let t = target(
+            r#"
+struct pkt { size len<u32, data>, data [u8] }
+fn send(p pkt)
+"#,
+        );
+        let tid = t.iter_group().next().unwrap().fns[0]
+            .params
+            .as_ref()
+            .unwrap()[0]
+            .tid;
+        let conf = Config::default();
+        let mut s = State::new(&conf);
+        for _ in 0..16 {
+            match gen_value(tid, &t, &mut s) {
+                Value::Group(vals) => {
+                    let l = vals[1].len().unwrap() as u64;
+                    assert_eq!(vals[0], Value::Num(NumValue::Unsigned(l)));
+                }
+                v => panic!("unexpected value: {:?}", v),
+            }
+        }
+    }
+
+    #[test]
+    fn gen_value_budget() {
+        let t = target(
+            r#"
+struct node { id u32, extra? [u64], body [u8;(0, 1000)] }
+fn big(n *[node;(0, 1000)], data *[u64;(0, 100000)])
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            max_values: 64,
+            ..Default::default()
+        };
+        for _ in 0..16 {
+            let p = gen_seq(&[0, 0, 0], g.id, &t, &conf);
+            assert_eq!(p.len(), 1);
+            // required fields of value under way and remaining args are still generated
+            let count = p.iter_args().count();
+            assert!(count <= conf.max_values + 8, "{} values", count);
+        }
+    }
+
+    #[test]
+    fn fill_len_kinds() {
+        let t = target(
+            r#"
+struct hdr { kind u8, flags u32 }
+struct rec { size len<u16, body, with_self>, body [u8;(1, 8)] }
+fn write(vals *[u32;(1, 8)], n len<u32, vals>, sz len<u32, vals, bytes>)
+fn send(h *hdr, n len<u64, h, bytes>)
+fn put(r rec)
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            null_ptr_prob: 0.0,
+            ..Default::default()
+        };
+        let num = |v: &Value| match v {
+            Value::Num(NumValue::Unsigned(n)) => *n as usize,
+            v => panic!("unexpected value: {:?}", v),
+        };
+        for _ in 0..16 {
+            let p = gen_seq(&[0, 1, 2], g.id, &t, &conf);
+            let args = &p.calls[0].args;
+            let count = args[0].val.len().unwrap();
+            assert_eq!(num(&args[1].val), count);
+            assert_eq!(num(&args[2].val), count * 4);
+
+            assert_eq!(num(&p.calls[1].args[1].val), 8);
+
+            match &p.calls[2].args[0].val {
+                Value::Group(vals) => assert_eq!(num(&vals[0]), vals[1].len().unwrap() + 2),
+                v => panic!("unexpected value: {:?}", v),
+            }
+        }
+    }
+
+    #[test]
+    fn gen_observed() {
+        #[derive(Default)]
+        struct Counter {
+            calls: HashMap<FnId, usize>,
+            created: usize,
+            reused: usize,
+        }
+
+        impl GenObserver for Counter {
+            fn on_call(&mut self, fid: FnId) {
+                *self.calls.entry(fid).or_default() += 1;
+            }
+            fn on_resource_created(&mut self, _tid: TypeId) {
+                self.created += 1;
+            }
+            fn on_resource_reused(&mut self, _tid: TypeId) {
+                self.reused += 1;
+            }
+        }
+
+        let t = target(DESC);
+        let rs = static_analyze(&t);
+        let conf = Config::default();
+        for _ in 0..64 {
+            let mut counter = Counter::default();
+            let p = gen_with_observer(&t, &rs, &conf, &mut counter).unwrap();
+            let mut calls = HashMap::new();
+            for c in p.calls.iter() {
+                *calls.entry(c.fid).or_default() += 1;
+            }
+            assert_eq!(counter.calls, calls);
+            let refs = p
+                .iter_args()
+                .filter(|(_, v)| matches!(v, Value::Ref(_)))
+                .count();
+            assert_eq!(counter.reused, refs);
+        }
+
+        let mut counter = Counter::default();
+        let mut g = Generator::new(&t, &rs, &conf).unwrap();
+        g.set_observer(&mut counter);
+        let total = (0..16).map(|_| g.next().len()).sum::<usize>();
+        drop(g);
+        assert_eq!(counter.calls.values().sum::<usize>(), total);
+        // discarded attempts of retried calls are not reported
+        let t = target(
+            r#"
+type fd = res<i32>
+group V {
+    fn open(flags u32{(0, 16)}, mode u32{(0, 16)}) fd
+    fn read(f fd, n u32{(0, 16)})
+}
+"#,
+        );
+        let rs = static_analyze(&t);
+        let g = t.iter_group().next().unwrap();
+        let same = |c: &Call| c.args[0].val == c.args[1].val;
+        let conf = Config {
+            validators: hashmap! {g.fns[0].id => Validator::new(same)},
+            max_arg_retries: 256,
+            auto_producer: true,
+            collect_stats: true,
+            ..Default::default()
+        };
+        let mut counter = Counter::default();
+        let mut gen = Generator::new(&t, &rs, &conf).unwrap();
+        gen.set_observer(&mut counter);
+        let progs = (0..16).map(|_| gen.next()).collect::<Vec<_>>();
+        let stats = gen.stats().unwrap().clone();
+        drop(gen);
+        assert_eq!(counter.calls, stats.fn_counts);
+        assert_eq!(counter.reused, stats.res_reused);
+        assert_eq!(counter.created, stats.res_created);
+        let total = progs.iter().map(Prog::len).sum::<usize>();
+        assert_eq!(counter.calls.values().sum::<usize>(), total);
+    }
+
+    #[test]
+    fn gen_no_ref_to_closed() {
+        let t = target(
+            r#"
+type fd = res<i32>
+group C {
+    fn open(name *filename) fd
+    #[close]
+    fn close(f fd)
+    fn read(f fd, buf *Out [i8], count len<i32, buf>)
+}
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config::default();
+        for _ in 0..64 {
+            let p = gen_seq(&[0, 1, 2], g.id, &t, &conf);
+            assert_eq!(p.calls[1].args[0].val, Value::Ref((0, ArgPos::Ret)));
+            assert!(!matches!(p.calls[2].args[0].val, Value::Ref(_)));
+
+            let p = gen_seq(&[0, 0, 1, 2], g.id, &t, &conf);
+            let closed = &p.calls[2].args[0].val;
+            assert!(matches!(p.calls[3].args[0].val, Value::Ref(_)));
+            assert_ne!(&p.calls[3].args[0].val, closed);
+        }
+    }
+
+    #[test]
+    fn gen_ptr_array() {
+        let t = target(
+            r#"
+type fd = res<i32>
+group E {
+    fn execve(path *filename, argv *[*cstr;(1, 4)])
+    fn pipes(fds *[*Out fd;(2, 3)]) fd
+    fn close(f fd)
+}
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            null_ptr_prob: 0.0,
+            str_min_len: 1,
+            ..Default::default()
+        };
+        for _ in 0..64 {
+            let p = gen_seq(&[0, 1, 2], g.id, &t, &conf);
+            match &p.calls[0].args[1].val {
+                Value::Group(argv) => {
+                    assert!((1..4).contains(&argv.len()));
+                    assert!(argv
+                        .iter()
+                        .all(|v| matches!(v, Value::Str(s) if !s.is_empty())));
+                }
+                v => panic!("unexpected value: {:?}", v),
+            }
+            match &p.calls[1].args[0].val {
+                Value::Group(fds) => {
+                    assert!(fds.iter().all(|v| *v == Value::Num(NumValue::Unsigned(0))))
+                }
+                v => panic!("unexpected value: {:?}", v),
+            }
+            let mut res = Vec::new();
+            arg_out_res(&p.calls[1].args[0], &t, &mut res);
+            assert_eq!(res.len(), p.calls[1].args[0].val.len().unwrap());
+            assert!(matches!(p.calls[2].args[0].val, Value::Ref((1, _))));
+        }
+        // resources of array share position of arg
+        let mut s = State::new(&conf);
+        s.reset(g.id);
+        gen_call(&t, &g.fns[1], &mut s);
+        let fd = g.fns[1].r_tid.unwrap();
+        assert_eq!(s.res[&fd], vec![(0, ArgPos::Arg(0)), (0, ArgPos::Ret)]);
+    }
+
+    #[test]
+    fn gen_required_flag() {
+        let t = target(
+            r#"
+#[required(O_RDONLY, O_WRONLY, O_RDWR)]
+flag open_flags{O_RDONLY=0,O_WRONLY=1,O_RDWR=2,O_APPEND=1024,O_CREAT=64,O_EXCL=128}
+fn open(f *filename, flags open_flags)
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config::default();
+        let mut extras = HashSet::new();
+        for _ in 0..1024 {
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            match p.calls[0].args[1].val {
+                Value::Num(NumValue::Signed(v)) => {
+                    assert!([0, 1, 2].contains(&(v & 0b11)));
+                    assert_eq!(v & !0b11 & !(1024 | 64 | 128), 0);
+                    extras.insert(v & !0b11);
+                }
+                ref v => panic!("unexpected value: {:?}", v),
+            }
+        }
+        assert!(extras.len() > 1);
+    }
+
+    #[test]
+    fn extend_prog() {
+        let t = target(
+            r#"
+type fd = res<i32>
+group C {
+    fn open(name *filename) fd
+    #[close]
+    fn close(f fd)
+    fn read(f fd, buf *Out [i8], count len<i32, buf>)
+}
+"#,
+        );
+        let rs = static_analyze(&t);
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            denied_fns: [(g.id, 0)].iter().cloned().collect(),
+            ..Default::default()
+        };
+        for _ in 0..64 {
+            let mut p = gen_seq(&[0, 0, 1], g.id, &t, &Config::default());
+            let closed = p.calls[2].args[0].val.clone();
+            let prefix = p.clone();
+            extend(&mut p, &t, &rs[&g.id], &conf);
+            assert_eq!(p.len(), 4);
+            assert_eq!(p.calls[..3], prefix.calls[..]);
+            let c = &p.calls[3];
+            assert_ne!(c.fid, g.fns[0].id);
+            match &c.args[0].val {
+                Value::Ref((cid, ArgPos::Ret)) => assert!(*cid < 2),
+                v => panic!("unexpected value: {:?}", v),
+            }
+            assert_ne!(c.args[0].val, closed);
+        }
+    }
+
+    #[test]
+    fn gen_slice_max_elems() {
+        let t = target(
+            r#"
+struct node { id u32, kids [u16;(0, 1000)] }
+fn big(n *[node;(100, 1000)], out *Out [u8], fixed [u8;64])
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            slice_max_elems: 3,
+            malformed_prob: 0.1,
+            ..Default::default()
+        };
+        for _ in 0..64 {
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            let lens = p
+                .iter_args()
+                .filter_map(|(_, v)| match v {
+                    Value::Group(vals) => Some(vals.len()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert!(!lens.is_empty());
+            assert!(lens.iter().all(|l| *l <= 3), "{:?}", lens);
+            // slices cut below their lower bound are malformed
+            let args = &p.calls[0].args;
+            assert!(args[0].val == Value::None || args[0].malformed);
+            assert!(args[2].malformed);
+        }
+        let conf = Config {
+            slice_max_elems: 64,
+            ..Default::default()
+        };
+        let p = gen_seq(&[0], g.id, &t, &conf);
+        assert!(!p.calls[0].args[2].malformed);
+    }
+
+    #[test]
+    fn call_will_likely_fail() {
+        let t = target(
+            r#"
+type fd = res<i32>
+fn open(name *filename, mode u32{(1, 8)}) fd
+fn stat(f fd, buf *Out [i8], n len<u32, buf>)
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let open = &g.fns[0];
+        let params = open.params.as_ref().unwrap();
+        let call = |name: Value, mode: u64| {
+            let mut c = Call::new(open.id);
+            c.add_arg(Arg::new(params[0].tid)).val = name;
+            c.add_arg(Arg::new(params[1].tid)).val = Value::Num(NumValue::Unsigned(mode));
+            c
+        };
+        let name = Value::Str("a".into());
+        assert!(!will_likely_fail(&call(name.clone(), 2), &t));
+        assert!(will_likely_fail(&call(name, 9), &t));
+        assert!(will_likely_fail(&call(Value::None, 2), &t));
+
+        // null In pointers are regenerated, out pointers are never null
+        let mut conf = Config {
+            null_ptr_prob: 0.5,
+            ..Default::default()
+        };
+        let null_opens = |conf: &Config| {
+            (0..64)
+                .map(|_| gen_seq(&[0, 1, 0], g.id, &t, conf))
+                .flat_map(|p| p.calls)
+                .filter(|c| will_likely_fail(c, &t))
+                .count()
+        };
+        assert!(null_opens(&conf) > 0);
+        conf.fail_retries = 32;
+        assert_eq!(null_opens(&conf), 0);
+    }
+
+    #[test]
+    fn gen_with_validators() {
+        let t = target(
+            r#"
+fn pair(a u32{(0, 16)}, b u32{(0, 16)})
+fn other(a u32{(0, 16)}, b u32{(0, 16)})
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let same = |c: &Call| c.args[0].val == c.args[1].val;
+        let conf = Config {
+            validators: hashmap! {g.fns[0].id => Validator::new(same)},
+            max_arg_retries: 256,
+            ..Default::default()
+        };
+        let mut others = 0;
+        for _ in 0..32 {
+            let p = gen_seq(&[0, 1], g.id, &t, &conf);
+            assert!(same(&p.calls[0]));
+            others += same(&p.calls[1]) as usize;
+        }
+        // validator only applies to its fn
+        assert!(others < 32);
+
+        // inserted producers and appended calls are checked too
+        let t = target(
+            r#"
+type fd = res<i32>
+group V {
+    fn open(flags u32{(0, 16)}, mode u32{(0, 16)}) fd
+    fn read(f fd)
+}
+"#,
+        );
+        let rs = static_analyze(&t);
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            validators: hashmap! {g.fns[0].id => Validator::new(same)},
+            max_arg_retries: 256,
+            auto_producer: true,
+            ..Default::default()
+        };
+        for _ in 0..32 {
+            let mut p = gen_seq(&[1], g.id, &t, &conf);
+            assert_eq!(p.calls[0].fid, g.fns[0].id);
+            assert!(same(&p.calls[0]));
+            let c = gen_call_at(&p, 0, &g.fns[0], &t, &conf);
+            assert!(same(&c));
+            let p_len = p.len();
+            extend(&mut p, &t, &rs[&g.id], &conf);
+            assert!(p.calls[p_len..]
+                .iter()
+                .all(|c| c.fid != g.fns[0].id || same(c)));
+            let p = gen_resource_chain(g, &t, &rs[&g.id], 0, &conf);
+            assert!(same(&p.calls[0]));
+        }
+    }
+
+    #[test]
+    fn gen_max_resources() {
+        let t = target(DESC);
+        let g = t.iter_group().next().unwrap();
+        let refs = |p: &Prog| {
+            p.iter_args()
+                .filter_map(|(_, v)| match v {
+                    Value::Ref(idx) => Some(idx.clone()),
+                    _ => None,
+                })
+                .collect::<HashSet<_>>()
+        };
+        let seq = [0, 0, 1, 0, 2, 1, 2, 3];
+        let mut distinct = 0;
+        for seed in 0..32 {
+            let mut conf = Config {
+                seed: Some(seed),
+                reuse_resource_prob: 0.5,
+                auto_producer: true,
+                ..Default::default()
+            };
+            distinct = distinct.max(refs(&gen_seq(&seq, g.id, &t, &conf)).len());
+
+            conf.max_resources = Some(1);
+            let p = gen_seq(&seq, g.id, &t, &conf);
+            assert_eq!(refs(&p), hashset! {(0, ArgPos::Ret)});
+        }
+        assert!(distinct > 1);
+    }
+
+    #[test]
+    fn gen_seed_resources() {
+        use crate::minimize::minimize_args;
+
+        let t = target(
+            r#"
+type fd = res<i32>
+fn write(f fd, buf *[i8], count len<i32, buf>)
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let fd = g.fns[0].params.as_ref().unwrap()[0].tid;
+        let seeds = (0..3)
+            .map(|v| Value::Num(NumValue::Signed(v)))
+            .collect::<Vec<_>>();
+        let conf = Config {
+            seed_resources: hashmap! {fd => seeds.clone()},
+            ..Default::default()
+        };
+        let mut used = HashSet::new();
+        for _ in 0..64 {
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            let arg = &p.calls[0].args[0];
+            assert!(seeds.contains(&arg.val));
+            assert!(arg.seeded && !p.calls[0].args[1].seeded);
+            used.insert(arg.val.clone());
+
+            let m = minimize_args(&p, &t, |_| true);
+            assert_eq!(m.calls[0].args[0], *arg);
+        }
+        assert_eq!(used.len(), 3);
+    }
+
+    #[test]
+    fn gen_stats() {
+        let t = target(DESC);
+        let rs = static_analyze(&t);
+        let conf = Config {
+            seed: Some(7),
+            collect_stats: true,
+            ..Default::default()
+        };
+        let mut g = Generator::new(&t, &rs, &conf).unwrap();
+        let total = (0..32).map(|_| g.next().len()).sum::<usize>();
+        let stats = g.stats().unwrap();
+        assert_eq!(stats.fn_counts.values().sum::<usize>(), total);
+        assert!(stats.max_depth > 0);
+
+        let conf = Config::default();
+        let g = Generator::new(&t, &rs, &conf).unwrap();
+        assert!(g.stats().is_none());
+    }
+
+    #[test]
+    fn fill_len_of_multibyte_str() {
+        let t = target("fn write(buf *In str, size len<usize, buf>)");
+        let f = &t.iter_group().next().unwrap().fns[0];
+        let params = f.params.as_ref().unwrap();
+        let mut c = Call::new(f.id);
+        let s = "héllo, 世界";
+        c.args.push(Arg::new(params[0].tid));
+        c.args[0].val = Value::Str(s.into());
+        c.args.push(Arg::new(params[1].tid));
+        fill_lens(&mut c, &t);
+        assert_eq!(c.args[0].val.byte_len(), Some(s.len()));
+        assert_ne!(s.len(), s.chars().count());
+        assert_eq!(
+            c.args[1].val,
+            Value::Num(NumValue::Unsigned(s.len() as u64))
+        );
+    }
+
+    #[test]
+    fn gen_chain_of_resource() {
+        let t = target(
+            r#"
+type fd = res<i32>
+group F {
+    fn open(name *filename) fd
+    fn read(f fd, buf *Out [i8])
+    fn dup(f fd) fd
+    fn getpid() i32
+}
+"#,
+        );
+        let rs = static_analyze(&t);
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            resource_chain_len: 16,
+            ..Default::default()
+        };
+        let p = gen_resource_chain(g, &t, &rs[&g.id], 0, &conf);
+        assert_eq!(p.len(), 17);
+        assert_eq!(p.calls[0].fid, g.fns[0].id);
+        for c in p.calls[1..].iter() {
+            assert!(c.fid == g.fns[1].id || c.fid == g.fns[2].id);
+            assert_eq!(c.args[0].val, Value::Ref((0, ArgPos::Ret)));
+        }
+
+        let p = gen_resource_chain(g, &t, &rs[&g.id], 3, &conf);
+        assert_eq!(p.len(), 1);
+    }
+
+    #[test]
+    fn gen_with_continue_prob() {
+        let t = target(DESC);
+        let rs = static_analyze(&t);
+        let avg_len = |p: f64| {
+            let conf = Config {
+                seed: Some(1),
+                continue_prob: Some(p),
+                ..Default::default()
+            };
+            let mut g = Generator::new(&t, &rs, &conf).unwrap();
+            (0..512).map(|_| g.next().len()).sum::<usize>() as f64 / 512.0
+        };
+        assert!(avg_len(0.9) > avg_len(0.1) + 1.0);
+
+        for p in [0.0, 1.0, -0.5].iter() {
+            let conf = Config {
+                continue_prob: Some(*p),
+                ..Default::default()
+            };
+            assert!(matches!(
+                gen(&t, &rs, &conf),
+                Err(GenError::InvalidConfig(ConfigError::ContinueProb(_)))
+            ));
+        }
+    }
+
+    #[test]
+    fn gen_with_auto_producer() {
+        let t = target(
+            r#"
+type fd = res<i32>
+group A {
+    fn read(f fd, buf *Out [i8])
+    fn dup(f fd) fd
+    fn open(name *filename, flags u32) fd
+    fn creat(name *filename) fd
+}
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            auto_producer: true,
+            ..Default::default()
+        };
+        let p = gen_seq(&[0], g.id, &t, &conf);
+        assert_eq!(p.len(), 2);
+        assert_eq!(p.calls[0].fid, g.fns[3].id);
+        assert_eq!(p.calls[1].fid, g.fns[0].id);
+        assert_eq!(p.calls[1].args[0].val, Value::Ref((0, ArgPos::Ret)));
+
+        let p = gen_seq(&[0], g.id, &t, &Config::default());
+        assert_eq!(p.len(), 1);
+    }
+
+    #[test]
+    fn gen_with_producer_of_null_out_ptr() {
+        let t = target(
+            r#"
+type fd = res<i32>
+group A {
+    fn read(f fd)
+    fn pipe(fds *Out fd)
+}
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let ptr = g.fns[1].params.as_ref().unwrap()[0].tid;
+        for hint in [GenHint::NullProb(1.0), GenHint::Default].iter() {
+            let conf = Config {
+                auto_producer: true,
+                type_overrides: hashmap! { ptr => hint.clone() },
+                ..Default::default()
+            };
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            assert_eq!(p.len(), 2);
+            assert_eq!(p.calls[0].fid, g.fns[1].id);
+            assert_eq!(p.calls[0].args[0].val, Value::None);
+            assert!(!matches!(p.calls[1].args[0].val, Value::Ref(_)));
+        }
+    }
+
+    #[test]
+    fn checked_config() {
+        assert!(Config::new(1, 16, 0, 32, 4).is_ok());
+        assert_eq!(
+            Config::new(1, 0, 0, 32, 4).unwrap_err(),
+            ConfigError::ZeroProgMaxLen
+        );
+        assert_eq!(
+            Config::new(8, 4, 0, 32, 4).unwrap_err(),
+            ConfigError::ProgLen { min: 8, max: 4 }
+        );
+        assert_eq!(
+            Config::new(1, 16, 32, 32, 4).unwrap_err(),
+            ConfigError::StrLen { min: 32, max: 32 }
+        );
+        assert_eq!(
+            Config::new(1, 16, 0, 32, 0).unwrap_err(),
+            ConfigError::ZeroPathDepth
+        );
+        let conf = Config {
+            target_len: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(conf.validate(), Err(ConfigError::ZeroTargetLen));
+        for w in [-1.0, f64::INFINITY].iter() {
+            let conf = Config {
+                fn_weights: hashmap! {(0, 1) => *w},
+                ..Default::default()
+            };
+            assert_eq!(
+                conf.validate(),
+                Err(ConfigError::FnWeight {
+                    gid: 0,
+                    i: 1,
+                    w: *w
+                })
+            );
+        }
+        let conf = Config {
+            fn_weights: hashmap! {(0, 1) => f64::NAN},
+            ..Default::default()
+        };
+        assert!(matches!(
+            conf.validate(),
+            Err(ConfigError::FnWeight { gid: 0, i: 1, .. })
+        ));
+        for align in [0, 3, 24].iter() {
+            let conf = Config {
+                default_ptr_align: Some(*align),
+                ..Default::default()
+            };
+            assert_eq!(conf.validate(), Err(ConfigError::PtrAlign(*align)));
+            let conf = Config {
+                ptr_aligns: hashmap! {0 => *align},
+                ..Default::default()
+            };
+            assert_eq!(conf.validate(), Err(ConfigError::PtrAlign(*align)));
+        }
+        let conf = Config {
+            ptr_aligns: hashmap! {0 => 4096},
+            default_ptr_align: Some(1),
+            ..Default::default()
+        };
+        assert!(conf.validate().is_ok());
+    }
+
+    #[test]
+    fn gen_with_pinned_param() {
+        let t = target(
+            r#"
+type fd = res<i32>
+group O {
+    fn open(name *filename, flags u32{(0, 8)}) fd
+    fn close(f fd)
+}
+"#,
+        );
+        let rs = static_analyze(&t);
+        let open = t.iter_group().next().unwrap().fns[0].id;
+        let path = Value::Str("/dev/null".into());
+        let fixtures = hashmap! {(open, 0) => path.clone()};
+        let conf = Config {
+            prog_min_len: 4,
+            ..Default::default()
+        };
+        for _ in 0..16 {
+            let p = gen_with_fixtures(&t, &rs, &conf, &fixtures).unwrap();
+            for c in p.calls.iter().filter(|c| c.fid == open) {
+                assert_eq!(c.args[0].val, path);
+            }
+        }
+
+        let bad = [
+            hashmap! {(open, 1) => Value::Num(NumValue::Unsigned(8))},
+            hashmap! {(open, 1) => path.clone()},
+            hashmap! {(open, 2) => path},
+        ];
+        for fixtures in bad.iter() {
+            assert!(matches!(
+                gen_with_fixtures(&t, &rs, &conf, fixtures),
+                Err(GenError::InvalidFixture { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn gen_from_bytes() {
+        let t = target(DESC);
+        let rs = static_analyze(&t);
+        let conf = Config::default();
+        let data = b"\x00\x01healer\xff";
+        let p = prog_from_bytes(data, &t, &rs, &conf).unwrap();
+        for _ in 0..8 {
+            let q = prog_from_bytes(data, &t, &rs, &conf).unwrap();
+            assert_eq!(q, p);
+            assert_eq!(q.seed, p.seed);
+        }
+        let q = prog_from_bytes(b"healer", &t, &rs, &conf).unwrap();
+        assert_ne!(q.seed, p.seed);
+    }
+
+    #[test]
+    fn gen_with_unknown_type() {
+        let mut t = target("fn getuid() u32\nfn close(f i32)");
+        let rs = static_analyze(&t);
+        let tid = t.iter_group().next().unwrap().fns[1]
+            .params
+            .as_ref()
+            .unwrap()[0]
+            .tid;
+        t.types.remove(&tid);
+        let conf = Config::default();
+        // only fns of chosen seq are checked, getuid alone is fine
+        let getuid = t.iter_group().next().unwrap().fns[0].id;
+        let mut errs = 0;
+        for _ in 0..64 {
+            match gen(&t, &rs, &conf) {
+                Ok(p) => assert!(p.calls.iter().all(|c| c.fid == getuid)),
+                Err(e) => {
+                    assert_eq!(e, GenError::UnknownType(tid));
+                    errs += 1;
+                }
+            }
+        }
+        assert!(errs > 0);
+        assert!(Generator::new(&t, &rs, &conf).is_err());
+    }
+
+    #[test]
+    fn gen_with_type_override() {
+        let t = target("fn mmap(addr *i8, size u64, prot i32)");
+        let g = t.iter_group().next().unwrap();
+        let params = g.fns[0].params.as_ref().unwrap();
+        let conf = Config {
+            type_overrides: hashmap! {
+                params[0].tid => GenHint::NullProb(1.0),
+                params[1].tid => GenHint::Const(NumValue::Unsigned(4096)),
+                params[2].tid => GenHint::Default,
+            },
+            ..Default::default()
+        };
+        for _ in 0..64 {
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            let args = &p.calls[0].args;
+            assert_eq!(args[0].val, Value::None);
+            assert_eq!(args[1].val, Value::Num(NumValue::Unsigned(4096)));
+            assert_eq!(args[2].val, Value::Num(NumValue::Unsigned(0)));
+        }
+    }
+
+    #[test]
+    fn gen_cstr_without_nul() {
+        let t = target("fn unlink(path *cstr)");
+        let g = t.iter_group().next().unwrap();
+        let tid = match t.type_of(g.fns[0].params.as_ref().unwrap()[0].tid) {
+            TypeInfo::Ptr { tid, .. } => *tid,
+            info => panic!("unexpected type: {}", info),
+        };
+        let conf = Config {
+            str_charset: CharSet::Ascii,
+            str_min_len: 16,
+            str_max_len: 64,
+            null_ptr_prob: 0.0,
+            ..Default::default()
+        };
+        for _ in 0..256 {
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            let val = &p.calls[0].args[0].val;
+            if let Value::Str(s) = val {
+                assert!(!s.contains('\0'));
+            } else {
+                panic!("str value expected");
+            }
+
+            let mut out = Vec::new();
+            crate::encode::encode_value(val, tid, &t, &mut out);
+            assert_eq!(out.iter().filter(|b| **b == 0).count(), 1);
+            assert_eq!(out.last(), Some(&0));
+        }
+    }
+
+    #[test]
+    fn gen_weighted_union() {
+        let t = target(
+            r#"
+union addr { v4 u32, v6 u64 }
+fn bind(a addr)
+"#,
+        );
+        let g = t.iter_group().next().unwrap();
+        let tid = g.fns[0].params.as_ref().unwrap()[0].tid;
+        let conf = Config {
+            union_weights: hashmap! {tid => vec![9.0, 1.0]},
+            ..Default::default()
+        };
+        let mut count = [0; 2];
+        for _ in 0..4096 {
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            match &p.calls[0].args[0].val {
+                Value::Opt { choice, .. } => count[*choice] += 1,
+                v => panic!("unexpected value: {:?}", v),
+            }
+        }
+        let ratio = count[0] as f64 / count[1] as f64;
+        assert!(ratio > 6.0 && ratio < 13.0, "{:?}", count);
+    }
+}
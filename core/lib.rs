@@ -7,9 +7,14 @@ extern crate lazy_static;
 
 pub mod analyze;
 pub mod c;
+pub mod corpus;
+pub mod encode;
+pub mod exec;
 pub mod gen;
 pub mod minimize;
 pub mod mutate;
 pub mod prog;
+pub mod syz;
 pub mod target;
 pub mod value;
+pub mod wire;
@@ -1,16 +1,25 @@
+use fots::types::{TypeId, TypeInfo};
+use rand::thread_rng;
+
+use crate::gen::fill_lens;
 use crate::prog::{ArgIndex, Call, Prog};
-use crate::value::Value;
+use crate::target::Target;
+use crate::value::{NumValue, Value};
 
-pub fn minimize<F>(p: &Prog, mut eq: F) -> Prog
+/// Greedily remove calls of p, a removal is kept only if still_repros returns true.
+///
+/// Calls that depend on the removed call are removed too and refs of rest calls are
+/// adjusted, last call of p is always kept.
+pub fn minimize_calls<F>(p: &Prog, mut still_repros: F) -> Prog
 where
     F: FnMut(&Prog) -> bool,
 {
     let mut p = p.clone();
     let mut p_orig;
     let mut i = 0;
-    while i != p.len() - 1 {
+    while i + 1 < p.len() {
         p_orig = p.clone();
-        if !remove(&mut p, i) || !eq(&p) {
+        if !remove(&mut p, i) || !still_repros(&p) {
             i += 1;
             p = p_orig;
         }
@@ -18,6 +27,119 @@ where
     p
 }
 
+/// Simplify args of p toward default values, a simplification is kept only if
+/// still_repros returns true.
+///
+/// Numbers are replaced with 0, strings are cleared, slices are truncated to their
+/// lower bound and unions are collapsed to their first choice. Values nested in
+/// pointers, structs, slices and unions are simplified too, refs and const nums
+/// are kept as is.
+pub fn minimize_args<F>(p: &Prog, t: &Target, mut still_repros: F) -> Prog
+where
+    F: FnMut(&Prog) -> bool,
+{
+    let mut p = p.clone();
+    for i in 0..p.len() {
+        for j in 0..p.calls[i].args.len() {
+            // malformed value is kept as is, simplifying it makes it valid
+            // seeded handle has no producer to fall back to
+            if p.calls[i].args[j].malformed || p.calls[i].args[j].seeded {
+                continue;
+            }
+            let tid = p.calls[i].args[j].tid;
+            shrink_val(&mut p, (i, j), tid, &mut Vec::new(), t, &mut still_repros);
+        }
+    }
+    p
+}
+
+fn shrink_val<F>(
+    p: &mut Prog,
+    (i, j): (usize, usize),
+    tid: TypeId,
+    path: &mut Vec<usize>,
+    t: &Target,
+    still_repros: &mut F,
+) where
+    F: FnMut(&Prog) -> bool,
+{
+    let val = val_at(&mut p.calls[i].args[j].val, path);
+    if let Some(new_val) = simplify(tid, val, t) {
+        let call = p.calls[i].clone();
+        *val_at(&mut p.calls[i].args[j].val, path) = new_val;
+        fill_lens(&mut p.calls[i], t);
+        if !still_repros(p) {
+            p.calls[i] = call;
+        }
+    }
+
+    let val = val_at(&mut p.calls[i].args[j].val, path);
+    let sub_vals = match (t.type_of(tid), &*val) {
+        (_, Value::Ref(_)) | (_, Value::None) => Vec::new(),
+        (TypeInfo::Ptr { tid, .. }, _)
+        | (TypeInfo::Alias { tid, .. }, _)
+        | (TypeInfo::Res { tid }, _) => {
+            return shrink_val(p, (i, j), *tid, path, t, still_repros);
+        }
+        (TypeInfo::Struct { fields, .. }, Value::Group(vals)) => fields
+            .iter()
+            .zip(0..vals.len())
+            .map(|(f, k)| (k, f.tid))
+            .collect(),
+        (TypeInfo::Slice { tid, .. }, Value::Group(vals)) => {
+            (0..vals.len()).map(|k| (k, *tid)).collect()
+        }
+        (TypeInfo::Union { fields, .. }, Value::Opt { choice, .. }) => {
+            vec![(0, fields[*choice].tid)]
+        }
+        _ => Vec::new(),
+    };
+    for (k, tid) in sub_vals {
+        path.push(k);
+        shrink_val(p, (i, j), tid, path, t, still_repros);
+        path.pop();
+    }
+}
+
+/// Simpler value of val, None if val is already the simplest one
+fn simplify(tid: TypeId, val: &Value, t: &Target) -> Option<Value> {
+    if t.is_const(tid) {
+        return None;
+    }
+    match (t.type_of(tid), val) {
+        (_, Value::Ref(_)) | (TypeInfo::Len { .. }, _) => None,
+        (_, Value::Num(NumValue::Signed(n))) if *n != 0 => Some(Value::Num(NumValue::Signed(0))),
+        (_, Value::Num(NumValue::Unsigned(n))) if *n != 0 => {
+            Some(Value::Num(NumValue::Unsigned(0)))
+        }
+        (_, Value::Str(s)) if !s.is_empty() => Some(Value::Str("".into())),
+        (TypeInfo::Slice { l, .. }, Value::Group(vals)) => {
+            let l = (*l).max(0) as usize;
+            if vals.len() > l {
+                Some(Value::Group(vals[..l].to_vec()))
+            } else {
+                None
+            }
+        }
+        (TypeInfo::Union { fields, .. }, Value::Opt { choice, .. }) if *choice != 0 => {
+            Some(Value::Opt {
+                choice: 0,
+                val: Box::new(Value::default_val(fields[0].tid, t, &mut thread_rng())),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Value nested in val, indexed by path
+fn val_at<'a>(val: &'a mut Value, path: &[usize]) -> &'a mut Value {
+    path.iter().fold(val, |v, &k| match v {
+        Value::Group(vals) => &mut vals[k],
+        Value::Opt { val, .. } => val,
+        _ => unreachable!(),
+    })
+}
+
 pub fn remove(p: &mut Prog, i: usize) -> bool {
     assert!(i < p.len() - 1);
 
@@ -26,9 +148,8 @@ pub fn remove(p: &mut Prog, i: usize) -> bool {
         return false;
     }
     // adjust ref arg
-    let last_call = p.len() - 1;
     for (j, call) in p.calls.iter_mut().enumerate().skip(i + 1) {
-        if !calls.contains(&j) && j != last_call {
+        if !calls.contains(&j) {
             for arg in call.args.iter_mut() {
                 for_each_ref_mut(&mut arg.val, |(ref mut cid, _)| {
                     let count = calls
@@ -81,7 +202,7 @@ fn is_arg_ref(call: &Call, i: usize) -> bool {
     false
 }
 
-fn for_each_ref<F: FnMut(&ArgIndex)>(val: &Value, f: F) {
+pub(crate) fn for_each_ref<F: FnMut(&ArgIndex)>(val: &Value, f: F) {
     struct InnerF<F: FnMut(&ArgIndex)> {
         f: Box<F>,
     }
@@ -90,7 +211,7 @@ fn for_each_ref<F: FnMut(&ArgIndex)>(val: &Value, f: F) {
     fn do_for_each_ref(val: &Value, f: &mut dyn FnMut(&ArgIndex)) {
         use Value::*;
         match val {
-            Num(_) | Str(_) | None => {}
+            Num(_) | Str(_) | None | Absent => {}
             Group(vals) => {
                 for v in vals.iter() {
                     do_for_each_ref(v, f)
@@ -104,7 +225,7 @@ fn for_each_ref<F: FnMut(&ArgIndex)>(val: &Value, f: F) {
     do_for_each_ref(val, &mut f.f)
 }
 
-fn for_each_ref_mut<F: FnMut(&mut ArgIndex)>(val: &mut Value, f: F) {
+pub(crate) fn for_each_ref_mut<F: FnMut(&mut ArgIndex)>(val: &mut Value, f: F) {
     struct InnerF<F: FnMut(&mut ArgIndex)> {
         f: Box<F>,
     }
@@ -112,7 +233,7 @@ fn for_each_ref_mut<F: FnMut(&mut ArgIndex)>(val: &mut Value, f: F) {
     fn do_for_each_ref_mut(val: &mut Value, f: &mut dyn FnMut(&mut ArgIndex)) {
         use Value::*;
         match val {
-            Num(_) | Str(_) | None => {}
+            Num(_) | Str(_) | None | Absent => {}
             Group(ref mut vals) => {
                 for v in vals.iter_mut() {
                     do_for_each_ref_mut(v, f)
@@ -125,3 +246,131 @@ fn for_each_ref_mut<F: FnMut(&mut ArgIndex)>(val: &mut Value, f: F) {
 
     do_for_each_ref_mut(val, &mut f.f)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prog::{Arg, ArgPos};
+
+    #[test]
+    fn minimize_to_last_call() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+group M {
+    fn open(name *filename) fd
+    fn close(f fd)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let g = t.iter_group().next().unwrap();
+        let (open, close) = (&g.fns[0], &g.fns[1]);
+        let fd = open.r_tid.unwrap();
+        let name = open.params.as_ref().unwrap()[0].tid;
+
+        let mut p = Prog::new(g.id);
+        let mut push = |fid, r: Option<usize>| {
+            let c = p.add_call(Call::new(fid));
+            match r {
+                Some(cid) => c.add_arg(Arg::new(fd)).val = Value::Ref((cid, ArgPos::Ret)),
+                None => {
+                    c.add_arg(Arg::new(name)).val = Value::Str("./a".into());
+                    c.ret = Some(Arg::new(fd));
+                }
+            }
+        };
+        push(open.id, None);
+        push(close.id, Some(0));
+        push(open.id, None);
+        push(open.id, None);
+        push(close.id, Some(3));
+        push(close.id, Some(2));
+
+        let check = |p: &Prog| {
+            for (i, c) in p.calls.iter().enumerate() {
+                for a in c.args.iter() {
+                    for_each_ref(&a.val, |(cid, _)| {
+                        assert!(*cid < i);
+                        assert!(p.calls[*cid].ret.is_some());
+                    })
+                }
+            }
+        };
+        let min_p = minimize_calls(&p, |p| {
+            check(p);
+            p.calls.last().unwrap().fid == close.id
+        });
+        assert_eq!(min_p.len(), 2);
+        assert_eq!(min_p.calls[0].fid, open.id);
+        assert_eq!(min_p.calls[1].args[0].val, Value::Ref((0, ArgPos::Ret)));
+
+        // last call doesn't depend on others
+        p.add_call(min_p.calls[0].clone());
+        let min_p = minimize_calls(&p, |p| {
+            check(p);
+            p.calls.last().unwrap().fid == open.id
+        });
+        assert_eq!(min_p.len(), 1);
+    }
+
+    #[test]
+    fn minimize_to_default_args() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+struct req { buf *[i8], size u64, magic u32 }
+fn submit(r *req)
+"#,
+            )
+            .unwrap(),
+        );
+        let g = t.iter_group().next().unwrap();
+        let f = &g.fns[0];
+        let buf = (0..128).map(|i| Value::Num(NumValue::Signed(i))).collect();
+        let magic = Value::Num(NumValue::Unsigned(0xdead));
+        let r = Value::Group(vec![
+            Value::Group(buf),
+            Value::Num(NumValue::Unsigned(1 << 40)),
+            magic.clone(),
+        ]);
+        let mut p = Prog::new(g.id);
+        let c = p.add_call(Call::new(f.id));
+        c.add_arg(Arg::new(f.params.as_ref().unwrap()[0].tid)).val = r;
+
+        let min_p = minimize_args(&p, &t, |p| match &p.calls[0].args[0].val {
+            Value::Group(vals) => vals[2] == magic,
+            _ => false,
+        });
+        let r = Value::Group(vec![
+            Value::Group(Vec::new()),
+            Value::Num(NumValue::Unsigned(0)),
+            magic,
+        ]);
+        assert_eq!(min_p.calls[0].args[0].val, r);
+    }
+
+    #[test]
+    fn keep_const_num() {
+        use crate::gen::{gen_seq, Config};
+
+        let t = Target::from(fots::parse_items("fn ioctl(cmd u32{0x5401}, arg u64)").unwrap());
+        let g = t.iter_group().next().unwrap();
+        let cmd = Value::Num(NumValue::Unsigned(0x5401));
+        assert!(t.is_const(g.fns[0].params.as_ref().unwrap()[0].tid));
+        for seed in 0..64 {
+            let conf = Config {
+                seed: Some(seed),
+                edge_value_prob: 0.5,
+                ..Default::default()
+            };
+            let p = gen_seq(&[0], g.id, &t, &conf);
+            assert_eq!(p.calls[0].args[0].val, cmd);
+            let p = minimize_args(&p, &t, |_| true);
+            assert_eq!(p.calls[0].args[0].val, cmd);
+            assert_eq!(p.calls[0].args[1].val, Value::Num(NumValue::Unsigned(0)));
+        }
+    }
+}
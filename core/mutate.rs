@@ -1,8 +1,10 @@
-use crate::analyze::RTable;
-use crate::gen::{gen_seq, Config};
-use crate::prog::Prog;
+use crate::analyze::{RTable, Relation};
+use crate::gen::{closed_res, gen_call_at, gen_pointee, gen_seq, regen_value, Config};
+use crate::minimize::{for_each_ref, for_each_ref_mut};
+use crate::prog::{Arg, ArgIndex, ArgPos, Call, Prog};
 use crate::target::Target;
-use fots::types::GroupId;
+use crate::value::{NumValue, Value};
+use fots::types::{GroupId, PtrDir, TypeId, TypeInfo};
 use rand::prelude::*;
 use std::collections::{HashMap, HashSet};
 
@@ -51,6 +53,276 @@ fn merge_seq(p0: &Prog, t: &Target, _rt: &RTable, corpus: &HashSet<Prog>, conf:
     gen_seq(&s0, p0.gid, t, conf)
 }
 
+/// Insert a new call of the same group into p at random position.
+///
+/// Calls depending on calls before the insertion point are preferred, args of
+/// inserted call only ref resources produced before it.
+pub fn mutate_insert(p: &mut Prog, t: &Target, rs: &HashMap<GroupId, RTable>, conf: &Config) {
+    let mut rng = thread_rng();
+    let g = &t.groups[&p.gid];
+    let rt = &rs[&p.gid];
+    let k = rng.gen_range(0, p.len() + 1);
+
+    let seq = extract_seq(p, t);
+    let deps = (0..g.fns.len())
+        .filter(|&i| seq[..k].iter().any(|&j| rt[(i, j)] == Relation::Some))
+        .collect::<Vec<_>>();
+    let fi = if !deps.is_empty() && rng.gen() {
+        *deps.choose(&mut rng).unwrap()
+    } else {
+        rng.gen_range(0, g.fns.len())
+    };
+
+    let c = gen_call_at(p, k, &g.fns[fi], t, conf);
+    for c in p.calls[k..].iter_mut() {
+        for arg in c.args.iter_mut() {
+            for_each_ref_mut(&mut arg.val, |(cid, _)| {
+                if *cid >= k {
+                    *cid += 1;
+                }
+            });
+        }
+    }
+    p.calls.insert(k, c);
+}
+
+/// Re-randomize value of arg in place, constraints of its type are kept and new
+/// values are generated by conf, except that its seed is ignored.
+///
+/// Resource refs and const nums are not changed, len args of the call should be
+/// refilled with `fill_lens` after mutation. Arg is marked as malformed only if
+/// the new value is.
+pub fn mutate_arg(arg: &mut Arg, t: &Target, conf: &Config) {
+    arg.malformed = regen_value(arg.tid, &mut arg.val, t, conf);
+}
+
+/// Remove a random call of p, refs to the removed call are re-pointed to
+/// another earlier producer of the same type, or reset to default value if
+/// there is no such producer.
+pub fn mutate_remove(p: &mut Prog) {
+    if p.len() <= 1 {
+        return;
+    }
+    let mut rng = thread_rng();
+    let k = rng.gen_range(0, p.len());
+    let removed = p.calls.remove(k);
+
+    for i in k..p.len() {
+        let producers = producers_of(&p.calls[..i]);
+        for arg in p.calls[i].args.iter_mut() {
+            repair_refs(&mut arg.val, &mut |(cid, pos)| {
+                if *cid > k {
+                    Some(Value::Ref((cid - 1, pos.clone())))
+                } else if *cid == k {
+                    let r = rebind(&removed, pos, &producers, &mut rng);
+                    Some(r.map_or(Value::Num(NumValue::Unsigned(0)), Value::Ref))
+                } else {
+                    None
+                }
+            });
+        }
+    }
+}
+
+/// Swap a random call of p with its next call, return false and leave p unchanged
+/// if the next call refs the call, since the ref would point to a later call, or
+/// if the next call closes a resource the call uses, since it would be used after
+/// close. Refs of following calls to the swapped calls are updated.
+pub fn mutate_swap(p: &mut Prog, t: &Target) -> bool {
+    if p.len() < 2 {
+        return false;
+    }
+    let k = thread_rng().gen_range(0, p.len() - 1);
+    let mut depends = false;
+    for arg in p.calls[k + 1].args.iter() {
+        for_each_ref(&arg.val, |(cid, _)| depends |= *cid == k);
+    }
+    let closed = closed_res(&p.calls[k + 1], t);
+    for arg in p.calls[k].args.iter() {
+        for_each_ref(&arg.val, |idx| depends |= closed.contains(idx));
+    }
+    if depends {
+        return false;
+    }
+
+    p.calls.swap(k, k + 1);
+    for c in p.calls[k + 2..].iter_mut() {
+        for arg in c.args.iter_mut() {
+            for_each_ref_mut(&mut arg.val, |(cid, _)| {
+                if *cid == k {
+                    *cid = k + 1;
+                } else if *cid == k + 1 {
+                    *cid = k;
+                }
+            });
+        }
+    }
+    true
+}
+
+/// Flip direction of a random pointer arg between In and Out, return false and
+/// leave p unchanged if p has no pointer arg whose direction can be flipped.
+///
+/// Only pointers of `InOut` type can be flipped, In and Out pointers are pinned
+/// by their type. Flipped to Out, the value becomes default out buffer, which
+/// produces resource if pointee is one. Flipped to In, content is generated and
+/// refs to resource that the arg produced are rebound to other producers or
+/// reset to default value, content is generated by conf.
+pub fn mutate_ptr_dir(p: &mut Prog, t: &Target, conf: &Config) -> bool {
+    let mut rng = thread_rng();
+    let ptrs = p
+        .calls
+        .iter()
+        .enumerate()
+        .flat_map(|(cid, c)| c.args.iter().enumerate().map(move |(j, a)| (cid, j, a)))
+        .filter_map(|(cid, j, arg)| match t.type_of(arg.tid) {
+            TypeInfo::Ptr {
+                dir: PtrDir::InOut,
+                tid,
+                ..
+            } => Some((cid, j, *tid)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let (k, j, tid) = match ptrs.choose(&mut rng) {
+        Some(ptr) => *ptr,
+        None => return false,
+    };
+
+    let arg = &mut p.calls[k].args[j];
+    // InOut pointer is written by callee unless it is flipped to In
+    let dir = match arg.dir {
+        Some(PtrDir::In) => PtrDir::Out,
+        _ => PtrDir::In,
+    };
+    arg.dir = Some(dir);
+    let (val, malformed) = gen_pointee(dir, tid, t, conf);
+    arg.val = val;
+    arg.malformed = malformed;
+    if dir == PtrDir::Out {
+        return true;
+    }
+
+    let flipped = p.calls[k].clone();
+    for i in k + 1..p.len() {
+        let producers = producers_of(&p.calls[..i])
+            .into_iter()
+            .filter(|(idx, _)| *idx != (k, ArgPos::Arg(j)))
+            .collect::<Vec<_>>();
+        for arg in p.calls[i].args.iter_mut() {
+            repair_refs(&mut arg.val, &mut |(cid, pos)| {
+                if *cid == k && *pos == ArgPos::Arg(j) {
+                    let r = rebind(&flipped, pos, &producers, &mut rng);
+                    Some(r.map_or_else(|| res_default(&flipped, pos, t), Value::Ref))
+                } else {
+                    None
+                }
+            });
+        }
+    }
+    true
+}
+
+/// Splice prefix of a and suffix of b, length of result is bounded by prog_max_len.
+///
+/// Refs inside suffix are shifted, refs to calls of b that are not included are
+/// rebound to producers of the same type in prefix or reset to default value.
+pub fn mutate_splice(a: &Prog, b: &Prog, t: &Target, conf: &Config) -> Prog {
+    assert_eq!(a.gid, b.gid);
+    assert!(!a.is_empty() && !b.is_empty());
+
+    let mut rng = thread_rng();
+    let max_len = conf.prog_max_len.max(1);
+    let i = rng.gen_range(1, a.len().min(max_len) + 1);
+    let j = rng.gen_range(0, b.len());
+    let suffix_len = (b.len() - j).min(max_len - i);
+
+    let mut p = a.sub_prog(i - 1);
+    let producers = producers_of(&a.calls[..i]);
+    for c in b.calls[j..j + suffix_len].iter() {
+        let mut c = c.clone();
+        for arg in c.args.iter_mut() {
+            repair_refs(&mut arg.val, &mut |(cid, pos)| {
+                if *cid >= j {
+                    return Some(Value::Ref((cid - j + i, pos.clone())));
+                }
+                let producer = &b.calls[*cid];
+                let r = rebind(producer, pos, &producers, &mut rng);
+                Some(r.map_or_else(|| res_default(producer, pos, t), Value::Ref))
+            });
+        }
+        p.add_call(c);
+    }
+    p
+}
+
+/// Resources producers of calls, rets and args, with their type
+fn producers_of(calls: &[Call]) -> Vec<(ArgIndex, TypeId)> {
+    calls
+        .iter()
+        .enumerate()
+        .flat_map(|(cid, c)| {
+            let ret = c.ret.iter().map(move |r| ((cid, ArgPos::Ret), r.tid));
+            let args = c
+                .args
+                .iter()
+                .enumerate()
+                .map(move |(j, a)| ((cid, ArgPos::Arg(j)), a.tid));
+            ret.chain(args)
+        })
+        .collect()
+}
+
+/// Choose producer that has same type with arg of c at pos
+fn rebind(
+    c: &Call,
+    pos: &ArgPos,
+    producers: &[(ArgIndex, TypeId)],
+    rng: &mut ThreadRng,
+) -> Option<ArgIndex> {
+    let tid = match pos {
+        ArgPos::Ret => c.ret.as_ref().unwrap().tid,
+        ArgPos::Arg(j) => c.args[*j].tid,
+    };
+    // rets only match rets, out args only match args of same ptr type
+    let is_ret = *pos == ArgPos::Ret;
+    producers
+        .iter()
+        .filter(|((_, p), p_tid)| *p_tid == tid && (*p == ArgPos::Ret) == is_ret)
+        .choose(rng)
+        .map(|(idx, _)| idx.clone())
+}
+
+/// Default value of resource produced by arg of c at pos
+fn res_default(c: &Call, pos: &ArgPos, t: &Target) -> Value {
+    let tid = match pos {
+        ArgPos::Ret => c.ret.as_ref().unwrap().tid,
+        ArgPos::Arg(j) => match t.type_of(c.args[*j].tid) {
+            TypeInfo::Ptr { tid, .. } => *tid,
+            _ => c.args[*j].tid,
+        },
+    };
+    Value::default_val(tid, t, &mut thread_rng())
+}
+
+/// Replace refs in val with value returned by f, if any
+fn repair_refs<F: FnMut(&ArgIndex) -> Option<Value>>(val: &mut Value, f: &mut F) {
+    match val {
+        Value::Group(vals) => {
+            for v in vals.iter_mut() {
+                repair_refs(v, f);
+            }
+        }
+        Value::Opt { val, .. } => repair_refs(val, f),
+        Value::Ref(idx) => {
+            if let Some(new_val) = f(idx) {
+                *val = new_val;
+            }
+        }
+        _ => (),
+    }
+}
+
 // fn insert_call(p: &Prog, t: &Target, rt: &RTable, corpus: &[Prog], conf: &Config) -> Prog {
 //     let seq = ex
 //     todo!()
@@ -80,3 +352,457 @@ fn merge_seq(p0: &Prog, t: &Target, _rt: &RTable, corpus: &HashSet<Prog>, conf:
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze::static_analyze;
+    use crate::gen::gen;
+
+    #[test]
+    fn insert_keeps_refs_valid() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+group I {
+    fn open(name *filename) fd
+    fn pipe(fds *Out fd)
+    fn read(f fd, buf *Out [i8], count len<i32, buf>)
+    fn close(f fd)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let rs = static_analyze(&t);
+        for seed in 0..256 {
+            let conf = Config {
+                seed: Some(seed),
+                ..Default::default()
+            };
+            let mut p = gen(&t, &rs, &conf).unwrap();
+            let len = p.len();
+            mutate_insert(&mut p, &t, &rs, &conf);
+            assert_eq!(p.len(), len + 1);
+            for (i, c) in p.calls.iter().enumerate() {
+                for arg in c.args.iter() {
+                    for_each_ref(&arg.val, |(cid, pos)| {
+                        assert!(*cid < i);
+                        let c = &p.calls[*cid];
+                        match pos {
+                            ArgPos::Ret => assert!(c.ret.is_some()),
+                            ArgPos::Arg(_) => assert_eq!(t.fn_of(c.fid).dec_name, "pipe"),
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn remove_repairs_refs() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+group R {
+    fn open(name *filename) fd
+    fn close(f fd)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let g = t.iter_group().next().unwrap();
+        let (open, close) = (&g.fns[0], &g.fns[1]);
+        let fd = open.r_tid.unwrap();
+        let name = open.params.as_ref().unwrap()[0].tid;
+        let mut open_call = Call::new(open.id);
+        open_call.add_arg(Arg::new(name)).val = Value::Str("./a".into());
+        open_call.ret = Some(Arg::new(fd));
+        let close_call = |cid| {
+            let mut c = Call::new(close.id);
+            c.add_arg(Arg::new(fd)).val = Value::Ref((cid, ArgPos::Ret));
+            c
+        };
+
+        for _ in 0..64 {
+            let mut p = Prog::new(g.id);
+            p.add_call(open_call.clone());
+            p.add_call(open_call.clone());
+            p.add_call(close_call(1));
+            p.add_call(close_call(0));
+            let orig = p.clone();
+            mutate_remove(&mut p);
+            assert_eq!(p.len(), 3);
+
+            let removed = (0..4).find(|&k| {
+                let mut ps = orig.clone();
+                ps.calls.remove(k);
+                ps.calls
+                    .iter()
+                    .map(|c| c.fid)
+                    .eq(p.calls.iter().map(|c| c.fid))
+            });
+            for (i, c) in p.calls.iter().enumerate() {
+                for arg in c.args.iter() {
+                    match &arg.val {
+                        Value::Ref((cid, ArgPos::Ret)) => {
+                            assert!(*cid < i);
+                            assert_eq!(p.calls[*cid].fid, open.id);
+                        }
+                        Value::Ref(_) => panic!("ref to non ret arg"),
+                        Value::Num(_) => assert_eq!(c.fid, close.id),
+                        _ => assert_eq!(c.fid, open.id),
+                    }
+                }
+            }
+            // removing the first producer forces close(r0) to use the other fd
+            if removed == Some(0) {
+                assert_eq!(p.calls[2].args[0].val, Value::Ref((0, ArgPos::Ret)));
+            }
+        }
+    }
+
+    #[test]
+    fn swap_keeps_refs_valid() {
+        use crate::prog::ProgBuilder;
+
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+group S {
+    fn open(name *filename) fd
+    fn close(f fd)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let g = t.iter_group().next().unwrap();
+        let (open, close) = (g.fns[0].id, g.fns[1].id);
+        let name = g.fns[0].params.as_ref().unwrap()[0].tid;
+        let fd = g.fns[0].r_tid.unwrap();
+        let open_call =
+            |b: ProgBuilder| b.call(open).arg(Value::Str("./a".into()), name).ret_res(fd);
+
+        let mut p = open_call(open_call(ProgBuilder::new(g.id)))
+            .call(close)
+            .ref_to(0, ArgPos::Ret, fd)
+            .build()
+            .unwrap();
+        p.calls[1].args[0].val = Value::Str("./b".into());
+        let orig = p.clone();
+        for _ in 0..32 {
+            let mut q = orig.clone();
+            if mutate_swap(&mut q, &t) {
+                assert_ne!(q, orig);
+                q.check_refs().unwrap();
+                // close still refs the open of "./a"
+                let (cid, _) = match &q.calls.iter().find(|c| c.fid == close).unwrap().args[0].val {
+                    Value::Ref(idx) => idx.clone(),
+                    v => panic!("unexpected value: {:?}", v),
+                };
+                assert_eq!(q.calls[cid].args[0].val, Value::Str("./a".into()));
+            }
+        }
+
+        let mut p = open_call(open_call(ProgBuilder::new(g.id)))
+            .build()
+            .unwrap();
+        p.calls[1].args[0].val = Value::Str("./b".into());
+        assert!(mutate_swap(&mut p, &t));
+        assert_eq!(p.calls[0].args[0].val, Value::Str("./b".into()));
+
+        let mut p = open_call(ProgBuilder::new(g.id))
+            .call(close)
+            .ref_to(0, ArgPos::Ret, fd)
+            .build()
+            .unwrap();
+        let orig = p.clone();
+        assert!(!mutate_swap(&mut p, &t));
+        assert_eq!(p, orig);
+    }
+
+    #[test]
+    fn swap_no_use_after_close() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+group C {
+    fn open(name *filename) fd
+    fn read(f fd, buf *Out [i8], count len<i32, buf>)
+    #[close]
+    fn close(f fd)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config::default();
+        for _ in 0..32 {
+            let mut p = gen_seq(&[0, 1, 2], g.id, &t, &conf);
+            assert_eq!(p.calls[1].args[0].val, Value::Ref((0, ArgPos::Ret)));
+            assert_eq!(p.calls[2].args[0].val, Value::Ref((0, ArgPos::Ret)));
+            let orig = p.clone();
+            // swapping read with close would use fd after close
+            assert!(!mutate_swap(&mut p, &t));
+            assert_eq!(p, orig);
+        }
+    }
+
+    #[test]
+    fn splice_keeps_refs_valid() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+group S {
+    fn open(name *filename) fd
+    fn pipe(fds *Out fd)
+    fn read(f fd, buf *Out [i8], count len<i32, buf>)
+    fn close(f fd)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let rs = static_analyze(&t);
+        let conf = Config {
+            prog_max_len: 8,
+            ..Default::default()
+        };
+        for seed in 0..256 {
+            let a_conf = Config {
+                seed: Some(seed),
+                ..conf.clone()
+            };
+            let a = gen(&t, &rs, &a_conf).unwrap();
+            let b_conf = Config {
+                seed: Some(seed + 256),
+                ..conf.clone()
+            };
+            let b = gen(&t, &rs, &b_conf).unwrap();
+            let p = mutate_splice(&a, &b, &t, &conf);
+            assert!(p.len() <= conf.prog_max_len);
+            for (i, c) in p.calls.iter().enumerate() {
+                for arg in c.args.iter() {
+                    for_each_ref(&arg.val, |(cid, pos)| {
+                        assert!(*cid < i);
+                        let c = &p.calls[*cid];
+                        match pos {
+                            ArgPos::Ret => assert!(c.ret.is_some()),
+                            ArgPos::Arg(_) => assert_eq!(t.fn_of(c.fid).dec_name, "pipe"),
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mutate_arg_keeps_constraints() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+flag mode {A=1,B=2,C=4}
+group A {
+    fn open(name *filename) fd
+    fn set(f fd, m mode, n u32{(10, 20)}, cmd u16{7}, buf *[i8;(2, 6)])
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let g = t.iter_group().next().unwrap();
+        let set = &g.fns[1];
+        let conf = Config {
+            null_ptr_prob: 0.0,
+            ..Default::default()
+        };
+        let p = gen_seq(&[0, 1], g.id, &t, &conf);
+        let mut ns = HashSet::new();
+        for _ in 0..256 {
+            let mut c = p.calls[1].clone();
+            assert_eq!(c.fid, set.id);
+            for arg in c.args.iter_mut() {
+                mutate_arg(arg, &t, &conf);
+            }
+            assert_eq!(c.args[0].val, Value::Ref((0, ArgPos::Ret)));
+            match &c.args[1].val {
+                Value::Num(NumValue::Signed(m)) => assert!(*m != 0 && *m & !7 == 0),
+                v => panic!("unexpected flag value: {:?}", v),
+            }
+            match &c.args[2].val {
+                Value::Num(NumValue::Unsigned(n)) => {
+                    assert!((10..20).contains(n));
+                    ns.insert(*n);
+                }
+                v => panic!("unexpected num value: {:?}", v),
+            }
+            assert_eq!(c.args[3].val, Value::Num(NumValue::Unsigned(7)));
+            let len = c.args[4].val.len().unwrap();
+            assert!((2..6).contains(&len));
+        }
+        assert!(ns.len() > 1);
+
+        // seed of config does not make mutations repeat
+        let conf = Config {
+            seed: Some(0),
+            ..conf
+        };
+        let mut ns = HashSet::new();
+        for _ in 0..64 {
+            let mut arg = p.calls[1].args[2].clone();
+            mutate_arg(&mut arg, &t, &conf);
+            ns.insert(arg.val);
+        }
+        assert!(ns.len() > 1);
+
+        // values are regenerated by given config
+        let conf = Config {
+            slice_boundary_prob: 1.0,
+            ..conf
+        };
+        for _ in 0..64 {
+            let mut arg = p.calls[1].args[4].clone();
+            mutate_arg(&mut arg, &t, &conf);
+            let len = arg.val.len().unwrap();
+            assert!(len == 2 || len == 6, "{}", len);
+        }
+    }
+
+    #[test]
+    fn mutate_arg_without_producer() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+struct s { f fd }
+group G {
+    fn open(name *filename) fd
+    fn ctl(a *s)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let g = t.iter_group().next().unwrap();
+        let ctl = &g.fns[1];
+        let tid = ctl.params.as_ref().unwrap()[0].tid;
+        let conf = Config {
+            auto_producer: true,
+            ..Default::default()
+        };
+        for _ in 0..64 {
+            // null pointer regenerated to struct holding a resource
+            let mut arg = Arg::new(tid);
+            mutate_arg(&mut arg, &t, &conf);
+            let mut refs = 0;
+            for_each_ref(&arg.val, |_| refs += 1);
+            assert_eq!(refs, 0);
+            assert!(!arg.malformed);
+        }
+
+        // fabricated handle keeps arg marked as malformed
+        let conf = Config {
+            dangling_ref_prob: 1.0,
+            ..conf
+        };
+        let mut arg = Arg::new(tid);
+        mutate_arg(&mut arg, &t, &conf);
+        assert!(arg.malformed);
+    }
+
+    #[test]
+    fn flip_ptr_dir() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+group F {
+    fn get(p *IO fd)
+    fn close(f fd)
+    fn stat(p *In u32, q *Out u32)
+    fn fill(buf *IO [i8;(2, 6)])
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let g = t.iter_group().next().unwrap();
+        let fd = match t.type_of(g.fns[0].params.as_ref().unwrap()[0].tid) {
+            TypeInfo::Ptr { tid, .. } => *tid,
+            _ => unreachable!(),
+        };
+        let conf = Config::default();
+
+        let mut p = gen_seq(&[2], g.id, &t, &conf);
+        assert!(!mutate_ptr_dir(&mut p, &t, &conf));
+        assert!(p.calls[0].args.iter().all(|a| a.dir.is_none()));
+
+        let mut p = gen_seq(&[0, 1], g.id, &t, &conf);
+        assert_eq!(p.calls[1].args[0].val, Value::Ref((0, ArgPos::Arg(0))));
+        assert!(mutate_ptr_dir(&mut p, &t, &conf));
+        assert_eq!(p.calls[0].args[0].dir, Some(PtrDir::In));
+        assert!(crate::prog::resource_edges(&p).is_empty());
+        assert_eq!(p.calls[1].args[0].val, Value::Num(NumValue::Unsigned(0)));
+
+        p.calls[0].args[0].val = Value::Num(NumValue::Signed(3));
+        assert!(mutate_ptr_dir(&mut p, &t, &conf));
+        let arg = &p.calls[0].args[0];
+        assert_eq!(arg.dir, Some(PtrDir::Out));
+        assert_eq!(arg.val, Value::default_val(fd, &t, &mut thread_rng()));
+        let mut res = Vec::new();
+        crate::gen::arg_out_res(arg, &t, &mut res);
+        assert_eq!(res, vec![fd]);
+
+        // pointee flipped to In is generated by given config
+        let conf = Config {
+            null_ptr_prob: 0.0,
+            slice_boundary_prob: 1.0,
+            ..conf
+        };
+        let mut p = gen_seq(&[3], g.id, &t, &conf);
+        for _ in 0..64 {
+            p.calls[0].args[0].dir = None;
+            assert!(mutate_ptr_dir(&mut p, &t, &conf));
+            let arg = &p.calls[0].args[0];
+            assert_eq!(arg.dir, Some(PtrDir::In));
+            let len = arg.val.len().unwrap();
+            assert!(len == 2 || len == 6, "{}", len);
+        }
+
+        // no producer is inserted for flipped pointee
+        let conf = Config {
+            auto_producer: true,
+            ..conf
+        };
+        for _ in 0..64 {
+            let mut p = gen_seq(&[0], g.id, &t, &conf);
+            p.calls[0].args[0].dir = Some(PtrDir::Out);
+            assert!(mutate_ptr_dir(&mut p, &t, &conf));
+            assert_eq!(p.len(), 1);
+            let mut refs = 0;
+            for_each_ref(&p.calls[0].args[0].val, |_| refs += 1);
+            assert_eq!(refs, 0);
+            assert!(!p.calls[0].args[0].malformed);
+        }
+
+        // fabricated handle keeps flipped arg marked as malformed
+        let conf = Config {
+            dangling_ref_prob: 1.0,
+            ..conf
+        };
+        let mut p = gen_seq(&[0], g.id, &t, &conf);
+        p.calls[0].args[0].dir = Some(PtrDir::Out);
+        assert!(mutate_ptr_dir(&mut p, &t, &conf));
+        assert_eq!(p.calls[0].args[0].dir, Some(PtrDir::In));
+        assert!(p.calls[0].args[0].malformed);
+    }
+}
@@ -1,8 +1,17 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write};
+use std::hash::{Hash, Hasher};
 use std::ops::Index;
 
-use fots::types::{FnId, GroupId, TypeId};
+use fots::types::{Flag, FnId, GroupId, PtrDir, TypeId, TypeInfo};
 
-use crate::value::Value;
+use crate::gen::arg_out_res;
+use crate::minimize::for_each_ref_mut;
+use crate::syz;
+use crate::target::Target;
+use crate::value::{NumValue, Value};
+use crate::wire::{self, WireError};
 
 /// Id of call in a prog
 pub type CId = usize;
@@ -17,10 +26,16 @@ pub enum ArgPos {
 }
 
 /// Seq of call of a group
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+///
+/// Equality, ordering and hash only depend on group and calls, seed is ignored
+/// so that progs with same structure are deduplicated.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Prog {
     pub gid: GroupId,
     pub calls: Vec<Call>,
+    /// Seed that generated this prog, if any
+    #[serde(default)]
+    pub seed: Option<u64>,
 }
 
 impl Prog {
@@ -28,6 +43,7 @@ impl Prog {
         Self {
             gid,
             calls: Vec::new(),
+            seed: None,
         }
     }
 
@@ -54,13 +70,245 @@ impl Prog {
         self.calls.shrink_to_fit();
     }
 
+    /// Check that every ref points to existing arg of previous call
+    pub fn check_refs(&self) -> Result<(), InvalidRef> {
+        for (i, c) in self.calls.iter().enumerate() {
+            let mut refs = Vec::new();
+            for a in c.args.iter() {
+                collect_refs(&a.val, &mut refs);
+            }
+            for (cid, pos) in refs {
+                let valid = cid < i
+                    && match pos {
+                        ArgPos::Ret => self.calls[cid].ret.is_some(),
+                        ArgPos::Arg(j) => j < self.calls[cid].args.len(),
+                    };
+                if !valid {
+                    return Err(InvalidRef {
+                        call: i,
+                        target: (cid, pos),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop returns that are never referenced, then check remaining refs.
+    ///
+    /// Only unused returns are removed, so the prog behaves the same. Progs
+    /// that only differ in such returns have the same normalized form.
+    pub fn normalize(&mut self) -> Result<(), InvalidRef> {
+        let used = self
+            .iter_args()
+            .filter_map(|(_, v)| match v {
+                Value::Ref((cid, ArgPos::Ret)) => Some(*cid),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        for (i, c) in self.calls.iter_mut().enumerate() {
+            if !used.contains(&i) {
+                c.ret = None;
+            }
+        }
+        self.check_refs()
+    }
+
+    /// Ids of fns of calls, in order of calls
+    pub fn function_ids(&self) -> Vec<FnId> {
+        self.calls.iter().map(|c| c.fid).collect()
+    }
+
+    /// Iterate over values of params and returns of all calls, descending into
+    /// groups and opts in depth-first order. Nested values are paired with index
+    /// of arg that holds them
+    pub fn iter_args(&self) -> impl Iterator<Item = (ArgIndex, &Value)> + '_ {
+        self.calls.iter().enumerate().flat_map(|(i, c)| {
+            let args = c.args.iter().enumerate();
+            args.map(move |(j, a)| ((i, ArgPos::Arg(j)), a))
+                .chain(c.ret.iter().map(move |a| ((i, ArgPos::Ret), a)))
+                .flat_map(|(idx, a)| a.val.iter_vals().map(move |v| (idx.clone(), v)))
+        })
+    }
+
+    /// Mutable version of `iter_args`, only values that hold no other values are
+    /// visited, since groups and opts can't be borrowed along with their elements
+    pub fn iter_args_mut(&mut self) -> impl Iterator<Item = (ArgIndex, &mut Value)> + '_ {
+        self.calls.iter_mut().enumerate().flat_map(|(i, c)| {
+            let args = c.args.iter_mut().enumerate();
+            args.map(move |(j, a)| ((i, ArgPos::Arg(j)), a))
+                .chain(c.ret.iter_mut().map(move |a| ((i, ArgPos::Ret), a)))
+                .flat_map(|(idx, a)| a.val.iter_leaves_mut().map(move |v| (idx.clone(), v)))
+        })
+    }
+
+    /// Serialize prog to json
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// Deserialize prog from json produced by `to_json`
+    pub fn from_json(s: &str) -> Result<Prog, serde_json::Error> {
+        serde_json::from_str(s)
+    }
+
+    /// Render prog in text format of syzkaller programs, see `syz`
+    pub fn to_syz(&self, t: &Target) -> String {
+        syz::to_syz(self, t)
+    }
+
+    /// Serialize prog to compact binary form, see `wire`
+    pub fn encode_wire(&self) -> Vec<u8> {
+        wire::encode(self)
+    }
+
+    /// Deserialize prog from bytes produced by `encode_wire`
+    pub fn decode_wire(buf: &[u8]) -> Result<Prog, WireError> {
+        wire::decode(buf)
+    }
+
+    /// Render prog as pseudo-c trace, one call per line.
+    ///
+    /// Produced resources are named as r0, r1 ... and refs to them are rendered as that name.
+    /// Flags are rendered as names of their members when possible, e.g. `O_CREAT|O_RDWR`.
+    pub fn render(&self, t: &Target) -> String {
+        let names = res_names(self, t);
+        let mut buf = String::new();
+        for (i, c) in self.calls.iter().enumerate() {
+            if let Some(name) = names.get(&(i, ArgPos::Ret)) {
+                write!(buf, "{} = ", name).unwrap();
+            }
+            let args = c
+                .args
+                .iter()
+                .enumerate()
+                .map(|(j, a)| match names.get(&(i, ArgPos::Arg(j))) {
+                    Some(name) => format!("&{}", name),
+                    None => render_val(a.tid, &a.val, t, &names),
+                })
+                .collect::<Vec<_>>();
+            writeln!(buf, "{}({})", t.fn_of(c.fid).dec_name, args.join(", ")).unwrap();
+        }
+        buf
+    }
+
     /// Return prog that contains calls from 0..=index
     pub fn sub_prog(&self, index: usize) -> Prog {
         Self {
             gid: self.gid,
             calls: Vec::from(&self.calls[..=index]),
+            seed: None,
         }
     }
+
+    /// Hash of group and first n calls with their args, later calls and seed are
+    /// ignored, n is clamped to length of prog. Hash is FNV-1a of wire form of the
+    /// prefix, so it is stable across builds and can key persisted snapshots of
+    /// state after executing the prefix
+    pub fn prefix_hash(&self, n: usize) -> u64 {
+        let prefix = Prog {
+            gid: self.gid,
+            calls: self.calls[..n.min(self.len())].to_vec(),
+            seed: None,
+        };
+        fnv1a(&crate::wire::encode(&prefix))
+    }
+}
+
+/// FNV-1a hash of data, stable across builds unlike DefaultHasher
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+        (h ^ u64::from(*b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Builder of prog, mainly for constructing progs by hand in tests
+pub struct ProgBuilder {
+    p: Prog,
+}
+
+/// Ref of call that points to nonexistent or later arg
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidRef {
+    pub call: usize,
+    pub target: ArgIndex,
+}
+
+impl fmt::Display for InvalidRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid ref of call {}: {:?}", self.call, self.target)
+    }
+}
+
+impl std::error::Error for InvalidRef {}
+
+impl ProgBuilder {
+    pub fn new(gid: GroupId) -> Self {
+        Self { p: Prog::new(gid) }
+    }
+
+    /// Start a new call of fn fid
+    pub fn call(mut self, fid: FnId) -> Self {
+        self.p.add_call(Call::new(fid));
+        self
+    }
+
+    /// Add arg of type tid to current call
+    pub fn arg(mut self, val: Value, tid: TypeId) -> Self {
+        self.last_call().add_arg(Arg::new(tid)).val = val;
+        self
+    }
+
+    /// Add arg of type tid to current call that refs arg at pos of call call_idx
+    pub fn ref_to(self, call_idx: usize, pos: ArgPos, tid: TypeId) -> Self {
+        self.arg(Value::Ref((call_idx, pos)), tid)
+    }
+
+    /// Current call returns resource of type tid
+    pub fn ret_res(mut self, tid: TypeId) -> Self {
+        self.last_call().ret = Some(Arg::new(tid));
+        self
+    }
+
+    /// Finish building, every ref must point to existing arg of previous call
+    pub fn build(self) -> Result<Prog, InvalidRef> {
+        self.p.check_refs()?;
+        Ok(self.p)
+    }
+
+    fn last_call(&mut self) -> &mut Call {
+        self.p
+            .calls
+            .last_mut()
+            .expect("call() must be called first")
+    }
+}
+
+impl PartialEq for Prog {
+    fn eq(&self, other: &Self) -> bool {
+        self.gid == other.gid && self.calls == other.calls
+    }
+}
+
+impl Eq for Prog {}
+
+impl PartialOrd for Prog {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Prog {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.gid, &self.calls).cmp(&(other.gid, &other.calls))
+    }
+}
+
+impl Hash for Prog {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.gid.hash(state);
+        self.calls.hash(state);
+    }
 }
 
 impl Index<ArgIndex> for Prog {
@@ -75,6 +323,483 @@ impl Index<ArgIndex> for Prog {
     }
 }
 
+/// Violation of invariant of prog found by `validate` or `concat`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgError {
+    /// Progs of different groups can not be concatenated
+    GroupMismatch { expected: GroupId, found: GroupId },
+    /// Fn of call not exists in target
+    UnknownFn { call: usize, fid: FnId },
+    /// Number of args of call differs from number of params of fn
+    Arity {
+        call: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// Ref points to nonexistent arg or arg of call that is not before referencing call
+    DanglingRef { call: usize, target: ArgIndex },
+    /// Ref points to arg that does not produce resource of referenced type
+    RefType { call: usize, target: ArgIndex },
+    /// Choice of union value is out of fields of union
+    UnionChoice {
+        call: usize,
+        arg: usize,
+        choice: usize,
+    },
+    /// Length of slice value is out of declared bounds of slice
+    SliceLen { call: usize, arg: usize, len: usize },
+}
+
+impl fmt::Display for ProgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgError::GroupMismatch { expected, found } => {
+                write!(f, "Expected prog of group {}, found {}", expected, found)
+            }
+            ProgError::UnknownFn { call, fid } => write!(f, "Call {}: unknown fn {}", call, fid),
+            ProgError::Arity {
+                call,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Call {}: expected {} args, found {}",
+                call, expected, found
+            ),
+            ProgError::DanglingRef { call, target } => {
+                write!(f, "Call {}: dangling ref {:?}", call, target)
+            }
+            ProgError::RefType { call, target } => write!(
+                f,
+                "Call {}: ref {:?} does not produce referenced resource",
+                call, target
+            ),
+            ProgError::UnionChoice { call, arg, choice } => write!(
+                f,
+                "Call {} arg {}: union choice {} out of range",
+                call, arg, choice
+            ),
+            ProgError::SliceLen { call, arg, len } => write!(
+                f,
+                "Call {} arg {}: slice length {} out of bounds",
+                call, arg, len
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProgError {}
+
+/// Append calls of b to a, refs inside b are shifted by length of a so that
+/// they still point to calls of b. Seed of result is None.
+pub fn concat(mut a: Prog, b: Prog) -> Result<Prog, ProgError> {
+    if a.gid != b.gid {
+        return Err(ProgError::GroupMismatch {
+            expected: a.gid,
+            found: b.gid,
+        });
+    }
+    let offset = a.len();
+    for mut c in b.calls.into_iter() {
+        for arg in c.args.iter_mut() {
+            for_each_ref_mut(&mut arg.val, |(cid, _)| *cid += offset);
+        }
+        a.calls.push(c);
+    }
+    a.seed = None;
+    Ok(a)
+}
+
+/// Difference between two progs found by `diff`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgDiff {
+    /// Call of fid exists in second prog only
+    AddedCall { call: usize, fid: FnId },
+    /// Call of fid exists in first prog only
+    RemovedCall { call: usize, fid: FnId },
+    /// Calls at same position call different fns, args are not compared
+    ChangedFn { call: usize, from: FnId, to: FnId },
+    /// Values of arg differ. Path indexes into groups and opts from arg to
+    /// the outermost differing value, missing arg is `Value::None`
+    ChangedArg {
+        call: usize,
+        arg: usize,
+        path: Vec<usize>,
+        from: Value,
+        to: Value,
+    },
+}
+
+/// Differences between calls of a and b, calls are aligned by position
+pub fn diff(a: &Prog, b: &Prog) -> Vec<ProgDiff> {
+    let mut diffs = Vec::new();
+    for (i, (ca, cb)) in a.calls.iter().zip(b.calls.iter()).enumerate() {
+        if ca.fid != cb.fid {
+            diffs.push(ProgDiff::ChangedFn {
+                call: i,
+                from: ca.fid,
+                to: cb.fid,
+            });
+            continue;
+        }
+        for j in 0..ca.args.len().max(cb.args.len()) {
+            let va = ca.args.get(j).map_or(&Value::None, |a| &a.val);
+            let vb = cb.args.get(j).map_or(&Value::None, |a| &a.val);
+            diff_val(va, vb, &mut Vec::new(), &mut |path, from, to| {
+                diffs.push(ProgDiff::ChangedArg {
+                    call: i,
+                    arg: j,
+                    path,
+                    from: from.clone(),
+                    to: to.clone(),
+                })
+            });
+        }
+    }
+    for (i, c) in a.calls.iter().enumerate().skip(b.len()) {
+        diffs.push(ProgDiff::RemovedCall {
+            call: i,
+            fid: c.fid,
+        });
+    }
+    for (i, c) in b.calls.iter().enumerate().skip(a.len()) {
+        diffs.push(ProgDiff::AddedCall {
+            call: i,
+            fid: c.fid,
+        });
+    }
+    diffs
+}
+
+fn diff_val<F: FnMut(Vec<usize>, &Value, &Value)>(
+    a: &Value,
+    b: &Value,
+    path: &mut Vec<usize>,
+    f: &mut F,
+) {
+    match (a, b) {
+        (Value::Group(va), Value::Group(vb)) if va.len() == vb.len() => {
+            for (i, (a, b)) in va.iter().zip(vb.iter()).enumerate() {
+                path.push(i);
+                diff_val(a, b, path, f);
+                path.pop();
+            }
+        }
+        (
+            Value::Opt {
+                choice: ca,
+                val: va,
+            },
+            Value::Opt {
+                choice: cb,
+                val: vb,
+            },
+        ) if ca == cb => {
+            path.push(*ca);
+            diff_val(va, vb, path, f);
+            path.pop();
+        }
+        (a, b) if a != b => f(path.clone(), a, b),
+        _ => (),
+    }
+}
+
+/// Check all invariants of p against t, every violation is reported
+pub fn validate(p: &Prog, t: &Target) -> Result<(), Vec<ProgError>> {
+    let mut errs = Vec::new();
+    for (i, c) in p.calls.iter().enumerate() {
+        if !t.fns.contains_key(&c.fid) {
+            errs.push(ProgError::UnknownFn {
+                call: i,
+                fid: c.fid,
+            });
+            continue;
+        }
+        let f = t.fn_of(c.fid);
+        let params = f.params.as_deref().unwrap_or(&[]);
+        if params.len() != c.args.len() {
+            errs.push(ProgError::Arity {
+                call: i,
+                expected: params.len(),
+                found: c.args.len(),
+            });
+        }
+        for (j, (param, arg)) in params.iter().zip(c.args.iter()).enumerate() {
+            validate_val(p, (i, j), param.tid, &arg.val, t, &mut errs);
+        }
+    }
+    if errs.is_empty() {
+        Ok(())
+    } else {
+        Err(errs)
+    }
+}
+
+fn validate_val(
+    p: &Prog,
+    (i, j): (usize, usize),
+    tid: TypeId,
+    val: &Value,
+    t: &Target,
+    errs: &mut Vec<ProgError>,
+) {
+    match (t.type_of(tid), val) {
+        (_, Value::Ref(target)) => {
+            if let Some(e) = validate_ref(p, i, tid, target, t) {
+                errs.push(e);
+            }
+        }
+        (_, Value::None) => (),
+        (TypeInfo::Ptr { tid, .. }, val)
+        | (TypeInfo::Alias { tid, .. }, val)
+        | (TypeInfo::Res { tid }, val) => validate_val(p, (i, j), *tid, val, t, errs),
+        (TypeInfo::Slice { tid, l, h }, Value::Group(vals)) => {
+            let len = vals.len();
+            if (*l != -1 && len < *l as usize) || (*h != -1 && len > *h as usize) {
+                errs.push(ProgError::SliceLen {
+                    call: i,
+                    arg: j,
+                    len,
+                });
+            }
+            for v in vals.iter() {
+                validate_val(p, (i, j), *tid, v, t, errs);
+            }
+        }
+        (TypeInfo::Struct { fields, .. }, Value::Group(vals)) => {
+            for (f, v) in fields.iter().zip(vals.iter()) {
+                validate_val(p, (i, j), f.tid, v, t, errs);
+            }
+        }
+        (TypeInfo::Union { fields, .. }, Value::Opt { choice, val }) => match fields.get(*choice) {
+            Some(f) => validate_val(p, (i, j), f.tid, val, t, errs),
+            None => errs.push(ProgError::UnionChoice {
+                call: i,
+                arg: j,
+                choice: *choice,
+            }),
+        },
+        _ => (),
+    }
+}
+
+/// Check ref of call i at position of type tid
+fn validate_ref(
+    p: &Prog,
+    i: usize,
+    mut tid: TypeId,
+    target: &ArgIndex,
+    t: &Target,
+) -> Option<ProgError> {
+    let (cid, pos) = target;
+    let dangling = ProgError::DanglingRef {
+        call: i,
+        target: target.clone(),
+    };
+    if *cid >= i {
+        return Some(dangling);
+    }
+    let c = &p.calls[*cid];
+    let mut produced = Vec::new();
+    match pos {
+        ArgPos::Ret => match c.ret.as_ref() {
+            Some(ret) => produced.push(ret.tid),
+            None => return Some(dangling),
+        },
+        ArgPos::Arg(k) => match c.args.get(*k) {
+            Some(arg) => arg_out_res(arg, t, &mut produced),
+            None => return Some(dangling),
+        },
+    }
+    // alias of resource can be bound to producer of its under resource
+    loop {
+        if produced.contains(&tid) {
+            return None;
+        }
+        match t.type_of(tid) {
+            TypeInfo::Alias { tid: under, .. } => tid = *under,
+            _ => {
+                return Some(ProgError::RefType {
+                    call: i,
+                    target: target.clone(),
+                })
+            }
+        }
+    }
+}
+
+/// Resource flow of prog, (producer, consumer) pair for each ref in args
+pub fn resource_edges(p: &Prog) -> Vec<(ArgIndex, ArgIndex)> {
+    p.iter_args()
+        .filter_map(|(idx, v)| match v {
+            Value::Ref(r) => Some((r.clone(), idx)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Resource types that prog produces and consumes.
+///
+/// Produced types come from returns and out pointers of calls, consumed types are
+/// types of args holding refs, so alias of resource is reported as itself.
+pub fn resource_profile(p: &Prog, t: &Target) -> (HashSet<TypeId>, HashSet<TypeId>) {
+    let mut produced = Vec::new();
+    let mut consumed = Vec::new();
+    for c in p.calls.iter() {
+        for arg in c.args.iter() {
+            arg_out_res(arg, t, &mut produced);
+            ref_types(arg.tid, &arg.val, t, &mut consumed);
+        }
+        if let Some(ret) = c.ret.as_ref() {
+            if t.is_res(ret.tid) {
+                produced.push(ret.tid);
+            }
+        }
+    }
+    (
+        produced.into_iter().collect(),
+        consumed.into_iter().collect(),
+    )
+}
+
+/// Collect types of refs in val of type tid
+fn ref_types(tid: TypeId, val: &Value, t: &Target, tids: &mut Vec<TypeId>) {
+    match (t.type_of(tid), val) {
+        (_, Value::Ref(_)) => tids.push(tid),
+        (TypeInfo::Ptr { tid, .. }, val) | (TypeInfo::Alias { tid, .. }, val) => {
+            ref_types(*tid, val, t, tids)
+        }
+        (TypeInfo::Struct { fields, .. }, Value::Group(vals)) => {
+            for (f, v) in fields.iter().zip(vals.iter()) {
+                ref_types(f.tid, v, t, tids);
+            }
+        }
+        (TypeInfo::Slice { tid, .. }, Value::Group(vals)) => {
+            for v in vals.iter() {
+                ref_types(*tid, v, t, tids);
+            }
+        }
+        (TypeInfo::Union { fields, .. }, Value::Opt { choice, val }) => {
+            if let Some(f) = fields.get(*choice) {
+                ref_types(f.tid, val, t, tids);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn collect_refs(val: &Value, refs: &mut Vec<ArgIndex>) {
+    refs.extend(val.iter_vals().filter_map(|v| match v {
+        Value::Ref(idx) => Some(idx.clone()),
+        _ => None,
+    }));
+}
+
+/// Symbolic form of flag value v, like `O_CREAT|O_RDWR`, members are in declared order.
+///
+/// Member equal to v is preferred, otherwise v is decomposed into members whose
+/// bits are all set in v. None if some bits of v are not covered by any member.
+fn flag_names(flags: &[Flag], v: u64) -> Option<String> {
+    if let Some(f) = flags.iter().find(|f| f.val as u64 == v) {
+        return Some(f.ident.clone());
+    }
+    let mut covered = 0;
+    let mut names = Vec::new();
+    for f in flags.iter() {
+        let bits = f.val as u64;
+        if bits != 0 && v & bits == bits && covered & bits != bits {
+            covered |= bits;
+            names.push(&f.ident[..]);
+        }
+    }
+    if covered == v && !names.is_empty() {
+        Some(names.join("|"))
+    } else {
+        None
+    }
+}
+
+/// Names of produced resources, r0, r1 ... in order of calls. Args are only
+/// named if referenced, resource returns are always named
+pub(crate) fn res_names(p: &Prog, t: &Target) -> HashMap<ArgIndex, String> {
+    let mut refs = Vec::new();
+    for c in p.calls.iter() {
+        for a in c.args.iter() {
+            collect_refs(&a.val, &mut refs);
+        }
+    }
+    let mut names = HashMap::new();
+    for (i, c) in p.calls.iter().enumerate() {
+        for j in 0..c.args.len() {
+            let idx = (i, ArgPos::Arg(j));
+            if refs.contains(&idx) {
+                let name = format!("r{}", names.len());
+                names.insert(idx, name);
+            }
+        }
+        if let Some(ret) = c.ret.as_ref() {
+            if t.is_res(ret.tid) {
+                let name = format!("r{}", names.len());
+                names.insert((i, ArgPos::Ret), name);
+            }
+        }
+    }
+    names
+}
+
+/// Name of resource produced by arg idx, refs of malformed progs may point to
+/// args that produce nothing
+pub(crate) fn res_name(names: &HashMap<ArgIndex, String>, idx: &ArgIndex) -> String {
+    match names.get(idx) {
+        Some(name) => name.clone(),
+        None => match idx.1 {
+            ArgPos::Ret => format!("<bad ref {}:ret>", idx.0),
+            ArgPos::Arg(j) => format!("<bad ref {}:{}>", idx.0, j),
+        },
+    }
+}
+
+fn render_val(tid: TypeId, val: &Value, t: &Target, names: &HashMap<ArgIndex, String>) -> String {
+    if let Value::Ref(idx) = val {
+        return res_name(names, idx);
+    }
+    match (t.type_of(tid), val) {
+        (TypeInfo::Ptr { .. }, Value::None) => "NULL".into(),
+        (TypeInfo::Ptr { tid, .. }, val) => format!("&{}", render_val(*tid, val, t, names)),
+        (TypeInfo::Alias { tid, .. }, val) | (TypeInfo::Res { tid }, val) => {
+            render_val(*tid, val, t, names)
+        }
+        (TypeInfo::Flag { flags, .. }, Value::Num(n)) => {
+            let v = match n {
+                NumValue::Signed(v) => *v as u64,
+                NumValue::Unsigned(v) => *v,
+            };
+            flag_names(flags, v).unwrap_or_else(|| format!("{:#x}", v))
+        }
+        (TypeInfo::Struct { fields, .. }, Value::Group(vals)) => {
+            let vals = fields
+                .iter()
+                .zip(vals.iter())
+                .filter(|(_, v)| **v != Value::Absent)
+                .map(|(f, v)| render_val(f.tid, v, t, names))
+                .collect::<Vec<_>>();
+            format!("{{{}}}", vals.join(", "))
+        }
+        (TypeInfo::Slice { tid, .. }, Value::Group(vals)) => {
+            let vals = vals
+                .iter()
+                .map(|v| render_val(*tid, v, t, names))
+                .collect::<Vec<_>>();
+            format!("[{}]", vals.join(", "))
+        }
+        (TypeInfo::Union { fields, .. }, Value::Opt { choice, val }) => {
+            render_val(fields[*choice].tid, val, t, names)
+        }
+        (_, Value::Str(s)) => format!("{:?}", s),
+        (_, val) => val.literal(),
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Call {
     /// prototype
@@ -113,6 +838,20 @@ impl Call {
 pub struct Arg {
     pub tid: TypeId,
     pub val: Value,
+    /// Val deliberately violates constraints of type, see `Config::malformed_prob`
+    /// and `Config::dangling_ref_prob`
+    #[serde(default)]
+    pub malformed: bool,
+    /// Alignment required by buffer that pointer arg points to
+    #[serde(default)]
+    pub align: Option<usize>,
+    /// Val holds handle of `Config::seed_resources`, which has no producer call
+    #[serde(default)]
+    pub seeded: bool,
+    /// Direction of pointer arg whose type is `InOut`, overrides direction of
+    /// type, see `mutate_ptr_dir`
+    #[serde(default)]
+    pub dir: Option<PtrDir>,
 }
 
 impl Arg {
@@ -120,6 +859,10 @@ impl Arg {
         Self {
             tid,
             val: Value::None,
+            malformed: false,
+            align: None,
+            seeded: false,
+            dir: None,
         }
     }
 
@@ -127,3 +870,552 @@ impl Arg {
         self.val.shrink()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze::static_analyze;
+    use crate::gen::{gen, Config};
+    use crate::target::Target;
+
+    #[test]
+    fn json_round_trip() {
+        let items = fots::parse_items(
+            r#"
+type fd = res<i32>
+union arg { fd fd, val u32, name *cstr }
+group J {
+    fn open(name *filename) fd
+    fn ctl(f fd, a *arg)
+    fn close(f fd)
+}
+"#,
+        )
+        .unwrap();
+        let t = Target::from(items);
+        let rs = static_analyze(&t);
+        for seed in 0..64 {
+            let conf = Config {
+                seed: Some(seed),
+                ..Default::default()
+            };
+            let p = gen(&t, &rs, &conf).unwrap();
+            assert_eq!(Prog::from_json(&p.to_json()).unwrap(), p);
+        }
+    }
+
+    #[test]
+    fn render_prog() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+flag open_flags{O_RDWR=2,O_APPEND=1024}
+group R {
+    fn open(name *filename, flags open_flags) fd
+    fn read(f fd, buf *Out [i8], count len<i32, buf>)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let g = t.iter_group().next().unwrap();
+        let (open, read) = (&g.fns[0], &g.fns[1]);
+        let open_params = open.params.as_ref().unwrap();
+        let read_params = read.params.as_ref().unwrap();
+
+        let mut p = Prog::new(g.id);
+        let c = p.add_call(Call::new(open.id));
+        c.add_arg(Arg::new(open_params[0].tid)).val = Value::Str("./a".into());
+        c.add_arg(Arg::new(open_params[1].tid)).val = Value::Num(NumValue::Signed(1026));
+        c.ret = Some(Arg::new(open.r_tid.unwrap()));
+        let c = p.add_call(Call::new(read.id));
+        c.add_arg(Arg::new(read_params[0].tid)).val = Value::Ref((0, ArgPos::Ret));
+        c.add_arg(Arg::new(read_params[1].tid)).val = Value::Group(vec![
+            Value::Num(NumValue::Unsigned(0)),
+            Value::Num(NumValue::Unsigned(0)),
+        ]);
+        c.add_arg(Arg::new(read_params[2].tid)).val = Value::Num(NumValue::Unsigned(2));
+
+        assert_eq!(
+            p.render(&t),
+            "r0 = open(&\"./a\", O_RDWR|O_APPEND)\nread(r0, &[0, 0], 2)\n"
+        );
+
+        let flags = match t.type_of(open_params[1].tid) {
+            TypeInfo::Flag { flags, .. } => flags,
+            _ => unreachable!(),
+        };
+        assert_eq!(flag_names(flags, 2).unwrap(), "O_RDWR");
+        assert_eq!(flag_names(flags, 1024).unwrap(), "O_APPEND");
+        // 0x1 is not a member
+        assert_eq!(flag_names(flags, 1027), None);
+        assert_eq!(flag_names(flags, 0), None);
+
+        // refs to ret of call without resource, or to missing call
+        p.calls[1].args[0].val = Value::Ref((1, ArgPos::Ret));
+        assert!(p.render(&t).contains("read(<bad ref 1:ret>, "));
+        p.calls[1].args[0].val = Value::Ref((5, ArgPos::Arg(2)));
+        assert!(p.render(&t).contains("read(<bad ref 5:2>, "));
+    }
+
+    #[test]
+    fn prog_eq_hash() {
+        use std::collections::hash_map::DefaultHasher;
+
+        fn hash(p: &Prog) -> u64 {
+            let mut h = DefaultHasher::new();
+            p.hash(&mut h);
+            h.finish()
+        }
+
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+group H {
+    fn open(name *filename) fd
+    fn close(f fd)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let g = t.iter_group().next().unwrap();
+        let conf = Config {
+            seed: Some(0),
+            ..Default::default()
+        };
+        let p0 = crate::gen::gen_seq(&[0, 1], g.id, &t, &conf);
+        let mut p1 = crate::gen::gen_seq(&[0, 1], g.id, &t, &conf);
+        p1.seed = Some(1);
+        assert_eq!(p0, p1);
+        assert_eq!(hash(&p0), hash(&p1));
+
+        let p2 = crate::gen::gen_seq(&[0, 0, 1], g.id, &t, &conf);
+        assert_ne!(p0, p2);
+        assert_ne!(hash(&p0), hash(&p2));
+    }
+
+    #[test]
+    fn prog_prefix_hash() {
+        let prefix = |b: ProgBuilder| {
+            b.call(0)
+                .ret_res(1)
+                .call(1)
+                .ref_to(0, ArgPos::Ret, 1)
+                .call(2)
+                .arg(Value::Num(NumValue::Unsigned(3)), 2)
+        };
+        let a = prefix(ProgBuilder::new(0)).call(3).build().unwrap();
+        let b = prefix(ProgBuilder::new(0))
+            .call(1)
+            .ref_to(0, ArgPos::Ret, 1)
+            .call(4)
+            .build()
+            .unwrap();
+        assert_eq!(a.prefix_hash(3), b.prefix_hash(3));
+        assert_eq!(a.prefix_hash(3), a.sub_prog(2).prefix_hash(3));
+        assert_ne!(a.prefix_hash(4), b.prefix_hash(4));
+        assert_ne!(a.prefix_hash(2), a.prefix_hash(3));
+
+        let mut c = a.clone();
+        c.calls[2].args[0].val = Value::Num(NumValue::Unsigned(4));
+        assert_ne!(a.prefix_hash(3), c.prefix_hash(3));
+        assert_eq!(a.prefix_hash(2), c.prefix_hash(2));
+
+        // hash is pinned, it keys persisted snapshots
+        assert_eq!(Prog::new(0).prefix_hash(0), fnv1a(&[0, 0, 0]));
+        assert_eq!(a.prefix_hash(3), 0xa6dc_b941_b64e_994b);
+    }
+
+    #[test]
+    fn edges_of_prog() {
+        let opt = Value::Opt {
+            choice: 1,
+            val: Box::new(Value::Ref((0, ArgPos::Ret))),
+        };
+        let p = ProgBuilder::new(0)
+            .call(0)
+            .ret_res(0)
+            .call(1)
+            .ref_to(0, ArgPos::Ret, 0)
+            .call(2)
+            .arg(Value::Num(NumValue::Unsigned(0)), 1)
+            .arg(Value::Group(vec![opt]), 2)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            resource_edges(&p),
+            vec![
+                ((0, ArgPos::Ret), (1, ArgPos::Arg(0))),
+                ((0, ArgPos::Ret), (2, ArgPos::Arg(1))),
+            ]
+        );
+    }
+
+    #[test]
+    fn build_prog() {
+        let p = ProgBuilder::new(1)
+            .call(0)
+            .arg(Value::Str("./a".into()), 3)
+            .ret_res(4)
+            .call(1)
+            .ref_to(0, ArgPos::Ret, 4)
+            .build()
+            .unwrap();
+        assert_eq!(p.gid, 1);
+        assert_eq!(p.len(), 2);
+        assert_eq!(p.calls[0].ret, Some(Arg::new(4)));
+        assert_eq!(p[(1, ArgPos::Arg(0))].val, Value::Ref((0, ArgPos::Ret)));
+
+        let err = ProgBuilder::new(1)
+            .call(0)
+            .ref_to(0, ArgPos::Ret, 4)
+            .build()
+            .unwrap_err();
+        assert_eq!(err.target, (0, ArgPos::Ret));
+        let err = ProgBuilder::new(1)
+            .call(0)
+            .call(1)
+            .ref_to(0, ArgPos::Ret, 4)
+            .build()
+            .unwrap_err();
+        assert_eq!(err.call, 1);
+    }
+
+    #[test]
+    fn iter_nested_args() {
+        let opt = Value::Opt {
+            choice: 0,
+            val: Box::new(Value::Num(NumValue::Unsigned(1))),
+        };
+        let nested = Value::Group(vec![Value::Str("a".into()), opt]);
+        let mut p = ProgBuilder::new(0)
+            .call(0)
+            .arg(Value::Num(NumValue::Unsigned(0)), 1)
+            .ret_res(0)
+            .call(1)
+            .arg(Value::Group(vec![nested, Value::None]), 2)
+            .build()
+            .unwrap();
+
+        let mut idx = p.iter_args().map(|(idx, _)| idx).collect::<Vec<_>>();
+        idx.dedup();
+        assert_eq!(
+            idx,
+            vec![(0, ArgPos::Arg(0)), (0, ArgPos::Ret), (1, ArgPos::Arg(0))]
+        );
+        // group, group, str, opt, num, none of second call
+        assert_eq!(p.iter_args().filter(|(idx, _)| idx.0 == 1).count(), 6);
+        assert_eq!(p.iter_args().count(), 8);
+
+        // num, ret, str, num, none
+        assert_eq!(p.iter_args_mut().count(), 5);
+        for (_, v) in p.iter_args_mut() {
+            *v = Value::Num(NumValue::Unsigned(7));
+        }
+        let nums = p
+            .iter_args()
+            .filter(|(_, v)| **v == Value::Num(NumValue::Unsigned(7)))
+            .count();
+        assert_eq!(nums, 5);
+        assert_eq!(p.iter_args().count(), 8);
+    }
+
+    #[test]
+    fn normalize_prog() {
+        let mut p = ProgBuilder::new(0)
+            .call(0)
+            .ret_res(3)
+            .call(0)
+            .ret_res(3)
+            .call(1)
+            .ref_to(1, ArgPos::Ret, 3)
+            .build()
+            .unwrap();
+        p.normalize().unwrap();
+        assert_eq!(p.calls[0].ret, None);
+        assert_eq!(p.calls[1].ret, Some(Arg::new(3)));
+
+        p.calls.remove(1);
+        assert_eq!(
+            p.normalize(),
+            Err(InvalidRef {
+                call: 1,
+                target: (1, ArgPos::Ret)
+            })
+        );
+    }
+
+    #[test]
+    fn group_and_fns_of_gen() {
+        use crate::gen::gen_seq;
+
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+group A {
+    fn getpid() i32
+}
+group B {
+    fn open(name *filename) fd
+    fn close(f fd)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let rs = static_analyze(&t);
+        let conf = Config::default();
+        for _ in 0..16 {
+            let p = gen(&t, &rs, &conf).unwrap();
+            let g = &t.groups[&p.gid];
+            let fids = p.function_ids();
+            assert_eq!(fids.len(), p.len());
+            assert!(fids.iter().all(|fid| g.fns.iter().any(|f| f.id == *fid)));
+        }
+
+        let g = t.iter_group().find(|g| g.ident == "B").unwrap();
+        let p = gen_seq(&[0, 1, 1], g.id, &t, &conf);
+        assert_eq!(p.gid, g.id);
+        assert_eq!(
+            p.function_ids(),
+            vec![g.fns[0].id, g.fns[1].id, g.fns[1].id]
+        );
+    }
+
+    #[test]
+    fn validate_prog() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+type pid = res<i32>
+union arg { v u32, p *cstr }
+group V {
+    fn open(name *filename) fd
+    fn getpid() pid
+    fn close(f fd)
+    fn ioctl(f fd, a arg, vals *[u8;(1, 4)])
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let g = t.iter_group().next().unwrap();
+        let fid = |i: usize| g.fns[i].id;
+        let param = |i: usize, k: usize| g.fns[i].params.as_ref().unwrap()[k].tid;
+        let fd = g.fns[0].r_tid.unwrap();
+        let pid = g.fns[1].r_tid.unwrap();
+        let num = |v| Value::Num(NumValue::Unsigned(v));
+        let opt = |choice| Value::Opt {
+            choice,
+            val: Box::new(num(1)),
+        };
+        let base = || {
+            ProgBuilder::new(g.id)
+                .call(fid(0))
+                .arg(Value::Str("./a".into()), param(0, 0))
+                .ret_res(fd)
+                .call(fid(1))
+                .ret_res(pid)
+        };
+
+        let p = base()
+            .call(fid(3))
+            .ref_to(0, ArgPos::Ret, fd)
+            .arg(opt(0), param(3, 1))
+            .arg(Value::Group(vec![num(1)]), param(3, 2))
+            .build()
+            .unwrap();
+        assert_eq!(validate(&p, &t), Ok(()));
+
+        let p = base()
+            .call(fid(2))
+            .ref_to(1, ArgPos::Ret, fd)
+            .build()
+            .unwrap();
+        assert_eq!(
+            validate(&p, &t),
+            Err(vec![ProgError::RefType {
+                call: 2,
+                target: (1, ArgPos::Ret)
+            }])
+        );
+
+        let mut p = base()
+            .call(fid(2))
+            .ref_to(0, ArgPos::Ret, fd)
+            .build()
+            .unwrap();
+        p.calls[2].args[0].val = Value::Ref((2, ArgPos::Ret));
+        assert_eq!(
+            validate(&p, &t),
+            Err(vec![ProgError::DanglingRef {
+                call: 2,
+                target: (2, ArgPos::Ret)
+            }])
+        );
+
+        let p = base()
+            .call(fid(3))
+            .ref_to(0, ArgPos::Ret, fd)
+            .arg(opt(2), param(3, 1))
+            .arg(Value::Group(vec![num(1); 5]), param(3, 2))
+            .build()
+            .unwrap();
+        assert_eq!(
+            validate(&p, &t),
+            Err(vec![
+                ProgError::UnionChoice {
+                    call: 2,
+                    arg: 1,
+                    choice: 2
+                },
+                ProgError::SliceLen {
+                    call: 2,
+                    arg: 2,
+                    len: 5
+                }
+            ])
+        );
+
+        let p = base().call(fid(2)).call(1000).build().unwrap();
+        assert_eq!(
+            validate(&p, &t),
+            Err(vec![
+                ProgError::Arity {
+                    call: 2,
+                    expected: 1,
+                    found: 0
+                },
+                ProgError::UnknownFn { call: 3, fid: 1000 }
+            ])
+        );
+    }
+
+    #[test]
+    fn concat_progs() {
+        let b = ProgBuilder::new(0)
+            .call(0)
+            .ret_res(1)
+            .call(1)
+            .ref_to(0, ArgPos::Ret, 1)
+            .call(2)
+            .ref_to(1, ArgPos::Arg(0), 1)
+            .build()
+            .unwrap();
+        let a = ProgBuilder::new(0)
+            .call(0)
+            .ret_res(1)
+            .call(1)
+            .ref_to(0, ArgPos::Ret, 1)
+            .build()
+            .unwrap();
+        let p = concat(a.clone(), b.clone()).unwrap();
+        assert_eq!(p.len(), 5);
+        assert_eq!(p.calls[..2], a.calls[..]);
+        assert_eq!(p.calls[3].args[0].val, Value::Ref((2, ArgPos::Ret)));
+        assert_eq!(p.calls[4].args[0].val, Value::Ref((3, ArgPos::Arg(0))));
+        assert_eq!(p.check_refs(), Ok(()));
+        assert_eq!(p.seed, None);
+
+        let c = Prog::new(1);
+        assert_eq!(
+            concat(a, c),
+            Err(ProgError::GroupMismatch {
+                expected: 0,
+                found: 1
+            })
+        );
+    }
+
+    #[test]
+    fn prog_resource_profile() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+type sock = res<i32>
+struct msg { s sock, size u32 }
+group S {
+    fn open(name *filename) fd
+    fn accept(f fd, peer *Out sock)
+    fn send(m *msg)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let g = t.iter_group().next().unwrap();
+        let fd = g.fns[0].r_tid.unwrap();
+        let sock = match t.type_of(g.fns[1].params.as_ref().unwrap()[1].tid) {
+            TypeInfo::Ptr { tid, .. } => *tid,
+            _ => unreachable!(),
+        };
+        let conf = Config {
+            seed: Some(0),
+            ..Default::default()
+        };
+
+        let p = crate::gen::gen_seq(&[0, 1], g.id, &t, &conf);
+        assert_eq!(
+            resource_profile(&p, &t),
+            (hashset! {fd, sock}, hashset! {fd})
+        );
+        let p = crate::gen::gen_seq(&[0, 1, 2], g.id, &t, &conf);
+        assert_eq!(
+            resource_profile(&p, &t),
+            (hashset! {fd, sock}, hashset! {fd, sock})
+        );
+        assert_eq!(
+            resource_profile(&Prog::new(g.id), &t),
+            (HashSet::new(), HashSet::new())
+        );
+    }
+
+    #[test]
+    fn diff_progs() {
+        let num = |v| Value::Num(NumValue::Unsigned(v));
+        let a = ProgBuilder::new(0)
+            .call(0)
+            .arg(Value::Group(vec![num(1), num(2)]), 1)
+            .arg(num(3), 2)
+            .call(1)
+            .build()
+            .unwrap();
+        assert!(diff(&a, &a).is_empty());
+
+        let mut b = a.clone();
+        b.calls[0].args[0].val = Value::Group(vec![num(1), num(5)]);
+        assert_eq!(
+            diff(&a, &b),
+            vec![ProgDiff::ChangedArg {
+                call: 0,
+                arg: 0,
+                path: vec![1],
+                from: num(2),
+                to: num(5)
+            }]
+        );
+
+        b.calls[1].fid = 2;
+        b.calls.push(Call::new(3));
+        let diffs = diff(&b, &a);
+        assert_eq!(diffs.len(), 3);
+        assert_eq!(
+            diffs[1..],
+            [
+                ProgDiff::ChangedFn {
+                    call: 1,
+                    from: 2,
+                    to: 1
+                },
+                ProgDiff::RemovedCall { call: 2, fid: 3 }
+            ]
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Syzkaller program format
+//!
+//! Render prog as text that syzkaller tools can parse, e.g.
+//! `r0 = open(&(0x7f0000000000)='./a\x00', 0x2)`. Nums, flags and lens are
+//! hex, pointees are placed at increasing addresses of data area, strs are
+//! quoted and byte slices are hex strings. Out buffers are only rendered as
+//! their size, resources produced by out pointers are marked as `<r0=>`.
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use fots::types::{PtrDir, StrType, TypeId, TypeInfo};
+
+use crate::encode::value_size;
+use crate::prog::{res_name, res_names, ArgIndex, ArgPos, Prog};
+use crate::target::Target;
+use crate::value::{NumValue, Value};
+
+/// Address of data area of syzkaller
+const DATA_START: u64 = 0x7f00_0000_0000;
+/// Pointees are placed at multiples of it
+const DATA_ALIGN: u64 = 0x40;
+
+pub fn to_syz(p: &Prog, t: &Target) -> String {
+    let mut s = Syz {
+        t,
+        names: res_names(p, t),
+        addr: DATA_START,
+        out_name: None,
+    };
+    let mut buf = String::new();
+    for (i, c) in p.calls.iter().enumerate() {
+        if let Some(name) = s.names.get(&(i, ArgPos::Ret)) {
+            buf.push_str(name);
+            buf.push_str(" = ");
+        }
+        let args = c
+            .args
+            .iter()
+            .enumerate()
+            .map(|(j, a)| {
+                s.out_name = s.names.get(&(i, ArgPos::Arg(j))).cloned();
+                let arg = s.val(a.tid, &a.val, a.dir);
+                s.out_name = None;
+                arg
+            })
+            .collect::<Vec<_>>();
+        writeln!(buf, "{}({})", t.fn_of(c.fid).dec_name, args.join(", ")).unwrap();
+    }
+    buf
+}
+
+struct Syz<'a> {
+    t: &'a Target,
+    names: HashMap<ArgIndex, String>,
+    // address of next pointee
+    addr: u64,
+    // name of resource produced by arg being rendered, given to its first resource
+    out_name: Option<String>,
+}
+
+impl<'a> Syz<'a> {
+    fn val(&mut self, tid: TypeId, val: &Value, dir: Option<PtrDir>) -> String {
+        let t = self.t;
+        if let Value::Ref(idx) = val {
+            return res_name(&self.names, idx);
+        }
+        if t.is_res(tid) {
+            if let Some(name) = self.out_name.take() {
+                return format!("<{}=>{}", name, self.val(tid, val, dir));
+            }
+        }
+        match (t.type_of(tid), val) {
+            (TypeInfo::Ptr { .. }, Value::None) => "0x0".into(),
+            (
+                TypeInfo::Ptr {
+                    dir: ty_dir, tid, ..
+                },
+                val,
+            ) => {
+                let size = value_size(val, *tid, t) as u64;
+                let addr = self.addr;
+                self.addr += size.max(1).next_multiple_of(DATA_ALIGN);
+                let data = if dir.unwrap_or(*ty_dir) == PtrDir::Out && is_buf(*tid, t) {
+                    format!("\"\"/{}", size)
+                } else {
+                    self.val(*tid, val, None)
+                };
+                format!("&({:#x})={}", addr, data)
+            }
+            (TypeInfo::Alias { tid, .. }, val)
+            | (TypeInfo::Res { tid }, val)
+            | (TypeInfo::Len { tid, .. }, val) => self.val(*tid, val, dir),
+            (TypeInfo::Str { str_type, .. }, Value::Str(s)) => {
+                let mut lit = escape(s.as_bytes());
+                if *str_type != StrType::Str {
+                    lit.push_str("\\x00");
+                }
+                format!("'{}'", lit)
+            }
+            (TypeInfo::Slice { tid, .. }, Value::Group(vals)) if is_byte(*tid, t) => {
+                let bytes = vals
+                    .iter()
+                    .map(|v| format!("{:02x}", num_bits(v) as u8))
+                    .collect::<String>();
+                format!("\"{}\"", bytes)
+            }
+            (TypeInfo::Slice { tid, .. }, Value::Group(vals)) => {
+                let vals = vals
+                    .iter()
+                    .map(|v| self.val(*tid, v, None))
+                    .collect::<Vec<_>>();
+                format!("[{}]", vals.join(", "))
+            }
+            (TypeInfo::Struct { fields, .. }, Value::Group(vals)) => {
+                let vals = fields
+                    .iter()
+                    .zip(vals.iter())
+                    .filter(|(_, v)| **v != Value::Absent)
+                    .map(|(f, v)| self.val(f.tid, v, None))
+                    .collect::<Vec<_>>();
+                format!("{{{}}}", vals.join(", "))
+            }
+            (TypeInfo::Union { fields, .. }, Value::Opt { choice, val }) => {
+                let f = &fields[*choice];
+                format!("@{}={}", f.ident, self.val(f.tid, val, None))
+            }
+            (_, val) => format!("{:#x}", num_bits(val)),
+        }
+    }
+}
+
+/// Whether out pointer to type tid is a plain buffer, rendered as its size only
+fn is_buf(tid: TypeId, t: &Target) -> bool {
+    match t.type_of(tid) {
+        TypeInfo::Alias { tid, .. } => is_buf(*tid, t),
+        TypeInfo::Str { .. } => true,
+        TypeInfo::Slice { tid, .. } => is_byte(*tid, t),
+        _ => false,
+    }
+}
+
+fn is_byte(tid: TypeId, t: &Target) -> bool {
+    match t.type_of(tid) {
+        TypeInfo::Alias { tid, .. } => is_byte(*tid, t),
+        TypeInfo::Num(info) => info.bit_width() == 8,
+        _ => false,
+    }
+}
+
+/// Two's complement bits of num, 0 for other values
+fn num_bits(val: &Value) -> u64 {
+    match val {
+        Value::Num(NumValue::Signed(v)) => *v as u64,
+        Value::Num(NumValue::Unsigned(v)) => *v,
+        _ => 0,
+    }
+}
+
+/// Content of single-quoted str, quote, backslash and non-printable bytes are hex escaped
+fn escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            b'\'' | b'\\' => format!("\\x{:02x}", b),
+            0x20..=0x7e => (b as char).to_string(),
+            _ => format!("\\x{:02x}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prog::{Arg, Call};
+
+    #[test]
+    fn syz_prog() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+flag open_flags{O_RDWR=2,O_APPEND=1024}
+struct iov { base *[i8], size len<usize, base> }
+union ctl { num u32, vec iov }
+group S {
+    fn open(name *filename, flags open_flags) fd
+    fn read(f fd, buf *Out [i8], count len<i32, buf>)
+    fn pipe(fds *Out fd)
+    fn ioctl(f fd, arg *ctl, mode i32)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let g = t.iter_group().next().unwrap();
+        let params = |i: usize| {
+            g.fns[i]
+                .params
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|p| p.tid)
+                .collect::<Vec<_>>()
+        };
+        let num = |v| Value::Num(NumValue::Unsigned(v));
+
+        let mut p = Prog::new(g.id);
+        let c = p.add_call(Call::new(g.fns[0].id));
+        c.add_arg(Arg::new(params(0)[0])).val = Value::Str("./a".into());
+        c.add_arg(Arg::new(params(0)[1])).val = Value::Num(NumValue::Signed(1026));
+        c.ret = Some(Arg::new(g.fns[0].r_tid.unwrap()));
+        let c = p.add_call(Call::new(g.fns[1].id));
+        c.add_arg(Arg::new(params(1)[0])).val = Value::Ref((0, ArgPos::Ret));
+        c.add_arg(Arg::new(params(1)[1])).val = Value::Group(vec![num(0), num(0)]);
+        c.add_arg(Arg::new(params(1)[2])).val = num(2);
+        let c = p.add_call(Call::new(g.fns[2].id));
+        c.add_arg(Arg::new(params(2)[0])).val = num(0);
+        let c = p.add_call(Call::new(g.fns[3].id));
+        c.add_arg(Arg::new(params(3)[0])).val = Value::Ref((2, ArgPos::Arg(0)));
+        c.add_arg(Arg::new(params(3)[1])).val = Value::Opt {
+            choice: 1,
+            val: Box::new(Value::Group(vec![
+                Value::Group(vec![num(1), num(0xab)]),
+                num(2),
+            ])),
+        };
+        c.add_arg(Arg::new(params(3)[2])).val = Value::Num(NumValue::Signed(-1));
+
+        assert_eq!(
+            p.to_syz(&t),
+            "r0 = open(&(0x7f0000000000)='./a\\x00', 0x402)\n\
+             read(r0, &(0x7f0000000040)=\"\"/2, 0x2)\n\
+             pipe(&(0x7f0000000080)=<r1=>0x0)\n\
+             ioctl(r1, &(0x7f00000000c0)=@vec={&(0x7f0000000100)=\"01ab\", 0x2}, 0xffffffffffffffff)\n"
+        );
+    }
+
+    #[test]
+    fn syz_bad_ref() {
+        let t = Target::from(fots::parse_items("fn close(f i32)").unwrap());
+        let g = t.iter_group().next().unwrap();
+        let mut p = Prog::new(g.id);
+        let tid = g.fns[0].params.as_ref().unwrap()[0].tid;
+        let c = p.add_call(Call::new(g.fns[0].id));
+        c.add_arg(Arg::new(tid)).val = Value::Ref((0, ArgPos::Ret));
+        assert_eq!(p.to_syz(&t), "close(<bad ref 0:ret>)\n");
+    }
+
+    #[test]
+    fn syz_escape() {
+        assert_eq!(escape(b"a'b\\c\n"), "a\\x27b\\x5cc\\x0a");
+    }
+}
@@ -1,15 +1,75 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
 
-use fots::types::{Field, FnId, FnInfo, Group, GroupId, Items, NumInfo, TypeId, TypeInfo};
+use fots::types::{
+    Field, FnId, FnInfo, Group, GroupId, Items, LenKind, NumInfo, NumLimit, PtrDir, TypeId,
+    TypeInfo,
+};
 use std::ptr::NonNull;
 
+use crate::encode::Endian;
+
 pub struct Target {
     pub types: HashMap<TypeId, TypeInfo>,
     pub groups: HashMap<GroupId, Group>,
     pub fns: HashMap<FnId, NonNull<FnInfo>>,
+    /// Byte order of nums of the arch, little-endian unless set after building
+    pub endian: Endian,
+    /// Resource types and alias of them
+    res: HashSet<TypeId>,
+}
+
+/// Error of building target from items
+#[derive(Debug)]
+pub enum TargetError {
+    /// Type referenced by param or return of fn not exists
+    FnType { fn_name: String, tid: TypeId },
+    /// Type referenced by other type not exists
+    Type { by: TypeId, tid: TypeId },
 }
 
+impl Display for TargetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            TargetError::FnType { fn_name, tid } => {
+                write!(f, "Type {} referenced by fn {} not exists", tid, fn_name)
+            }
+            TargetError::Type { by, tid } => {
+                write!(f, "Type {} referenced by type {} not exists", tid, by)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TargetError {}
+
+/// Resources that can only be produced by calls consuming each other
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceCycle(pub Vec<TypeId>);
+
 impl Target {
+    /// Build target from items, check that every referenced type exists
+    pub fn from_fots(items: Items) -> Result<Self, TargetError> {
+        let tids = items.types.iter().map(|t| t.tid).collect::<HashSet<_>>();
+        for t in items.types.iter() {
+            for tid in sub_types(&t.info) {
+                if !tids.contains(&tid) {
+                    return Err(TargetError::Type { by: t.tid, tid });
+                }
+            }
+        }
+        for f in items.groups.iter().flat_map(|g| g.iter_fn()) {
+            let param_tids = f.params.iter().flatten().map(|p| p.tid);
+            for tid in param_tids.chain(f.r_tid) {
+                if !tids.contains(&tid) {
+                    let fn_name = f.dec_name.clone();
+                    return Err(TargetError::FnType { fn_name, tid });
+                }
+            }
+        }
+        Ok(Self::from(items))
+    }
+
     pub fn from(items: Items) -> Self {
         let mut types = items
             .types
@@ -28,14 +88,52 @@ impl Target {
             .flat_map(|g| g.iter_fn().map(|f| (f.id, NonNull::from(f))))
             .collect();
         fns.shrink_to_fit();
+        let res = types
+            .keys()
+            .filter(|&&tid| is_res(tid, &types))
+            .cloned()
+            .collect();
 
-        Target { groups, types, fns }
+        Target {
+            groups,
+            types,
+            fns,
+            endian: Endian::default(),
+            res,
+        }
     }
 
     pub fn type_of(&self, tid: TypeId) -> &TypeInfo {
         &self.types.get(&tid).unwrap()
     }
 
+    /// Checked version of type_of, returns None if tid not exists
+    pub fn get_type(&self, tid: TypeId) -> Option<&TypeInfo> {
+        self.types.get(&tid)
+    }
+
+    /// First type that is reachable from params or return of f but not exists
+    pub fn unknown_type_of(&self, f: &FnInfo) -> Option<TypeId> {
+        let mut visited = HashSet::new();
+        let mut stack = f
+            .params
+            .iter()
+            .flatten()
+            .map(|p| p.tid)
+            .chain(f.r_tid)
+            .collect::<Vec<_>>();
+        while let Some(tid) = stack.pop() {
+            if !visited.insert(tid) {
+                continue;
+            }
+            match self.get_type(tid) {
+                Some(info) => stack.extend(sub_types(info)),
+                None => return Some(tid),
+            }
+        }
+        None
+    }
+
     pub fn fn_of(&self, fid: FnId) -> &FnInfo {
         unsafe { self.fns[&fid].as_ref() }
     }
@@ -45,11 +143,7 @@ impl Target {
     }
 
     pub fn is_res(&self, tid: TypeId) -> bool {
-        match self.type_of(tid) {
-            TypeInfo::Alias { tid, .. } => self.is_res(*tid),
-            TypeInfo::Res { .. } => true,
-            _ => false,
-        }
+        self.res.contains(&tid)
     }
 
     pub fn is_str(&self, tid: TypeId) -> bool {
@@ -84,6 +178,14 @@ impl Target {
         }
     }
 
+    pub fn len_kind_of(&self, tid: TypeId) -> Option<LenKind> {
+        match self.type_of(tid) {
+            TypeInfo::Alias { tid, .. } => self.len_kind_of(*tid),
+            TypeInfo::Len { kind, .. } => Some(*kind),
+            _ => None,
+        }
+    }
+
     pub fn num_info_of(&self, tid: TypeId) -> Option<&NumInfo> {
         match self.type_of(tid) {
             TypeInfo::Alias { tid, .. } => self.num_info_of(*tid),
@@ -92,9 +194,31 @@ impl Target {
         }
     }
 
+    /// Whether tid is num type that only has one value, value of such type
+    /// is never changed by mutation or minimization
+    pub fn is_const(&self, tid: TypeId) -> bool {
+        fn is_single<T>(l: &NumLimit<T>) -> bool {
+            matches!(l, NumLimit::Vals(vals) if vals.len() == 1)
+        }
+
+        match self.num_info_of(tid) {
+            Some(NumInfo::I8(l)) => is_single(l),
+            Some(NumInfo::I16(l)) => is_single(l),
+            Some(NumInfo::I32(l)) => is_single(l),
+            Some(NumInfo::I64(l)) => is_single(l),
+            Some(NumInfo::U8(l)) => is_single(l),
+            Some(NumInfo::U16(l)) => is_single(l),
+            Some(NumInfo::U32(l)) => is_single(l),
+            Some(NumInfo::U64(l)) => is_single(l),
+            Some(NumInfo::Usize(l)) => is_single(l),
+            Some(NumInfo::Isize(l)) => is_single(l),
+            None => false,
+        }
+    }
+
     pub fn struct_info_of(&self, tid: TypeId) -> Option<(&str, &[Field])> {
         match self.type_of(tid) {
-            TypeInfo::Struct { fields, ident } => Some((ident, fields)),
+            TypeInfo::Struct { fields, ident, .. } => Some((ident, fields)),
             TypeInfo::Alias { tid, .. } => self.struct_info_of(*tid),
             _ => None,
         }
@@ -107,8 +231,225 @@ impl Target {
     pub fn group_name_of(&self, gid: GroupId) -> &str {
         &self.groups[&gid].ident
     }
+
+    /// Check that every resource that has producers can be produced.
+    ///
+    /// Resources without any producer are generated from their under type,
+    /// so they are not reported.
+    pub fn validate_resources(&self) -> Result<(), Vec<ResourceCycle>> {
+        // consumed and produced resources of each fn
+        let uses = self
+            .fns
+            .keys()
+            .map(|&fid| self.res_use_of(self.fn_of(fid)))
+            .collect::<Vec<_>>();
+
+        let mut producible = self.res.clone();
+        for (_, produced) in uses.iter() {
+            for tid in produced.iter() {
+                producible.remove(tid);
+            }
+        }
+        loop {
+            let mut changed = false;
+            for (consumed, produced) in uses.iter() {
+                if consumed.iter().all(|tid| producible.contains(tid)) {
+                    for tid in produced.iter() {
+                        changed |= producible.insert(*tid);
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        let mut stuck = self
+            .res
+            .difference(&producible)
+            .cloned()
+            .collect::<Vec<_>>();
+        stuck.sort();
+        let mut cycles = Vec::new();
+        let mut reported = HashSet::new();
+        for &r in stuck.iter() {
+            if reported.contains(&r) {
+                continue;
+            }
+            // every producer of stuck resource consumes some stuck resource
+            let mut path = vec![r];
+            let mut crt = r;
+            loop {
+                let next = uses
+                    .iter()
+                    .filter(|(_, produced)| produced.contains(&crt))
+                    .flat_map(|(consumed, _)| consumed.iter())
+                    .filter(|tid| !producible.contains(tid))
+                    .min()
+                    .cloned()
+                    .unwrap();
+                if let Some(i) = path.iter().position(|&tid| tid == next) {
+                    let cycle = path.split_off(i);
+                    if cycle.iter().all(|tid| !reported.contains(tid)) {
+                        reported.extend(cycle.iter().cloned());
+                        cycles.push(ResourceCycle(cycle));
+                    }
+                    break;
+                }
+                if reported.contains(&next) {
+                    break;
+                }
+                path.push(next);
+                crt = next;
+            }
+            reported.extend(path);
+        }
+
+        if cycles.is_empty() {
+            Ok(())
+        } else {
+            Err(cycles)
+        }
+    }
+
+    /// Resources consumed and produced by params and return of f
+    pub(crate) fn res_use_of(&self, f: &FnInfo) -> (Vec<TypeId>, Vec<TypeId>) {
+        let mut consumed = Vec::new();
+        let mut produced = Vec::new();
+        for p in f.params.iter().flatten() {
+            match self.type_of(p.tid) {
+                TypeInfo::Ptr { tid, dir, .. } if self.is_res(*tid) => {
+                    if *dir == PtrDir::In {
+                        consumed.push(*tid)
+                    } else {
+                        produced.push(*tid)
+                    }
+                }
+                _ if self.is_res(p.tid) => consumed.push(p.tid),
+                _ => (),
+            }
+        }
+        if let Some(tid) = f.r_tid {
+            if self.is_res(tid) {
+                produced.push(tid);
+            }
+        }
+        (consumed, produced)
+    }
+}
+
+fn is_res(tid: TypeId, types: &HashMap<TypeId, TypeInfo>) -> bool {
+    match types.get(&tid) {
+        Some(TypeInfo::Alias { tid, .. }) => is_res(*tid, types),
+        Some(TypeInfo::Res { .. }) => true,
+        _ => false,
+    }
+}
+
+/// Types that are directly referenced by type info
+fn sub_types(info: &TypeInfo) -> Vec<TypeId> {
+    match info {
+        TypeInfo::Ptr { tid, .. }
+        | TypeInfo::Slice { tid, .. }
+        | TypeInfo::Alias { tid, .. }
+        | TypeInfo::Res { tid }
+        | TypeInfo::Len { tid, .. } => vec![*tid],
+        TypeInfo::Struct { fields, .. } | TypeInfo::Union { fields, .. } => {
+            fields.iter().map(|f| f.tid).collect()
+        }
+        TypeInfo::Num(_) | TypeInfo::Str { .. } | TypeInfo::Flag { .. } => Vec::new(),
+    }
 }
 
 // TODO
 unsafe impl Send for Target {}
 unsafe impl Sync for Target {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_from_fots() {
+        let items = fots::parse_items(
+            r#"
+type fd = res<i32>
+type sock = fd
+group T {
+    fn socket(domain u16) sock
+    fn close(f fd)
+    fn getuid() u32
+}
+"#,
+        )
+        .unwrap();
+        let t = Target::from_fots(items.clone()).unwrap();
+        let g = t.iter_group().next().unwrap();
+        let socket = &g.fns[0];
+        let sock = socket.r_tid.unwrap();
+        let domain = socket.params.as_ref().unwrap()[0].tid;
+        assert!(t.is_res(sock));
+        assert!(t.is_res(g.fns[1].params.as_ref().unwrap()[0].tid));
+        assert!(!t.is_res(domain));
+        assert!(t.is_num(domain));
+        match t.type_of(sock) {
+            TypeInfo::Alias { ident, .. } => assert_eq!(ident, "sock"),
+            info => panic!("unexpected type: {}", info),
+        }
+
+        let mut items = items;
+        items.types.retain(|t| t.tid != domain);
+        match Target::from_fots(items) {
+            Err(TargetError::FnType { fn_name, tid }) => {
+                assert_eq!(fn_name, "socket");
+                assert_eq!(tid, domain);
+            }
+            _ => panic!("unresolved type not reported"),
+        }
+    }
+
+    #[test]
+    fn resource_cycle() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+type key = res<u32>
+type sess = res<u64>
+group C {
+    fn open(name *filename) fd
+    fn login(f fd, s sess) key
+    fn start(k key) sess
+    fn close(f fd)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let cycles = t.validate_resources().unwrap_err();
+        assert_eq!(cycles.len(), 1);
+        let g = t.iter_group().next().unwrap();
+        let key = g.fns[1].r_tid.unwrap();
+        let sess = g.fns[2].r_tid.unwrap();
+        let mut cycle = cycles[0].0.clone();
+        cycle.sort();
+        let mut expected = vec![key, sess];
+        expected.sort();
+        assert_eq!(cycle, expected);
+
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+group O {
+    fn open(name *filename) fd
+    fn dup(f fd) fd
+    fn sync()
+}
+"#,
+            )
+            .unwrap(),
+        );
+        assert!(t.validate_resources().is_ok());
+    }
+}
@@ -1,7 +1,9 @@
+use std::sync::Arc;
+
 use rand::prelude::SliceRandom;
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
-use fots::types::{TypeId, TypeInfo};
+use fots::types::{NumInfo, TypeId, TypeInfo};
 
 use crate::gen::gen_slice_len;
 use crate::prog::ArgIndex;
@@ -12,8 +14,9 @@ use crate::target::Target;
 pub enum Value {
     /// Value that stores num, both signed and unsigned but not bigger then 8 bytes
     Num(NumValue),
-    /// Value that stores utf-8 encoded value
-    Str(String),
+    /// Value that stores utf-8 encoded value, shared so that same strs of
+    /// different progs can be interned, see `Corpus`
+    Str(Arc<str>),
     /// Combined value
     Group(Vec<Value>),
     /// Value for union
@@ -22,6 +25,8 @@ pub enum Value {
     Ref(ArgIndex),
     /// Nothing
     None,
+    /// Absent optional field of struct, takes no space unlike null pointer
+    Absent,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -31,6 +36,38 @@ pub enum NumValue {
 }
 
 impl NumValue {
+    /// Wrap value around to width of info.
+    ///
+    /// Value is taken as 64-bit two's complement and truncated to the width of
+    /// info, result is sign-extended for signed info and zero-extended for unsigned
+    /// one, e.g. signed -1 as u8 is 255 and unsigned 200 as i8 is -56.
+    pub fn truncate_to(&self, info: &NumInfo) -> NumValue {
+        let bits = match self {
+            NumValue::Signed(v) => *v as u64,
+            NumValue::Unsigned(v) => *v,
+        };
+        match info {
+            NumInfo::I8(_) => NumValue::Signed(bits as i8 as i64),
+            NumInfo::I16(_) => NumValue::Signed(bits as i16 as i64),
+            NumInfo::I32(_) => NumValue::Signed(bits as i32 as i64),
+            NumInfo::I64(_) | NumInfo::Isize(_) => NumValue::Signed(bits as i64),
+            NumInfo::U8(_) => NumValue::Unsigned(bits as u8 as u64),
+            NumInfo::U16(_) => NumValue::Unsigned(bits as u16 as u64),
+            NumInfo::U32(_) => NumValue::Unsigned(bits as u32 as u64),
+            NumInfo::U64(_) | NumInfo::Usize(_) => NumValue::Unsigned(bits),
+        }
+    }
+
+    /// Truncate value to its low bits of bitfield of given width, signed value is
+    /// sign-extended from the top bit of bitfield
+    pub fn truncate_bits(&self, bits: u8) -> NumValue {
+        let shift = 64 - u32::from(bits.clamp(1, 64));
+        match self {
+            NumValue::Signed(v) => NumValue::Signed((v << shift) >> shift),
+            NumValue::Unsigned(v) => NumValue::Unsigned((v << shift) >> shift),
+        }
+    }
+
     pub fn literal(&self) -> String {
         match self {
             NumValue::Signed(v) => format!("{}", v),
@@ -41,26 +78,25 @@ impl NumValue {
 
 #[allow(clippy::len_without_is_empty)]
 impl Value {
-    pub fn default_val(tid: TypeId, t: &Target) -> Value {
+    pub fn default_val<R: Rng + ?Sized>(tid: TypeId, t: &Target, rng: &mut R) -> Value {
         use NumValue::*;
 
-        let mut rng = thread_rng();
         match t.type_of(tid) {
             TypeInfo::Num(..) => Value::Num(Unsigned(0)),
             TypeInfo::Ptr { .. } => Value::None,
             TypeInfo::Slice { tid, l, h } => {
-                let len: usize = gen_slice_len(*l, *h);
+                let len: usize = gen_slice_len(*l, *h, rng);
                 let mut vals = Vec::new();
                 for _ in 0..len {
-                    vals.push(Value::default_val(*tid, t));
+                    vals.push(Value::default_val(*tid, t, rng));
                 }
                 Value::Group(vals)
             }
-            TypeInfo::Str { .. } => Value::Str(String::new()),
+            TypeInfo::Str { .. } => Value::Str("".into()),
             TypeInfo::Struct { fields, .. } => {
                 let mut vals = Vec::new();
                 for field in fields.iter() {
-                    vals.push(Value::default_val(field.tid, t));
+                    vals.push(Value::default_val(field.tid, t, rng));
                 }
                 Value::Group(vals)
             }
@@ -69,22 +105,25 @@ impl Value {
                 let field = &fields[field_i];
                 Value::Opt {
                     choice: field_i,
-                    val: Box::new(Value::default_val(field.tid, t)),
+                    val: Box::new(Value::default_val(field.tid, t, rng)),
                 }
             }
-            TypeInfo::Flag { flags, .. } => {
-                let flag_val = flags.choose(&mut thread_rng()).unwrap();
+            TypeInfo::Flag {
+                flags, required, ..
+            } => {
+                let flag_val = required.choose(rng).or_else(|| flags.choose(rng)).unwrap();
                 Value::Num(NumValue::Signed(flag_val.val))
             }
-            TypeInfo::Alias { tid, .. } => Value::default_val(*tid, t),
-            TypeInfo::Res { tid } => Value::default_val(*tid, t),
+            TypeInfo::Alias { tid, .. } => Value::default_val(*tid, t, rng),
+            TypeInfo::Res { tid } => Value::default_val(*tid, t, rng),
             TypeInfo::Len { .. } => Value::Num(NumValue::Unsigned(0)),
         }
     }
 
+    /// Element count of group or utf-8 byte count of str
     pub fn len(&self) -> Option<usize> {
         match self {
-            Value::Str(s) => Some(s.len()),
+            Value::Str(_) => self.byte_len(),
             Value::Group(g) => Some(g.len()),
             _ => None,
         }
@@ -93,7 +132,7 @@ impl Value {
     pub fn literal(&self) -> String {
         match self {
             Value::Num(n) => n.literal(),
-            Value::Str(s) => s.clone(),
+            Value::Str(s) => s.to_string(),
             Value::Group(vals) => {
                 use std::fmt::Write;
                 let mut buf = String::new();
@@ -111,9 +150,45 @@ impl Value {
             Value::Opt { val, .. } => val.literal(),
             Value::Ref(_) => unreachable!(),
             Value::None => "NULL".into(),
+            // storage of field is zeroed when struct has no variable layout
+            Value::Absent => "0".into(),
         }
     }
 
+    /// Utf-8 byte count of str, which is what len type counts, not count of chars
+    pub fn byte_len(&self) -> Option<usize> {
+        match self {
+            Value::Str(s) => Some(s.len()),
+            _ => None,
+        }
+    }
+
+    /// Iterate over self and all values nested in groups and opts, in depth-first order
+    pub fn iter_vals(&self) -> impl Iterator<Item = &Value> + '_ {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let val = stack.pop()?;
+            match val {
+                Value::Group(vals) => stack.extend(vals.iter().rev()),
+                Value::Opt { val, .. } => stack.push(val),
+                _ => (),
+            }
+            Some(val)
+        })
+    }
+
+    /// Mutable values nested in self that hold no other values, in depth-first order
+    pub fn iter_leaves_mut(&mut self) -> impl Iterator<Item = &mut Value> + '_ {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || loop {
+            match stack.pop()? {
+                Value::Group(vals) => stack.extend(vals.iter_mut().rev()),
+                Value::Opt { val, .. } => stack.push(val),
+                val => return Some(val),
+            }
+        })
+    }
+
     pub fn shrink(&mut self) {
         if let Value::Group(v) = self {
             v.shrink_to_fit()
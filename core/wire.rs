@@ -0,0 +1,456 @@
+//! Wire format of prog
+//!
+//! Compact binary form used to ship progs between fuzzer and executors.
+//! Unsigned ints are LEB128 varints, signed ones are zigzag encoded first,
+//! strs and seqs are prefixed with their length and every value starts with
+//! one byte tag of its kind.
+//!
+//! Prog is `gid seed? call_count call*`, call is `fid arg_count arg* ret?`
+//! and arg is `tid attrs align? val`, where attrs packs flags of arg.
+use std::fmt;
+
+use fots::types::PtrDir;
+
+use crate::prog::{Arg, ArgPos, Call, Prog};
+use crate::value::{NumValue, Value};
+
+const TAG_NONE: u8 = 0;
+const TAG_SIGNED: u8 = 1;
+const TAG_UNSIGNED: u8 = 2;
+const TAG_STR: u8 = 3;
+const TAG_GROUP: u8 = 4;
+const TAG_OPT: u8 = 5;
+const TAG_REF: u8 = 6;
+const TAG_ABSENT: u8 = 7;
+
+/// Bits of attrs byte of arg, direction takes the two bits above them
+const ATTR_MALFORMED: u8 = 1;
+const ATTR_SEEDED: u8 = 1 << 1;
+const ATTR_ALIGN: u8 = 1 << 2;
+const ATTR_DIR_SHIFT: u8 = 3;
+
+/// Max nesting depth of decoded values, deeper input is rejected instead of
+/// exhausting stack
+const MAX_DEPTH: usize = 256;
+
+/// Error of decoding prog from wire format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WireError {
+    /// Input ends in the middle of prog
+    Eof,
+    /// Varint does not fit in u64
+    Overflow { offset: usize },
+    /// Unknown tag of value or flag of optional field
+    Tag { offset: usize, tag: u8 },
+    /// Str is not valid utf-8
+    Utf8 { offset: usize },
+    /// Bytes left after prog
+    Trailing { offset: usize },
+    /// Values nested deeper than `MAX_DEPTH`
+    Depth { offset: usize },
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireError::Eof => write!(f, "unexpected end of input"),
+            WireError::Overflow { offset } => write!(f, "varint overflow at {}", offset),
+            WireError::Tag { offset, tag } => write!(f, "invalid tag {} at {}", tag, offset),
+            WireError::Utf8 { offset } => write!(f, "invalid utf-8 str at {}", offset),
+            WireError::Trailing { offset } => write!(f, "trailing bytes at {}", offset),
+            WireError::Depth { offset } => write!(f, "values nested too deep at {}", offset),
+        }
+    }
+}
+
+impl std::error::Error for WireError {}
+
+pub fn encode(p: &Prog) -> Vec<u8> {
+    let mut buf = Vec::new();
+    put_uint(&mut buf, p.gid as u64);
+    match p.seed {
+        Some(seed) => {
+            buf.push(1);
+            put_uint(&mut buf, seed);
+        }
+        None => buf.push(0),
+    }
+    put_uint(&mut buf, p.calls.len() as u64);
+    for c in p.calls.iter() {
+        put_uint(&mut buf, c.fid as u64);
+        put_uint(&mut buf, c.args.len() as u64);
+        for a in c.args.iter() {
+            put_arg(&mut buf, a);
+        }
+        match &c.ret {
+            Some(a) => {
+                buf.push(1);
+                put_arg(&mut buf, a);
+            }
+            None => buf.push(0),
+        }
+    }
+    buf
+}
+
+pub fn decode(buf: &[u8]) -> Result<Prog, WireError> {
+    let mut r = Reader {
+        buf,
+        offset: 0,
+        depth: 0,
+    };
+    let mut p = Prog::new(r.uint()? as usize);
+    if r.flag()? {
+        p.seed = Some(r.uint()?);
+    }
+    for _ in 0..r.uint()? {
+        let mut c = Call::new(r.uint()? as usize);
+        for _ in 0..r.uint()? {
+            c.args.push(r.arg()?);
+        }
+        if r.flag()? {
+            c.ret = Some(r.arg()?);
+        }
+        p.calls.push(c);
+    }
+    if r.offset != buf.len() {
+        return Err(WireError::Trailing { offset: r.offset });
+    }
+    Ok(p)
+}
+
+fn put_uint(buf: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        buf.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+fn put_int(buf: &mut Vec<u8>, v: i64) {
+    put_uint(buf, ((v << 1) ^ (v >> 63)) as u64)
+}
+
+fn put_arg(buf: &mut Vec<u8>, a: &Arg) {
+    put_uint(buf, a.tid);
+    let mut attrs = 0;
+    if a.malformed {
+        attrs |= ATTR_MALFORMED;
+    }
+    if a.seeded {
+        attrs |= ATTR_SEEDED;
+    }
+    if a.align.is_some() {
+        attrs |= ATTR_ALIGN;
+    }
+    let dir = match a.dir {
+        None => 0,
+        Some(PtrDir::In) => 1,
+        Some(PtrDir::Out) => 2,
+        Some(PtrDir::InOut) => 3,
+    };
+    buf.push(attrs | dir << ATTR_DIR_SHIFT);
+    if let Some(align) = a.align {
+        put_uint(buf, align as u64);
+    }
+    put_val(buf, &a.val);
+}
+
+fn put_val(buf: &mut Vec<u8>, val: &Value) {
+    match val {
+        Value::None => buf.push(TAG_NONE),
+        Value::Absent => buf.push(TAG_ABSENT),
+        Value::Num(NumValue::Signed(v)) => {
+            buf.push(TAG_SIGNED);
+            put_int(buf, *v);
+        }
+        Value::Num(NumValue::Unsigned(v)) => {
+            buf.push(TAG_UNSIGNED);
+            put_uint(buf, *v);
+        }
+        Value::Str(s) => {
+            buf.push(TAG_STR);
+            put_uint(buf, s.len() as u64);
+            buf.extend_from_slice(s.as_bytes());
+        }
+        Value::Group(vals) => {
+            buf.push(TAG_GROUP);
+            put_uint(buf, vals.len() as u64);
+            for v in vals.iter() {
+                put_val(buf, v);
+            }
+        }
+        Value::Opt { choice, val } => {
+            buf.push(TAG_OPT);
+            put_uint(buf, *choice as u64);
+            put_val(buf, val);
+        }
+        Value::Ref((cid, pos)) => {
+            buf.push(TAG_REF);
+            put_uint(buf, *cid as u64);
+            // 0 for ret, i + 1 for i-th arg
+            match pos {
+                ArgPos::Ret => put_uint(buf, 0),
+                ArgPos::Arg(i) => put_uint(buf, *i as u64 + 1),
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    offset: usize,
+    // nesting depth of value being decoded
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, WireError> {
+        let b = *self.buf.get(self.offset).ok_or(WireError::Eof)?;
+        self.offset += 1;
+        Ok(b)
+    }
+
+    fn flag(&mut self) -> Result<bool, WireError> {
+        let offset = self.offset;
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(WireError::Tag { offset, tag }),
+        }
+    }
+
+    fn uint(&mut self) -> Result<u64, WireError> {
+        let offset = self.offset;
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            if shift == 63 && b > 1 {
+                return Err(WireError::Overflow { offset });
+            }
+            v |= u64::from(b & 0x7f) << shift;
+            if b & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err(WireError::Overflow { offset })
+    }
+
+    fn int(&mut self) -> Result<i64, WireError> {
+        let v = self.uint()?;
+        Ok((v >> 1) as i64 ^ -((v & 1) as i64))
+    }
+
+    fn arg(&mut self) -> Result<Arg, WireError> {
+        let mut a = Arg::new(self.uint()?);
+        let offset = self.offset;
+        let attrs = self.byte()?;
+        if attrs >> (ATTR_DIR_SHIFT + 2) != 0 {
+            return Err(WireError::Tag { offset, tag: attrs });
+        }
+        a.malformed = attrs & ATTR_MALFORMED != 0;
+        a.seeded = attrs & ATTR_SEEDED != 0;
+        a.dir = match attrs >> ATTR_DIR_SHIFT {
+            0 => None,
+            1 => Some(PtrDir::In),
+            2 => Some(PtrDir::Out),
+            _ => Some(PtrDir::InOut),
+        };
+        if attrs & ATTR_ALIGN != 0 {
+            a.align = Some(self.uint()? as usize);
+        }
+        a.val = self.val()?;
+        Ok(a)
+    }
+
+    fn val(&mut self) -> Result<Value, WireError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(WireError::Depth {
+                offset: self.offset,
+            });
+        }
+        self.depth += 1;
+        let val = self.nested_val();
+        self.depth -= 1;
+        val
+    }
+
+    fn nested_val(&mut self) -> Result<Value, WireError> {
+        let offset = self.offset;
+        let val = match self.byte()? {
+            TAG_NONE => Value::None,
+            TAG_ABSENT => Value::Absent,
+            TAG_SIGNED => Value::Num(NumValue::Signed(self.int()?)),
+            TAG_UNSIGNED => Value::Num(NumValue::Unsigned(self.uint()?)),
+            TAG_STR => {
+                let len = self.uint()? as usize;
+                let start = self.offset;
+                let end = start.checked_add(len).ok_or(WireError::Eof)?;
+                let bytes = self.buf.get(start..end).ok_or(WireError::Eof)?;
+                self.offset = end;
+                let s = std::str::from_utf8(bytes).map_err(|_| WireError::Utf8 { offset })?;
+                Value::Str(s.into())
+            }
+            TAG_GROUP => {
+                let len = self.uint()?;
+                // every value takes at least one byte, don't trust len for capacity
+                let mut vals = Vec::with_capacity((len as usize).min(self.buf.len() - self.offset));
+                for _ in 0..len {
+                    vals.push(self.val()?);
+                }
+                Value::Group(vals)
+            }
+            TAG_OPT => Value::Opt {
+                choice: self.uint()? as usize,
+                val: Box::new(self.val()?),
+            },
+            TAG_REF => {
+                let cid = self.uint()? as usize;
+                let pos = match self.uint()? {
+                    0 => ArgPos::Ret,
+                    i => ArgPos::Arg(i as usize - 1),
+                };
+                Value::Ref((cid, pos))
+            }
+            tag => return Err(WireError::Tag { offset, tag }),
+        };
+        Ok(val)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze::static_analyze;
+    use crate::gen::{gen, Config};
+    use crate::target::Target;
+
+    #[test]
+    fn wire_round_trip() {
+        let t = Target::from(
+            fots::parse_items(
+                r#"
+type fd = res<i32>
+union arg { fd fd, val u32, name *cstr }
+struct stat { dev u64, ino u64, mode i32, size i64 }
+group W {
+    fn open(name *filename, mode i32) fd
+    fn ctl(f fd, a *arg)
+    fn fstat(f fd, s *Out stat)
+    fn write(f fd, buf *In [i8], count len<usize, buf>)
+    fn close(f fd)
+}
+"#,
+            )
+            .unwrap(),
+        );
+        let rs = static_analyze(&t);
+        for seed in 0..64 {
+            let conf = Config {
+                seed: Some(seed),
+                malformed_prob: 0.1,
+                ..Default::default()
+            };
+            let p = gen(&t, &rs, &conf).unwrap();
+            let wire = p.encode_wire();
+            let decoded = Prog::decode_wire(&wire).unwrap();
+            assert_eq!(decoded, p);
+            assert_eq!(decoded.seed, p.seed);
+            assert!(wire.len() < p.to_json().len());
+
+            assert_eq!(
+                Prog::decode_wire(&wire[..wire.len() - 1]),
+                Err(WireError::Eof)
+            );
+            let mut trailing = wire.clone();
+            trailing.push(0);
+            assert!(matches!(
+                Prog::decode_wire(&trailing),
+                Err(WireError::Trailing { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn wire_values() {
+        let mut p = Prog::new(3);
+        let c = p.add_call(Call::new(7));
+        let mut a = Arg::new(u64::MAX);
+        a.val = Value::Group(vec![
+            Value::Num(NumValue::Signed(i64::MIN)),
+            Value::Num(NumValue::Signed(-1)),
+            Value::Num(NumValue::Unsigned(u64::MAX)),
+            Value::Str("héllo\0".into()),
+            Value::Opt {
+                choice: 2,
+                val: Box::new(Value::None),
+            },
+            Value::Absent,
+        ]);
+        a.malformed = true;
+        a.seeded = true;
+        a.align = Some(4096);
+        a.dir = Some(PtrDir::Out);
+        c.add_arg(a);
+        c.ret = Some(Arg::new(1));
+        let c = p.add_call(Call::new(0));
+        c.add_arg(Arg::new(1)).val = Value::Ref((0, ArgPos::Ret));
+        c.add_arg(Arg::new(2)).val = Value::Ref((0, ArgPos::Arg(0)));
+
+        let decoded = Prog::decode_wire(&p.encode_wire()).unwrap();
+        assert_eq!(decoded.calls, p.calls);
+        assert_eq!(decoded.gid, 3);
+
+        let mut bad = Prog::new(0);
+        bad.add_call(Call::new(0)).add_arg(Arg::new(0)).val = Value::Str("a".into());
+        let mut wire = bad.encode_wire();
+        let len = wire.len();
+        // last bytes are tag of str, len of str, str and flag of ret
+        wire[len - 2] = 0xff;
+        assert!(matches!(
+            Prog::decode_wire(&wire),
+            Err(WireError::Utf8 { .. })
+        ));
+        wire[len - 4] = 0xff;
+        assert!(matches!(
+            Prog::decode_wire(&wire),
+            Err(WireError::Tag { tag: 0xff, .. })
+        ));
+        assert!(matches!(
+            Prog::decode_wire(&[0xff; 11]),
+            Err(WireError::Overflow { .. })
+        ));
+    }
+
+    #[test]
+    fn wire_nested_too_deep() {
+        let nested = |depth: usize| {
+            let mut val = Value::None;
+            for _ in 0..depth {
+                val = Value::Opt {
+                    choice: 0,
+                    val: Box::new(val),
+                };
+            }
+            let mut p = Prog::new(0);
+            p.add_call(Call::new(0)).add_arg(Arg::new(0)).val = val;
+            p.encode_wire()
+        };
+        assert!(Prog::decode_wire(&nested(MAX_DEPTH - 1)).is_ok());
+        assert!(matches!(
+            Prog::decode_wire(&nested(MAX_DEPTH)),
+            Err(WireError::Depth { .. })
+        ));
+
+        // gid, no seed, one call of fn 0 with one arg of type 0 and no attrs,
+        // then opt tags nested far beyond stack capacity
+        let mut wire = vec![0, 0, 1, 0, 1, 0, 0];
+        for _ in 0..(4 << 20) / 2 {
+            wire.extend_from_slice(&[TAG_OPT, 0]);
+        }
+        assert!(matches!(
+            Prog::decode_wire(&wire),
+            Err(WireError::Depth { .. })
+        ));
+    }
+}
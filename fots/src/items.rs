@@ -11,8 +11,9 @@ use pest::iterators::{Pair, Pairs};
 use crate::errors;
 use crate::grammar::Rule;
 use crate::types::{
-    Attr, Field, Flag, FnId, FnInfo, Group, GroupId, Items, NumInfo, NumLimit, Param, PtrDir,
-    StrType, Type, TypeId, TypeInfo, DEFAULT_GID,
+    Attr, Field, Flag, FnId, FnInfo, Group, GroupId, Items, LenKind, NumInfo, NumLimit, Param,
+    PtrDir, StrType, Type, TypeId, TypeInfo, DEFAULT_GID, FLAG_ATTR_REQUIRED, STRUCT_ATTR_PACKED,
+    STRUCT_ATTR_TAG,
 };
 use crate::{num, parse_grammar};
 
@@ -176,10 +177,28 @@ impl Parser {
 
     fn parse_struct(&mut self, p: Pair<Rule>) -> TypeId {
         let mut p: Pairs<Rule> = p.into_inner();
-        let ident_p: Pair<Rule> = p.next().unwrap();
+        let mut ident_p: Pair<Rule> = p.next().unwrap();
+        let mut attrs = Vec::new();
+        if ident_p.as_rule() == Rule::AttrsDef {
+            attrs = self.parse_attrs(ident_p);
+            ident_p = p.next().unwrap();
+        }
+        let packed = attrs.iter().any(|a| a.ident == STRUCT_ATTR_PACKED);
         let fields_p: Pair<Rule> = p.next().unwrap();
         assert_eq!(fields_p.as_rule(), Rule::Fields);
-        let info = TypeInfo::struct_info(ident_p.as_str(), self.parse_fields(fields_p));
+        let fields = self.parse_fields(fields_p);
+        // tag naming unknown fields is ignored
+        let tag = attrs
+            .iter()
+            .find(|a| a.ident == STRUCT_ATTR_TAG)
+            .and_then(|a| match a.vals.as_deref() {
+                Some([flag, body]) => {
+                    let index = |ident: &String| fields.iter().position(|f| f.ident == *ident);
+                    Some((index(flag)?, index(body)?))
+                }
+                _ => None,
+            });
+        let info = TypeInfo::struct_info(ident_p.as_str(), fields, packed, tag);
         self.type_table.add(info)
     }
 
@@ -190,10 +209,20 @@ impl Parser {
     fn parse_field(&mut self, p: Pair<Rule>) -> Field {
         let mut field_p = p.into_inner();
         let ident_p = field_p.next().unwrap();
-        let tid = self.parse_type_exp(field_p.next().unwrap());
+        let mut type_p = field_p.next().unwrap();
+        let optional = type_p.as_rule() == Rule::Optional;
+        if optional {
+            type_p = field_p.next().unwrap();
+        }
+        let tid = self.parse_type_exp(type_p);
+        let bits = field_p
+            .next()
+            .map(|p| self.parse_num::<u8>(p.into_inner().next().unwrap()));
         Field {
             ident: String::from(ident_p.as_str()),
             tid,
+            optional,
+            bits,
         }
     }
 
@@ -272,7 +301,11 @@ impl Parser {
         let mut p = p.into_inner();
         let tid = self.parse_num_type(p.next().unwrap());
         let path = p.next().unwrap().as_str();
-        self.type_table.add(TypeInfo::len_info(tid, path))
+        let kind = p
+            .next()
+            .map(|k| LenKind::from_rule(k.into_inner().next().unwrap().as_rule()))
+            .unwrap_or_default();
+        self.type_table.add(TypeInfo::len_info(tid, path, kind))
     }
 
     fn parse_name_type(&mut self, p: Pair<Rule>) -> TypeId {
@@ -411,7 +444,11 @@ impl Parser {
 
         if let Some(p) = p.next() {
             match p.as_rule() {
-                Rule::NumLiteral => limit.0 = self.parse_num(p),
+                Rule::NumLiteral => {
+                    // fixed length array
+                    let len = self.parse_num(p);
+                    limit = (len, len);
+                }
                 Rule::Range => {
                     let range = self.parse_range(p);
                     limit.0 = range.start;
@@ -445,8 +482,24 @@ impl Parser {
 
     fn parse_flag(&mut self, p: Pair<Rule>) -> TypeId {
         let mut p = p.into_inner();
-        let ident = p.next().unwrap().as_str();
-        let t_info = TypeInfo::flag_info(ident, self.parse_flag_members(p.next().unwrap()));
+        let mut ident_p = p.next().unwrap();
+        let mut required = Vec::new();
+        if ident_p.as_rule() == Rule::AttrsDef {
+            required = self
+                .parse_attrs(ident_p)
+                .into_iter()
+                .filter(|a| a.ident == FLAG_ATTR_REQUIRED)
+                .flat_map(|a| a.vals.unwrap_or_default())
+                .collect::<Vec<_>>();
+            ident_p = p.next().unwrap();
+        }
+        let flags = self.parse_flag_members(p.next().unwrap());
+        let required = flags
+            .iter()
+            .filter(|f| required.contains(&f.ident))
+            .cloned()
+            .collect();
+        let t_info = TypeInfo::flag_info(ident_p.as_str(), flags, required);
         self.type_table.add(t_info)
     }
 
@@ -125,7 +125,8 @@ pub enum TypeInfo {
         depth: usize,
         tid: TypeId,
     },
-    // Slice type. If range specified,use (l,h) as range. if len specified, l is len,h is -1.
+    // Slice type. If range specified,use (l,h) as range. if len specified, both l and h are len.
+    // (l,-1) means l is lower bound without upper bound.
     Slice {
         tid: TypeId,
         l: isize,
@@ -138,6 +139,12 @@ pub enum TypeInfo {
     Struct {
         ident: String,
         fields: Vec<Field>,
+        /// Fields are laid out without padding
+        packed: bool,
+        /// Indices of flag field and union field whose variant is chosen by
+        /// value of flag, see `STRUCT_ATTR_TAG`
+        #[serde(default)]
+        tag: Option<(usize, usize)>,
     },
     Union {
         ident: String,
@@ -146,6 +153,9 @@ pub enum TypeInfo {
     Flag {
         ident: String,
         flags: Vec<Flag>,
+        /// Members of flags of which every value has exactly one
+        #[serde(default)]
+        required: Vec<Flag>,
     },
     Alias {
         ident: String,
@@ -158,9 +168,45 @@ pub enum TypeInfo {
         tid: TypeId,
         path: String,
         is_param: bool,
+        #[serde(default)]
+        kind: LenKind,
     },
 }
 
+/// What value of len type measures
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LenKind {
+    /// Element count of slice or byte count of str, `len<u32, buf>`
+    #[default]
+    Count,
+    /// Encoded byte size of value, `len<u32, buf, bytes>`
+    Bytes,
+    /// Encoded byte size of value plus width of len itself, for records whose
+    /// length covers their own length field, `len<u32, buf, with_self>`
+    WithSelf,
+}
+
+impl Display for LenKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            LenKind::Count => write!(f, "count"),
+            LenKind::Bytes => write!(f, "bytes"),
+            LenKind::WithSelf => write!(f, "with_self"),
+        }
+    }
+}
+
+impl LenKind {
+    pub fn from_rule(kind: Rule) -> Self {
+        match kind {
+            Rule::LenCount => Self::Count,
+            Rule::LenBytes => Self::Bytes,
+            Rule::LenWithSelf => Self::WithSelf,
+            _ => unreachable!(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialOrd, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum StrType {
     Str,
@@ -191,7 +237,8 @@ impl Display for TypeInfo {
             ),
             TypeInfo::Slice { tid, l, h } => match (*l, *h) {
                 (-1, -1) => write!(f, "[id({})]", tid),
-                (l, -1) => write!(f, "[id({});{}]", tid, l),
+                (l, -1) => write!(f, "[id({});({}:)]", tid, l),
+                (l, h) if l == h => write!(f, "[id({});{}]", tid, l),
                 (l, h) => write!(f, "[id({});({}:{})]", tid, l, h),
             },
             TypeInfo::Str { vals, str_type } => {
@@ -202,12 +249,30 @@ impl Display for TypeInfo {
                     write!(f, "{}", s)
                 }
             }
-            TypeInfo::Struct { ident, fields } => {
+            TypeInfo::Struct {
+                ident,
+                fields,
+                packed,
+                tag,
+            } => {
                 let fields_str = fields
                     .iter()
                     .map(|f| f.to_string())
                     .collect::<Vec<_>>()
                     .join(",");
+                let mut attrs = Vec::new();
+                if *packed {
+                    attrs.push(STRUCT_ATTR_PACKED.to_string());
+                }
+                if let Some((flag, body)) = tag {
+                    attrs.push(format!(
+                        "{}({},{})",
+                        STRUCT_ATTR_TAG, fields[*flag].ident, fields[*body].ident
+                    ));
+                }
+                if !attrs.is_empty() {
+                    write!(f, "#[{}] ", attrs.join(","))?;
+                }
                 write!(f, "struct {}{{{}}}", ident, fields_str)
             }
             TypeInfo::Union { ident, fields } => {
@@ -218,7 +283,19 @@ impl Display for TypeInfo {
                     .join(",");
                 write!(f, "union {}{{{}}}", ident, fields_str)
             }
-            TypeInfo::Flag { ident, flags } => {
+            TypeInfo::Flag {
+                ident,
+                flags,
+                required,
+            } => {
+                if !required.is_empty() {
+                    let required = required
+                        .iter()
+                        .map(|f| f.ident.as_str())
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    write!(f, "#[{}({})] ", FLAG_ATTR_REQUIRED, required)?;
+                }
                 let flags_str = flags
                     .iter()
                     .map(|f| f.to_string())
@@ -228,7 +305,9 @@ impl Display for TypeInfo {
             }
             TypeInfo::Alias { ident, tid } => write!(f, "Alias {}=>id({})", ident, tid),
             TypeInfo::Res { tid } => write!(f, "res<id({})>", tid),
-            TypeInfo::Len { tid, path, .. } => write!(f, "len<id({}),{}>", tid, path),
+            TypeInfo::Len {
+                tid, path, kind, ..
+            } => write!(f, "len<id({}),{},{}>", tid, path, kind),
         }
     }
 }
@@ -294,12 +373,13 @@ impl TypeInfo {
         TypeInfo::Ptr { tid, dir, depth }
     }
 
-    pub fn len_info(tid: TypeId, path: &str) -> Self {
+    pub fn len_info(tid: TypeId, path: &str, kind: LenKind) -> Self {
         // TODO path parse
         TypeInfo::Len {
             path: String::from(path),
             is_param: true,
             tid,
+            kind,
         }
     }
 
@@ -307,10 +387,17 @@ impl TypeInfo {
         TypeInfo::Res { tid }
     }
 
-    pub fn struct_info(ident: &str, fields: Vec<Field>) -> Self {
+    pub fn struct_info(
+        ident: &str,
+        fields: Vec<Field>,
+        packed: bool,
+        tag: Option<(usize, usize)>,
+    ) -> Self {
         TypeInfo::Struct {
             ident: String::from(ident),
             fields,
+            packed,
+            tag,
         }
     }
 
@@ -328,10 +415,11 @@ impl TypeInfo {
         }
     }
 
-    pub fn flag_info(ident: &str, flags: Vec<Flag>) -> Self {
+    pub fn flag_info(ident: &str, flags: Vec<Flag>, required: Vec<Flag>) -> Self {
         TypeInfo::Flag {
             ident: ident.into(),
             flags,
+            required,
         }
     }
 }
@@ -657,6 +745,16 @@ impl<T: Display> Display for NumLimit<T> {
 }
 
 impl NumInfo {
+    /// Width of num in bits
+    pub fn bit_width(&self) -> usize {
+        match self {
+            NumInfo::I8(_) | NumInfo::U8(_) => 8,
+            NumInfo::I16(_) | NumInfo::U16(_) => 16,
+            NumInfo::I32(_) | NumInfo::U32(_) => 32,
+            NumInfo::I64(_) | NumInfo::U64(_) | NumInfo::Isize(_) | NumInfo::Usize(_) => 64,
+        }
+    }
+
     pub fn from_rule(ru: Rule) -> Self {
         match ru {
             Rule::I8 => Self::I8(NumLimit::None),
@@ -756,7 +854,7 @@ impl NumInfo {
 }
 
 /// Direction of pointer
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum PtrDir {
     In,
     Out,
@@ -785,11 +883,23 @@ impl PtrDir {
 pub struct Field {
     pub ident: String,
     pub tid: TypeId,
+    /// Field may be absent from struct, marked by `?` after ident
+    #[serde(default)]
+    pub optional: bool,
+    /// Bit width of num field of struct, marked by `:width` after type.
+    /// Consecutive bitfields share storage of their num type
+    #[serde(default)]
+    pub bits: Option<u8>,
 }
 
 impl Display for Field {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        write!(f, "{}:id({})", self.ident, self.tid)
+        let mark = if self.optional { "?" } else { "" };
+        write!(f, "{}{}:id({})", self.ident, mark, self.tid)?;
+        if let Some(bits) = self.bits {
+            write!(f, ":{}", bits)?;
+        }
+        Ok(())
     }
 }
 
@@ -798,6 +908,8 @@ impl Field {
         Field {
             ident: String::from(ident),
             tid,
+            optional: false,
+            bits: None,
         }
     }
 }
@@ -824,7 +936,20 @@ impl Flag {
     }
 }
 
-/// Attribute of group or function
+/// Attribute of struct that lays out fields without padding
+pub const STRUCT_ATTR_PACKED: &str = "packed";
+/// Attribute of struct whose union field is tagged by a flag field, like family
+/// of sockaddr. `#[tag(family, addr)]` chooses the variant of addr named as the
+/// member of family that is generated
+pub const STRUCT_ATTR_TAG: &str = "tag";
+/// Attribute of function that closes resources it consumes, `#[close]` closes
+/// resources of all params, `#[close(f)]` only closes resources of param f
+pub const FN_ATTR_CLOSE: &str = "close";
+/// Attribute of flag whose args are members that are mutually exclusive and
+/// mandatory, every value of flag has exactly one of them, `#[required(A, B)]`
+pub const FLAG_ATTR_REQUIRED: &str = "required";
+
+/// Attribute of group, function or struct
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Attr {
     pub ident: String,
@@ -351,7 +351,7 @@ impl Fuzzer {
         } else if self.corpus.is_empty().await || *gen_cnt % 100 != 0 {
             *gen_cnt += 1;
             let rt = self.rt.lock().await;
-            gen(&self.target, &rt, &self.conf)
+            gen(&self.target, &rt, &self.conf).expect("Target has no fn to generate")
         } else {
             let rt = {
                 let rt = self.rt.lock().await;
@@ -26,7 +26,10 @@ fn main() {
 
     let target = load_target(&settings.items);
     let rt = analyze::static_analyze(&target);
-    let p = gen::gen(&target, &rt, &Default::default());
+    let p = gen::gen(&target, &rt, &Default::default()).unwrap_or_else(|e| {
+        eprintln!("Fail to generate: {}", e);
+        exit(exitcode::SOFTWARE)
+    });
 
     if settings.translate {
         let p = c::to_prog(&p, &target);
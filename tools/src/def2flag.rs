@@ -24,6 +24,7 @@ fn combine(flags: Vec<Flag>, flags_vals: Vec<Vec<i64>>) -> Vec<TypeInfo> {
         result.push(TypeInfo::Flag {
             ident: flag.ident,
             flags,
+            required: Vec::new(),
         });
     }
     result